@@ -0,0 +1,42 @@
+//! Compares the owned and borrowed KRPC decode paths.
+//!
+//! Run with `cargo bench --bench krpc`; each path reports decoded packets per
+//! second over a mix of typical DHT messages.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rdht::protocl::KRPC;
+
+const PACKETS: [&str; 6] = [
+    "d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
+    "d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re",
+    "d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q9:find_node1:t2:aa1:y1:qe",
+    "d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe",
+    "d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:axje.u6:idhtnmee1:t2:aa1:y1:re",
+    "d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe",
+];
+
+const ROUNDS: usize = 200_000;
+
+fn bench(name: &str, decode: impl Fn(&str) -> bool) -> f64 {
+    // Warm up caches and the allocator before measuring.
+    for packet in PACKETS.iter().cycle().take(ROUNDS / 10) {
+        assert!(decode(black_box(packet)));
+    }
+    let start = Instant::now();
+    for packet in PACKETS.iter().cycle().take(ROUNDS) {
+        black_box(decode(black_box(packet)));
+    }
+    let rate = ROUNDS as f64 / start.elapsed().max(Duration::from_nanos(1)).as_secs_f64();
+    println!("{:<20} {:>12.0} packets/sec", name, rate);
+    rate
+}
+
+fn main() {
+    let owned = bench("KRPC::decode", |p| KRPC::decode(p).is_ok());
+    let borrowed = bench("KRPC::decode_bytes", |p| {
+        KRPC::decode_bytes(p.as_bytes()).is_ok()
+    });
+    println!("speedup: {:.2}x", borrowed / owned);
+}
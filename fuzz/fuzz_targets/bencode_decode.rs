@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rdht::util::bencode::{self, Decoder, Limits};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = bencode::decode_ref(data);
+    let _ = bencode::decode_strict(data);
+    if let Ok(mut decoder) = Decoder::with_limits(data, Limits::datagram()) {
+        let _ = decoder.decode();
+    }
+    if let Ok(s) = std::str::from_utf8(data) {
+        let _ = bencode::decode(&mut s.chars().peekable());
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rdht::protocl::KRPC;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        let _ = KRPC::decode(s);
+    }
+    // Anything we accept must survive being sent back out.
+    if let Ok(msg) = KRPC::decode_bytes(data) {
+        let mut buf = Vec::new();
+        msg.encode_to(&mut buf).expect("decoded message must encode");
+        assert_eq!(KRPC::decode_bytes(&buf), Ok(msg));
+    }
+});
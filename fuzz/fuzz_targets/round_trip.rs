@@ -0,0 +1,26 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rdht::util::bencode;
+
+fuzz_target!(|data: &[u8]| {
+    // Canonical input re-encodes to exactly the same bytes.
+    if let Ok(v) = bencode::decode_strict(data) {
+        let mut buf = Vec::new();
+        v.encode_to(&mut buf).expect("decoded value must encode");
+        assert_eq!(buf, data);
+    }
+
+    // Lenient input may normalise, but must then be stable.
+    if let Ok(v) = bencode::decode_ref(data) {
+        let mut buf = Vec::new();
+        v.encode_to(&mut buf).expect("decoded value must encode");
+        assert_eq!(bencode::decode_ref(&buf), Ok(v));
+    }
+
+    if let Ok(v) = bencode::decode_ref(data).and_then(|v| v.to_value()) {
+        let mut buf = Vec::new();
+        v.encode_to(&mut buf).expect("owned value must encode");
+        assert_eq!(bencode::decode_ref(&buf).and_then(|v| v.to_value()), Ok(v));
+    }
+});
@@ -1,3 +1,616 @@
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rdht::errors::{Error, Result};
+use rdht::lsd::{self, Lsd};
+use rdht::magnet::Magnet;
+use rdht::pcap;
+use rdht::protocl::{DHTQuery, DHTResponse, KRPC};
+use rdht::server::config::ServerConfig;
+use rdht::server::rate_limit::RateLimit;
+use rdht::server::route_table::{Key, Node, NodeLimits};
+use rdht::server::Server;
+use rdht::tracker::server::{TrackerConfig, TrackerServer};
+use rdht::util::bencode::{self, Encoder};
+use rdht::util::bloom::BloomFilter;
+use rdht::util::{compact, hex, json};
+
+const USAGE: &str = "usage: rdht [options] <command> [args]
+
+commands:
+  node                          run a DHT node with a tracker beside it,
+                                also finding peers on the local network
+  ping <addr>                   ping a node
+  find-node <id>                find the nodes closest to a hex id
+  get-peers <infohash|magnet>   find peers for a torrent
+  announce <infohash> <port>    announce that we have a torrent on a port
+  put <value>                   store a string as an immutable item
+  get <target>                  fetch an immutable item by its hex target
+  dump-table                    print the routing table
+  dissect <capture>             print the KRPC messages in a pcap or pcapng
+                                file
+
+options:
+  --config <file>               read server settings from a TOML file, which
+                                the options below override
+  --bind <addr>                 DHT address, 0.0.0.0:6881 or the config's
+                                for node and any free port otherwise
+  --bootstrap <addr,...>        nodes to join through, well-known routers by
+                                default
+  --state <file>                load the routing table from and save it to
+                                a file
+  --tracker <addr>              tracker address for node, 0.0.0.0:6969 by
+                                default
+  --admin <addr>                serve Prometheus metrics for node over HTTP
+                                at /metrics and the routing table as JSON
+                                at /table, off by default
+  --log <level>                 log to stderr at error, warn, info, debug or
+                                trace level
+  --packet-trace <file>         append every KRPC message sent and received
+                                to a file as JSON lines
+  --announce <infohash:port,...>
+                                torrents a node keeps announcing every 15
+                                to 30 minutes, and on the local network
+                                every 5
+  --replay                      with dissect, also answer the captured
+                                queries with a local node and print its
+                                responses
+  --json                        print JSON instead of text";
+
+const NODE_ADDR: &str = "0.0.0.0:6881";
+const CLIENT_ADDR: &str = "0.0.0.0:0";
+const TRACKER_ADDR: &str = "0.0.0.0:6969";
+/// How often a running node saves its routing table.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+struct Options {
+    config: Option<PathBuf>,
+    bind: Option<String>,
+    bootstrap: Option<Vec<String>>,
+    state: Option<PathBuf>,
+    tracker: String,
+    admin: Option<String>,
+    log: Option<tracing::Level>,
+    packet_trace: Option<PathBuf>,
+    announce: Vec<String>,
+    replay: bool,
+    json: bool,
+    command: String,
+    args: Vec<String>,
+}
+
 fn main() {
-    println!("Hello, world!");
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("rdht: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Some(level) = options.log {
+        tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+    if let Err(e) = run(&options) {
+        eprintln!("rdht: {}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> std::result::Result<Options, String> {
+    let mut options = Options {
+        config: None,
+        bind: None,
+        bootstrap: None,
+        state: None,
+        tracker: TRACKER_ADDR.into(),
+        admin: None,
+        log: None,
+        packet_trace: None,
+        announce: vec![],
+        replay: false,
+        json: false,
+        command: String::new(),
+        args: vec![],
+    };
+    let mut positional = vec![];
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--config" => options.config = Some(value()?.into()),
+            "--bind" => options.bind = Some(value()?),
+            "--bootstrap" => {
+                options.bootstrap = Some(value()?.split(',').map(str::to_string).collect());
+            }
+            "--state" => options.state = Some(value()?.into()),
+            "--tracker" => options.tracker = value()?,
+            "--admin" => options.admin = Some(value()?),
+            "--log" => {
+                let level = value()?;
+                let level = level
+                    .parse()
+                    .map_err(|_| format!("unknown log level {}", level))?;
+                options.log = Some(level);
+            }
+            "--packet-trace" => options.packet_trace = Some(value()?.into()),
+            "--announce" => options.announce = value()?.split(',').map(str::to_string).collect(),
+            "--replay" => options.replay = true,
+            "--json" => options.json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    options.command = positional.next().ok_or("missing command")?;
+    options.args = positional.collect();
+    let arity = match options.command.as_str() {
+        "node" | "dump-table" => 0,
+        "ping" | "find-node" | "get-peers" | "put" | "get" | "dissect" => 1,
+        "announce" => 2,
+        command => return Err(format!("unknown command {}", command)),
+    };
+    if options.args.len() != arity {
+        return Err(format!("{} takes {} argument(s)", options.command, arity));
+    }
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<()> {
+    let args = &options.args;
+    match options.command.as_str() {
+        "node" => return node(options),
+        "dump-table" => return dump_table(options),
+        "dissect" => return dissect(options),
+        _ => {}
+    }
+    let server = start(options, false)?;
+    bootstrap(&server)?;
+    let output = match options.command.as_str() {
+        "ping" => {
+            let addr = resolve(&args[0])?;
+            let id = server.ping(addr)?;
+            node_output(&Node::from_parts(id, addr), options.json)
+        }
+        "find-node" => {
+            let nodes = server.find_node(&parse_key(&args[0])?)?;
+            nodes_output(&nodes, options.json)
+        }
+        "get-peers" => {
+            let info_hash = if args[0].starts_with("magnet:") {
+                Magnet::from_str(&args[0])?.info_hash
+            } else {
+                parse_key(&args[0])?
+            };
+            let peers = server.get_peers(&info_hash)?;
+            let peers: Vec<_> = peers.iter().map(SocketAddr::to_string).collect();
+            if options.json {
+                json_list(peers.iter().map(|p| json::string(p)))
+            } else {
+                peers.join("\n")
+            }
+        }
+        "announce" => {
+            let port = args[1]
+                .parse()
+                .map_err(|_| Error::InvalidNetAddr(format!("invalid port {}", args[1])))?;
+            let accepted = server.announce_peer(&parse_key(&args[0])?, port)?;
+            if options.json {
+                format!("{{\"nodes\":{}}}", accepted)
+            } else {
+                format!("announced to {} nodes", accepted)
+            }
+        }
+        "put" => {
+            let mut e = Encoder::new(Vec::new());
+            e.write_str(&args[0])?;
+            let target = hex::encode(server.put(&e.into_inner())?.as_bytes());
+            if options.json {
+                format!("{{\"target\":{}}}", json::string(&target))
+            } else {
+                target
+            }
+        }
+        "get" => {
+            let value = server
+                .get(&parse_key(&args[0])?)?
+                .ok_or_else(|| Error::Dht("item not found".into()))?;
+            // Strings are shown as themselves, anything else as bencode.
+            let decoded = bencode::decode_ref(&value)?;
+            let text = match decoded.as_str() {
+                Some(s) => s.to_string(),
+                None => String::from_utf8_lossy(&value).into_owned(),
+            };
+            if options.json {
+                format!("{{\"value\":{}}}", json::string(&text))
+            } else {
+                text
+            }
+        }
+        _ => unreachable!("checked by parse_args"),
+    };
+    println!("{}", output);
+    save(&server)
+}
+
+/// Run a DHT node with an HTTP and UDP tracker beside it, both handing out
+/// the peers announced to the node, and local service discovery.
+fn node(options: &Options) -> Result<()> {
+    let server = start(options, true)?;
+    if let Err(e) = bootstrap(&server) {
+        // We can still answer whoever finds us.
+        eprintln!("rdht: {}", e);
+    }
+    let tracker = Arc::new(TrackerServer::new(
+        server.peer_store(),
+        TrackerConfig::default(),
+    ));
+    let listener = TcpListener::bind(&options.tracker)?;
+    let http = Arc::clone(&tracker);
+    thread::spawn(move || http.serve_http(listener));
+    let socket = UdpSocket::bind(&options.tracker)?;
+    thread::spawn(move || tracker.serve_udp(socket));
+    for torrent in &options.announce {
+        let (info_hash, port) = torrent
+            .rsplit_once(':')
+            .ok_or_else(|| Error::InvalidNetAddr(format!("{} is not infohash:port", torrent)))?;
+        let port = port
+            .parse()
+            .map_err(|_| Error::InvalidNetAddr(format!("invalid port {}", port)))?;
+        let info_hash = parse_key(info_hash)?;
+        server.add_torrent(info_hash, port);
+        if let Some(lsd) = server.lsd() {
+            lsd.add_torrent(info_hash, port);
+        }
+    }
+    let announcer = Arc::clone(&server);
+    thread::spawn(move || announcer.run_announces());
+    if let Some(lsd) = server.lsd() {
+        let announcer = Arc::clone(lsd);
+        thread::spawn(move || announcer.run_announces());
+    }
+    if let Some(addr) = server.config().admin_addr() {
+        let listener = TcpListener::bind(addr)?;
+        let admin = Arc::clone(&server);
+        thread::spawn(move || admin.serve_admin(listener));
+    }
+
+    println!(
+        "dht {} id {}, tracker {}",
+        server.local_addr()?,
+        hex::encode(server.id().as_bytes()),
+        options.tracker
+    );
+    loop {
+        save(&server)?;
+        thread::sleep(SAVE_INTERVAL);
+        server.refresh();
+    }
+}
+
+/// Print the routing table, from the state file if there is one and
+/// after bootstrapping otherwise.
+fn dump_table(options: &Options) -> Result<()> {
+    let server = start(options, false)?;
+    if server.config().state_path().is_none() {
+        bootstrap(&server)?;
+    }
+    let dump = server.dump_table();
+    if options.json {
+        println!("{}", dump.to_json());
+        return Ok(());
+    }
+    println!("{} ({} nodes)", hex::encode(dump.id.as_bytes()), dump.nodes);
+    for bucket in &dump.buckets {
+        println!(
+            "\nbucket {}* (depth {}, {} nodes, changed {})",
+            bucket.prefix,
+            bucket.depth,
+            bucket.nodes.len(),
+            bucket.last_changed
+        );
+        for dump in &bucket.nodes {
+            let rtt = dump.rtt.map_or("-".to_string(), |rtt| format!("{:?}", rtt));
+            println!(
+                "  {} {} rtt {}",
+                node_output(&dump.node, false),
+                dump.status.as_str(),
+                rtt
+            );
+        }
+        for node in &bucket.replacements {
+            println!("  {} replacement", node_output(node, false));
+        }
+    }
+    Ok(())
+}
+
+/// Print every KRPC message in a capture, and with `--replay` what a
+/// local node answers to each query. Datagrams that are not KRPC, such as
+/// uTP, are counted but not shown.
+fn dissect(options: &Options) -> Result<()> {
+    let datagrams = pcap::read_file(&options.args[0])?;
+    let server = if options.replay {
+        Some(replay_server()?)
+    } else {
+        None
+    };
+    let mut skipped = 0;
+    for datagram in &datagrams {
+        let Ok(message) = KRPC::decode_bytes(&datagram.payload) else {
+            skipped += 1;
+            continue;
+        };
+        println!(
+            "{}.{:06} {} -> {}",
+            datagram.time.as_secs(),
+            datagram.time.subsec_micros(),
+            datagram.src,
+            datagram.dst
+        );
+        println!("{}", dissect_message(&message));
+        let Some(server) = server
+            .as_ref()
+            .filter(|_| matches!(message, KRPC::Query(..)))
+        else {
+            continue;
+        };
+        match server.handle(&datagram.payload, datagram.src) {
+            Some(reply) => println!(
+                "  replayed:\n{}",
+                dissect_message(&KRPC::decode_bytes(&reply)?)
+            ),
+            None => println!("  replayed: no answer"),
+        }
+    }
+    println!(
+        "{} KRPC messages, {} other datagrams",
+        datagrams.len() - skipped,
+        skipped
+    );
+    Ok(())
+}
+
+/// A node answering captured queries as they come, whatever their source:
+/// captures are full of private addresses and bursts that a live node
+/// would refuse. It only answers, so it does not run.
+fn replay_server() -> Result<Server> {
+    let config = ServerConfig::default()
+        .bind(CLIENT_ADDR)
+        .allow_private(true)
+        .node_limits(NodeLimits::UNLIMITED)
+        .rate_limits(RateLimit::UNLIMITED, RateLimit::UNLIMITED)
+        .ban(u32::MAX, Duration::from_secs(1));
+    Server::with_config(config)
+}
+
+/// A KRPC message as indented lines, with compact nodes and peers
+/// expanded.
+fn dissect_message(message: &KRPC) -> String {
+    let mut lines = vec![];
+    let mut field = |name: &str, value: String| lines.push(format!("    {:<10}{}", name, value));
+    let header = match message {
+        KRPC::Query(t, query, read_only) => {
+            match query {
+                DHTQuery::Ping { id } => field("id", hex::encode(id)),
+                DHTQuery::FindNode { id, target } => {
+                    field("id", hex::encode(id));
+                    field("target", hex::encode(target));
+                }
+                DHTQuery::GetPeers {
+                    id,
+                    info_hash,
+                    scrape,
+                    noseed,
+                } => {
+                    field("id", hex::encode(id));
+                    field("info_hash", hex::encode(info_hash));
+                    field("scrape", scrape.to_string());
+                    field("noseed", noseed.to_string());
+                }
+                DHTQuery::AnnouncePeer {
+                    id,
+                    impiled_port,
+                    port,
+                    info_hash,
+                    token,
+                    seed,
+                } => {
+                    field("id", hex::encode(id));
+                    field("info_hash", hex::encode(info_hash));
+                    field("port", port.to_string());
+                    field("implied", (*impiled_port != 0).to_string());
+                    field("token", hex::encode(token));
+                    field("seed", seed.to_string());
+                }
+                DHTQuery::Get { id, target } => {
+                    field("id", hex::encode(id));
+                    field("target", hex::encode(target));
+                }
+                DHTQuery::Put { id, token, value } => {
+                    field("id", hex::encode(id));
+                    field("token", hex::encode(token));
+                    field("value", String::from_utf8_lossy(value).into_owned());
+                }
+            }
+            if *read_only {
+                field("ro", "true".into());
+            }
+            format!("  query {} t={}", query.method(), hex::encode(t))
+        }
+        KRPC::Response(t, response) => {
+            field("id", hex::encode(response.id()));
+            let (token, nodes, values, value, filters) = match response {
+                DHTResponse::ID { .. } => (None, None, None, None, None),
+                DHTResponse::FindNode { nodes, .. } => (None, Some(nodes), None, None, None),
+                DHTResponse::GetPeers {
+                    token,
+                    values,
+                    nodes,
+                    seeds,
+                    peers,
+                    ..
+                } => (
+                    Some(token),
+                    Some(nodes),
+                    Some(values),
+                    None,
+                    Some((seeds, peers)),
+                ),
+                DHTResponse::Get {
+                    token,
+                    nodes,
+                    value,
+                    ..
+                } => (Some(token), Some(nodes), None, Some(value), None),
+            };
+            if let Some(token) = token {
+                field("token", hex::encode(token));
+            }
+            if let Some(nodes) = nodes.filter(|nodes| !nodes.is_empty()) {
+                match Node::decode_compact(nodes) {
+                    Ok(nodes) => {
+                        for node in nodes {
+                            field("node", node_output(&node, false));
+                        }
+                    }
+                    Err(_) => field("nodes", format!("invalid {}", hex::encode(nodes))),
+                }
+            }
+            for value in values.into_iter().flatten() {
+                match compact::decode_peer(value) {
+                    Ok(peer) => field("peer", peer.to_string()),
+                    Err(_) => field("peer", format!("invalid {}", hex::encode(value))),
+                }
+            }
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                field("value", String::from_utf8_lossy(value).into_owned());
+            }
+            if let Some((seeds, peers)) = filters {
+                for (name, filter) in [("BFsd", seeds), ("BFpe", peers)] {
+                    if let Ok(filter) = BloomFilter::try_from(filter.as_slice()) {
+                        field(name, format!("~{:.0}", filter.estimate()));
+                    }
+                }
+            }
+            format!("  response t={}", hex::encode(t))
+        }
+        KRPC::Error(t, code, msg) => format!("  error t={} {} {}", hex::encode(t), code, msg),
+    };
+    lines.insert(0, header);
+    lines.join("\n")
+}
+
+/// Bind a server, restore its state, and answer on a background thread.
+/// Only a `node` binds the configured address, so other commands can run
+/// beside one.
+fn start(options: &Options, node: bool) -> Result<Arc<Server>> {
+    let mut config = match &options.config {
+        Some(path) => ServerConfig::from_file(path)?,
+        None => ServerConfig::default(),
+    };
+    let bind = match (&options.bind, node) {
+        (Some(bind), _) => bind.clone(),
+        (None, true) => config.bind_addr().unwrap_or(NODE_ADDR).to_string(),
+        (None, false) => CLIENT_ADDR.to_string(),
+    };
+    config = config.bind(&bind);
+    if let Some(nodes) = &options.bootstrap {
+        config = config.bootstrap(nodes.clone());
+    }
+    if let Some(path) = &options.state {
+        config = config.state(path);
+    }
+    if let Some(addr) = &options.admin {
+        config = config.admin(addr);
+    }
+    if let Some(path) = &options.packet_trace {
+        config = config.packet_trace(path);
+    }
+    let mut server = Server::with_config(config)?;
+    if node {
+        match join_lsd(server.config()) {
+            Ok(lsd) => server = server.with_lsd(lsd),
+            // Not every network has multicast; the DHT still works.
+            Err(e) => eprintln!("rdht: local service discovery: {}", e),
+        }
+    }
+    let server = Arc::new(server);
+    let runner = Arc::clone(&server);
+    thread::spawn(move || runner.run());
+    Ok(server)
+}
+
+/// Join the IPv4 LSD group, with the limits of the DHT's peer store, and
+/// listen on another thread.
+fn join_lsd(config: &ServerConfig) -> Result<Arc<Lsd>> {
+    let (max_torrents, max_peers) = config.peer_limits();
+    let lsd = Arc::new(Lsd::new(lsd::IPV4_GROUP)?.with_limits(max_torrents, max_peers));
+    let runner = Arc::clone(&lsd);
+    thread::spawn(move || runner.run());
+    Ok(lsd)
+}
+
+fn bootstrap(server: &Server) -> Result<usize> {
+    // Routers that do not resolve are skipped like ones that do not answer.
+    let nodes: Vec<_> = server
+        .config()
+        .bootstrap_nodes()
+        .iter()
+        .filter_map(|addr| resolve(addr).ok())
+        .collect();
+    server.bootstrap(&nodes)
+}
+
+fn save(server: &Server) -> Result<()> {
+    match server.config().state_path() {
+        Some(path) => server.save_state(path),
+        None => Ok(()),
+    }
+}
+
+fn resolve(addr: &str) -> Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::InvalidNetAddr(addr.to_string()))
+}
+
+fn parse_key(s: &str) -> Result<Key> {
+    Key::try_from(hex::decode(s)?.as_slice()).map_err(|_| Error::InvalidKey(s.to_string()))
+}
+
+fn node_output(node: &Node, json: bool) -> String {
+    let id = hex::encode(node.id().as_bytes());
+    if json {
+        format!(
+            "{{\"id\":{},\"addr\":{}}}",
+            json::string(&id),
+            json::string(&node.addr().to_string())
+        )
+    } else {
+        format!("{} {}", id, node.addr())
+    }
+}
+
+fn nodes_output(nodes: &[Node], json: bool) -> String {
+    let lines = nodes.iter().map(|node| node_output(node, json));
+    if json {
+        json_list(lines)
+    } else {
+        lines.collect::<Vec<_>>().join("\n")
+    }
+}
+
+fn json_list(items: impl Iterator<Item = String>) -> String {
+    format!("[{}]", items.collect::<Vec<_>>().join(","))
 }
@@ -1,16 +1,64 @@
-use std::{array::TryFromSliceError, net::AddrParseError, num::ParseIntError};
+use std::{array::TryFromSliceError, fmt::Display, io, net::AddrParseError, num::ParseIntError};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
     BencodeParseError(String),
+    BencodeTooDeep(usize),
+    BencodeStringTooLong(usize),
+    BencodeTooManyElements(usize),
+    BencodeInputTooLarge(usize),
     InvalidKRPC,
     InvalidKey(String),
     InvalidValue,
     InvalidNetAddr(String),
+    InvalidMetainfo(String),
+    InvalidMagnet(String),
+    InvalidEncoding(String),
+    InvalidCapture(String),
+    PeerProtocol(String),
+    Tracker(String),
+    Dht(String),
+    Config(String),
+    Serde(String),
+    Io(String),
 }
 
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BencodeParseError(msg) => write!(f, "bencode parse error: {}", msg),
+            Error::BencodeTooDeep(max) => write!(f, "bencode nested deeper than {}", max),
+            Error::BencodeStringTooLong(max) => {
+                write!(f, "bencode string longer than {} bytes", max)
+            }
+            Error::BencodeTooManyElements(max) => {
+                write!(f, "bencode has more than {} elements", max)
+            }
+            Error::BencodeInputTooLarge(max) => {
+                write!(f, "bencode input larger than {} bytes", max)
+            }
+            Error::InvalidKRPC => f.write_str("invalid KRPC message"),
+            Error::InvalidKey(key) => write!(f, "invalid key: {}", key),
+            Error::InvalidValue => f.write_str("invalid value"),
+            Error::InvalidNetAddr(addr) => write!(f, "invalid net address: {}", addr),
+            Error::InvalidMetainfo(msg) => write!(f, "invalid metainfo: {}", msg),
+            Error::InvalidMagnet(msg) => write!(f, "invalid magnet link: {}", msg),
+            Error::InvalidEncoding(msg) => write!(f, "invalid encoding: {}", msg),
+            Error::InvalidCapture(msg) => write!(f, "invalid capture: {}", msg),
+            Error::PeerProtocol(msg) => write!(f, "peer protocol error: {}", msg),
+            Error::Tracker(msg) => write!(f, "tracker error: {}", msg),
+            Error::Dht(msg) => write!(f, "dht error: {}", msg),
+            Error::Config(msg) => write!(f, "invalid config: {}", msg),
+            Error::Serde(msg) => write!(f, "serde error: {}", msg),
+            Error::Io(msg) => write!(f, "io error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<ParseIntError> for Error {
     fn from(e: ParseIntError) -> Self {
         Self::BencodeParseError(e.to_string())
@@ -23,8 +71,28 @@ impl From<AddrParseError> for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
 impl From<TryFromSliceError> for Error {
     fn from(e: TryFromSliceError) -> Self {
         Self::InvalidKey(e.to_string())
     }
 }
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Serde(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Serde(msg.to_string())
+    }
+}
@@ -1,4 +1,10 @@
 pub mod errors;
+pub mod lsd;
+pub mod magnet;
+pub mod metainfo;
+pub mod pcap;
+pub mod peer;
 pub mod protocl;
 pub mod server;
+pub mod tracker;
 pub mod util;
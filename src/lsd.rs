@@ -0,0 +1,201 @@
+//! Local Service Discovery (BEP 14): finding peers on the same network
+//! through multicast announcements, without the DHT.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use tracing::warn;
+
+use crate::errors::{Error, Result};
+use crate::server::config::{DEFAULT_MAX_PEERS, DEFAULT_MAX_TORRENTS};
+use crate::server::peer_store::PeerStore;
+use crate::server::route_table::Key;
+use crate::util::{hex, random};
+
+pub const IPV4_GROUP: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(239, 192, 152, 143), 6771));
+pub const IPV6_GROUP: SocketAddr = SocketAddr::V6(SocketAddrV6::new(
+    Ipv6Addr::new(0xff15, 0, 0, 0, 0, 0, 0xefc0, 0x988f),
+    6771,
+    0,
+    0,
+));
+/// How often BEP 14 suggests announcing each torrent. Peers that stay
+/// silent for two intervals are forgotten.
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Info hashes per announcement, keeping packets under 1400 bytes.
+const MAX_INFO_HASHES: usize = 20;
+const MAX_PACKET_LENGTH: usize = 1400;
+
+/// A member of an LSD multicast group, announcing our torrents and
+/// collecting the peers others announce.
+pub struct Lsd {
+    socket: UdpSocket,
+    group: SocketAddr,
+    /// Sent with our announcements so we can ignore them when they loop
+    /// back.
+    cookie: String,
+    peers: PeerStore,
+    /// Our torrents and the port each is served on.
+    torrents: Mutex<HashMap<Key, u16>>,
+}
+
+impl Lsd {
+    /// Join `group` on the default interface.
+    pub fn new(group: SocketAddr) -> Result<Self> {
+        Self::on_interface(group, Ipv4Addr::UNSPECIFIED)
+    }
+
+    /// Join an IPv4 `group` on the interface with address `interface`,
+    /// such as loopback. IPv6 groups always use the default interface.
+    pub fn on_interface(group: SocketAddr, interface: Ipv4Addr) -> Result<Self> {
+        let socket = Socket::new(Domain::for_address(group), Type::DGRAM, Some(Protocol::UDP))?;
+        // Every client on the host listens on the same port.
+        socket.set_reuse_address(true)?;
+        match group {
+            SocketAddr::V4(g) => {
+                let local = SocketAddr::from((Ipv4Addr::UNSPECIFIED, g.port()));
+                socket.bind(&local.into())?;
+                socket.join_multicast_v4(g.ip(), &interface)?;
+                socket.set_multicast_if_v4(&interface)?;
+            }
+            SocketAddr::V6(g) => {
+                socket.set_only_v6(true)?;
+                let local = SocketAddr::from((Ipv6Addr::UNSPECIFIED, g.port()));
+                socket.bind(&local.into())?;
+                socket.join_multicast_v6(g.ip(), 0)?;
+            }
+        }
+        Ok(Self {
+            socket: socket.into(),
+            group,
+            cookie: hex::encode(&random::bytes::<8>()),
+            peers: PeerStore::new(ANNOUNCE_INTERVAL * 2)
+                .with_limits(DEFAULT_MAX_TORRENTS, DEFAULT_MAX_PEERS),
+            torrents: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Keep at most `max_torrents` torrents and `max_peers` peers for each,
+    /// as the DHT's peer store does, since anyone on the network can
+    /// announce.
+    pub fn with_limits(mut self, max_torrents: usize, max_peers: usize) -> Self {
+        self.peers = PeerStore::new(ANNOUNCE_INTERVAL * 2).with_limits(max_torrents, max_peers);
+        self
+    }
+
+    /// Keep announcing `info_hash` on `port` whenever
+    /// [`run_announces`](Lsd::run_announces) comes round.
+    pub fn add_torrent(&self, info_hash: Key, port: u16) {
+        self.torrents.lock().unwrap().insert(info_hash, port);
+    }
+
+    /// Stop announcing `info_hash`, returning whether it was announced.
+    pub fn remove_torrent(&self, info_hash: &Key) -> bool {
+        self.torrents.lock().unwrap().remove(info_hash).is_some()
+    }
+
+    /// Announce our torrents now and every [`ANNOUNCE_INTERVAL`] after,
+    /// forever.
+    pub fn run_announces(&self) {
+        loop {
+            let mut by_port: BTreeMap<u16, Vec<Key>> = BTreeMap::new();
+            for (info_hash, port) in self.torrents.lock().unwrap().iter() {
+                by_port.entry(*port).or_default().push(*info_hash);
+            }
+            for (port, info_hashes) in by_port {
+                if let Err(e) = self.announce(&info_hashes, port) {
+                    warn!(error = %e, port, "LSD announce failed");
+                }
+            }
+            thread::sleep(ANNOUNCE_INTERVAL);
+        }
+    }
+
+    /// Tell the group we serve `info_hashes` on `port`, in as many
+    /// packets as needed.
+    pub fn announce(&self, info_hashes: &[Key], port: u16) -> Result<()> {
+        for chunk in info_hashes.chunks(MAX_INFO_HASHES) {
+            let packet = self.encode(chunk, port);
+            self.socket.send_to(packet.as_bytes(), self.group)?;
+        }
+        Ok(())
+    }
+
+    fn encode(&self, info_hashes: &[Key], port: u16) -> String {
+        let mut packet = format!(
+            "BT-SEARCH * HTTP/1.1\r\nHost: {}\r\nPort: {}\r\n",
+            self.group, port
+        );
+        for info_hash in info_hashes {
+            packet.push_str(&format!(
+                "Infohash: {}\r\n",
+                hex::encode(info_hash.as_bytes())
+            ));
+        }
+        packet.push_str(&format!("cookie: {}\r\n\r\n\r\n", self.cookie));
+        packet
+    }
+
+    /// Listen for announcements until the socket fails.
+    pub fn run(&self) -> Result<()> {
+        self.socket.set_read_timeout(Some(ANNOUNCE_INTERVAL))?;
+        let mut buf = [0; MAX_PACKET_LENGTH];
+        let mut expired = Instant::now();
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) => {
+                    // Anyone on the network can send us garbage.
+                    let _ = self.handle(&buf[..len], from);
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.into()),
+            }
+            if expired.elapsed() >= ANNOUNCE_INTERVAL {
+                self.peers.expire();
+                expired = Instant::now();
+            }
+        }
+    }
+
+    /// Record the peer announced by one packet from `from`. Our own
+    /// announcements are ignored.
+    pub fn handle(&self, packet: &[u8], from: SocketAddr) -> Result<()> {
+        let invalid = || Error::InvalidNetAddr(format!("invalid LSD announcement from {}", from));
+        let text = std::str::from_utf8(packet).map_err(|_| invalid())?;
+        let mut lines = text.split("\r\n");
+        if lines.next() != Some("BT-SEARCH * HTTP/1.1") {
+            return Err(invalid());
+        }
+        let mut port = None;
+        let mut info_hashes = vec![];
+        for line in lines.take_while(|l| !l.is_empty()) {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "port" => port = value.parse::<u16>().ok(),
+                "infohash" => info_hashes.push(Key::try_from(hex::decode(value)?.as_slice())?),
+                "cookie" if value == self.cookie => return Ok(()),
+                _ => {}
+            }
+        }
+        let addr = SocketAddr::new(from.ip(), port.filter(|p| *p != 0).ok_or_else(invalid)?);
+        for info_hash in info_hashes {
+            self.peers.announce(info_hash, addr, false);
+        }
+        Ok(())
+    }
+
+    /// Peers announced on the local network for `info_hash`.
+    pub fn peers(&self, info_hash: &Key) -> Vec<SocketAddr> {
+        self.peers.peers(info_hash, usize::MAX)
+    }
+}
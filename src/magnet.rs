@@ -0,0 +1,168 @@
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::errors::{Error, Result};
+use crate::metainfo::Metainfo;
+use crate::server::route_table::Key;
+use crate::util::{hex, url};
+
+const BTIH_PREFIX: &str = "urn:btih:";
+
+/// A magnet link (BEP 9), with peer addresses (`x.pe`) and file selection
+/// (`so`, BEP 53).
+#[derive(Debug, PartialEq, Clone)]
+pub struct Magnet {
+    pub info_hash: Key,
+    pub display_name: Option<String>,
+    pub trackers: Vec<String>,
+    pub peers: Vec<(String, u16)>,
+    /// Indices into the torrent's file list to download.
+    pub select_only: Vec<RangeInclusive<usize>>,
+}
+
+impl Magnet {
+    pub fn new(info_hash: Key) -> Self {
+        Self {
+            info_hash,
+            display_name: None,
+            trackers: vec![],
+            peers: vec![],
+            select_only: vec![],
+        }
+    }
+}
+
+impl FromStr for Magnet {
+    type Err = Error;
+
+    /// Parse a magnet link. Only the first `urn:btih:` topic is used and
+    /// unknown parameters are ignored.
+    fn from_str(s: &str) -> Result<Self> {
+        let query = s
+            .strip_prefix("magnet:?")
+            .ok_or_else(|| invalid("missing 'magnet:?' prefix"))?;
+        let mut info_hash = None;
+        let mut magnet = Self::new(Key::default());
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| invalid(&format!("parameter '{}' has no value", param)))?;
+            let value = String::from_utf8_lossy(&url::decode(value)?).into_owned();
+            match key {
+                "xt" => {
+                    if let (None, Some(hash)) = (info_hash, value.strip_prefix(BTIH_PREFIX)) {
+                        info_hash = Some(btih(hash)?);
+                    }
+                }
+                "dn" => magnet.display_name = Some(value),
+                // Some clients number repeated keys: `tr.1=...&tr.2=...`.
+                "tr" => magnet.trackers.push(value),
+                k if k.starts_with("tr.") => magnet.trackers.push(value),
+                "x.pe" => magnet.peers.push(peer(&value)?),
+                "so" => magnet.select_only.extend(
+                    value
+                        .split(',')
+                        .map(file_range)
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                _ => {}
+            }
+        }
+        magnet.info_hash = info_hash.ok_or_else(|| invalid("missing urn:btih: topic"))?;
+        Ok(magnet)
+    }
+}
+
+impl Display for Magnet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "magnet:?xt={}{}",
+            BTIH_PREFIX,
+            hex::encode(self.info_hash.as_bytes())
+        )?;
+        if let Some(name) = &self.display_name {
+            write!(f, "&dn={}", url::encode(name.as_bytes()))?;
+        }
+        for tracker in &self.trackers {
+            write!(f, "&tr={}", url::encode(tracker.as_bytes()))?;
+        }
+        for (host, port) in &self.peers {
+            let peer = if host.contains(':') {
+                format!("[{}]:{}", host, port)
+            } else {
+                format!("{}:{}", host, port)
+            };
+            write!(f, "&x.pe={}", url::encode(peer.as_bytes()))?;
+        }
+        if !self.select_only.is_empty() {
+            let ranges: Vec<_> = self
+                .select_only
+                .iter()
+                .map(|r| match (r.start(), r.end()) {
+                    (start, end) if start == end => start.to_string(),
+                    (start, end) => format!("{}-{}", start, end),
+                })
+                .collect();
+            write!(f, "&so={}", ranges.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&Metainfo> for Magnet {
+    fn from(m: &Metainfo) -> Self {
+        Self {
+            display_name: Some(m.info.name.clone()),
+            trackers: m.trackers(),
+            ..Self::new(m.info_hash())
+        }
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidMagnet(msg.into())
+}
+
+/// A v1 info hash, as 40 hex or 32 base32 characters.
+fn btih(s: &str) -> Result<Key> {
+    let bytes = match s.len() {
+        40 => hex::decode(s)?,
+        32 => hex::decode_base32(s)?,
+        _ => return Err(invalid(&format!("info hash '{}' has the wrong length", s))),
+    };
+    let bytes: [u8; 20] = bytes.as_slice().try_into()?;
+    Ok(bytes.into())
+}
+
+/// `host:port`, with IPv6 hosts in brackets.
+fn peer(s: &str) -> Result<(String, u16)> {
+    let (host, port) = s
+        .rsplit_once(':')
+        .ok_or_else(|| invalid(&format!("peer '{}' has no port", s)))?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    let port = port
+        .parse()
+        .map_err(|_| invalid(&format!("peer '{}' has an invalid port", s)))?;
+    Ok((host.to_string(), port))
+}
+
+/// A file index `n` or inclusive range `n-m`.
+fn file_range(s: &str) -> Result<RangeInclusive<usize>> {
+    let index = |i: &str| {
+        i.parse::<usize>()
+            .map_err(|_| invalid(&format!("invalid file selection '{}'", s)))
+    };
+    let range = match s.split_once('-') {
+        Some((start, end)) => index(start)?..=index(end)?,
+        None => index(s)?..=index(s)?,
+    };
+    if range.is_empty() {
+        return Err(invalid(&format!("invalid file selection '{}'", s)));
+    }
+    Ok(range)
+}
@@ -0,0 +1,233 @@
+use std::path::Path;
+
+use sha1::{Digest, Sha1};
+
+use crate::errors::{Error, Result};
+use crate::server::route_table::Key;
+use crate::util::bencode::{Decoder, ValueRef};
+
+const PIECE_HASH_LENGTH: usize = 20;
+
+/// A parsed `.torrent` file (BEP 3), with the announce-list (BEP 12) and DHT
+/// bootstrap nodes (BEP 5) extensions.
+#[derive(Debug, PartialEq)]
+pub struct Metainfo {
+    pub announce: Option<String>,
+    pub announce_list: Vec<Vec<String>>,
+    pub nodes: Vec<(String, u16)>,
+    pub comment: Option<String>,
+    pub created_by: Option<String>,
+    pub creation_date: Option<i64>,
+    pub info: Info,
+    info_hash: Key,
+}
+
+/// The `info` dictionary shared by every peer of a torrent.
+#[derive(Debug, PartialEq)]
+pub struct Info {
+    pub name: String,
+    pub piece_length: u64,
+    pub pieces: Vec<[u8; PIECE_HASH_LENGTH]>,
+    /// Set for single-file torrents.
+    pub length: Option<u64>,
+    /// Set for multi-file torrents, relative to a directory called `name`.
+    pub files: Vec<File>,
+    pub private: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct File {
+    pub path: Vec<String>,
+    pub length: u64,
+}
+
+impl Metainfo {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
+        // Walk the top-level dict by hand to capture the exact bytes of
+        // `info`: re-encoding would normalise things like `i03e` and change
+        // the hash.
+        let mut decoder = Decoder::new(buf);
+        decoder.enter(b'd')?;
+        let mut entries = Vec::new();
+        let mut raw_info = None;
+        while decoder.next_item("unterminated dict")? {
+            let key = decoder.parse_key(None)?;
+            let start = decoder.position();
+            let value = decoder.decode()?;
+            // With a key twice, the info parsed and the info hashed could
+            // differ.
+            if entries.iter().any(|(known, _)| *known == key) {
+                return Err(invalid("duplicate key"));
+            }
+            if key == b"info" {
+                raw_info = Some(&buf[start..decoder.position()]);
+            }
+            entries.push((key, value));
+        }
+        decoder.leave()?;
+        decoder.end()?;
+
+        let raw_info = raw_info.ok_or_else(|| invalid("missing info dict"))?;
+        let m = ValueRef::Dict(entries);
+        Ok(Self {
+            announce: m.get(b"announce").map(string).transpose()?,
+            announce_list: match m.get(b"announce-list") {
+                Some(tiers) => list(tiers)?
+                    .iter()
+                    .map(|tier| list(tier)?.iter().map(string).collect())
+                    .collect::<Result<_>>()?,
+                None => vec![],
+            },
+            nodes: match m.get(b"nodes") {
+                Some(nodes) => list(nodes)?.iter().map(node).collect::<Result<_>>()?,
+                None => vec![],
+            },
+            comment: m.get(b"comment").map(string).transpose()?,
+            created_by: m.get(b"created by").map(string).transpose()?,
+            creation_date: m.get(b"creation date").map(int).transpose()?,
+            info: Info::from_value(m.get(b"info").ok_or_else(|| invalid("missing info dict"))?)?,
+            info_hash: hash(raw_info),
+        })
+    }
+
+    /// Build metainfo from a bare info dict, as fetched from peers with
+    /// ut_metadata. Only the info dict and its hash are filled in.
+    pub fn from_info_bytes(raw_info: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(raw_info);
+        let info = Info::from_value(&decoder.decode()?)?;
+        decoder.end()?;
+        Ok(Self {
+            announce: None,
+            announce_list: vec![],
+            nodes: vec![],
+            comment: None,
+            created_by: None,
+            creation_date: None,
+            info,
+            info_hash: hash(raw_info),
+        })
+    }
+
+    /// SHA-1 of the raw `info` dict, the key peers are looked up by.
+    pub fn info_hash(&self) -> Key {
+        self.info_hash
+    }
+
+    /// Trackers in tier order, falling back to `announce` when there is no
+    /// announce-list.
+    pub fn trackers(&self) -> Vec<String> {
+        if self.announce_list.is_empty() {
+            return self.announce.iter().cloned().collect();
+        }
+        self.announce_list.iter().flatten().cloned().collect()
+    }
+}
+
+impl Info {
+    fn from_value(v: &ValueRef) -> Result<Self> {
+        if !matches!(v, ValueRef::Dict(_)) {
+            return Err(invalid("info is not a dict"));
+        }
+        let pieces = bytes(v.get(b"pieces").ok_or_else(|| invalid("missing pieces"))?)?;
+        if pieces.len() % PIECE_HASH_LENGTH != 0 {
+            return Err(invalid("pieces is not a multiple of 20 bytes"));
+        }
+        let length = v.get(b"length").map(uint).transpose()?;
+        let files = match v.get(b"files") {
+            Some(files) => list(files)?
+                .iter()
+                .map(File::from_value)
+                .collect::<Result<_>>()?,
+            None => vec![],
+        };
+        if length.is_some() != files.is_empty() {
+            return Err(invalid("exactly one of length and files is required"));
+        }
+        Ok(Self {
+            name: string(v.get(b"name").ok_or_else(|| invalid("missing name"))?)?,
+            piece_length: uint(
+                v.get(b"piece length")
+                    .ok_or_else(|| invalid("missing piece length"))?,
+            )?,
+            pieces: pieces
+                .chunks_exact(PIECE_HASH_LENGTH)
+                .map(|c| c.try_into().expect("chunk is 20 bytes"))
+                .collect(),
+            length,
+            files,
+            private: v.get(b"private").map(int).transpose()? == Some(1),
+        })
+    }
+
+    pub fn is_multi_file(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.length
+            .unwrap_or_else(|| self.files.iter().map(|f| f.length).sum())
+    }
+}
+
+impl File {
+    fn from_value(v: &ValueRef) -> Result<Self> {
+        let path = list(v.get(b"path").ok_or_else(|| invalid("missing file path"))?)?
+            .iter()
+            .map(string)
+            .collect::<Result<Vec<_>>>()?;
+        if path.is_empty() {
+            return Err(invalid("empty file path"));
+        }
+        Ok(Self {
+            path,
+            length: uint(
+                v.get(b"length")
+                    .ok_or_else(|| invalid("missing file length"))?,
+            )?,
+        })
+    }
+}
+
+fn hash(raw: &[u8]) -> Key {
+    let digest: [u8; 20] = Sha1::digest(raw).into();
+    digest.into()
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidMetainfo(msg.into())
+}
+
+fn bytes<'a>(v: &ValueRef<'a>) -> Result<&'a [u8]> {
+    v.as_bytes().ok_or_else(|| invalid("expected a string"))
+}
+
+/// Names and paths are not always utf-8 in the wild; keep what we can.
+fn string(v: &ValueRef) -> Result<String> {
+    Ok(String::from_utf8_lossy(bytes(v)?).into_owned())
+}
+
+fn int(v: &ValueRef) -> Result<i64> {
+    v.as_int().ok_or_else(|| invalid("expected an integer"))
+}
+
+fn uint(v: &ValueRef) -> Result<u64> {
+    u64::try_from(int(v)?).map_err(|_| invalid("expected a non-negative integer"))
+}
+
+fn list<'a, 'b>(v: &'b ValueRef<'a>) -> Result<&'b [ValueRef<'a>]> {
+    v.as_list().ok_or_else(|| invalid("expected a list"))
+}
+
+fn node(v: &ValueRef) -> Result<(String, u16)> {
+    match list(v)? {
+        [host, port] => Ok((
+            string(host)?,
+            u16::try_from(int(port)?).map_err(|_| invalid("invalid node port"))?,
+        )),
+        _ => Err(invalid("node must be a [host, port] pair")),
+    }
+}
@@ -0,0 +1,259 @@
+//! Reading UDP datagrams out of packet captures, in the classic pcap
+//! format or pcapng, so DHT traffic captured on a node can be looked at
+//! offline.
+
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::errors::{Error, Result};
+
+const PCAP_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+/// The `if_tsresol` option of an interface description.
+const IF_TSRESOL: u16 = 9;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const UDP: u8 = 17;
+
+/// One captured UDP datagram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Datagram {
+    /// When it was captured, since the Unix epoch.
+    pub time: Duration,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub payload: Vec<u8>,
+}
+
+/// The UDP datagrams in the capture file at `path`.
+pub fn read_file(path: impl AsRef<Path>) -> Result<Vec<Datagram>> {
+    read(&fs::read(path)?)
+}
+
+/// The UDP datagrams in a pcap or pcapng capture, in capture order. Link
+/// types other than Ethernet, Linux cooked, loopback and raw IP are
+/// skipped, and so are IP fragments and IPv6 packets with extension
+/// headers. A capture cut off in the middle of a packet, as one stopped
+/// abruptly often is, ends at the last whole packet.
+pub fn read(buf: &[u8]) -> Result<Vec<Datagram>> {
+    let magic = u32_at(buf, 0, false).ok_or_else(|| invalid("file too short"))?;
+    match magic {
+        PCAPNG_SECTION => read_pcapng(buf),
+        PCAP_MICROS | PCAP_NANOS => read_pcap(buf, false),
+        _ if magic.swap_bytes() == PCAP_MICROS || magic.swap_bytes() == PCAP_NANOS => {
+            read_pcap(buf, true)
+        }
+        _ => Err(invalid("not a pcap or pcapng file")),
+    }
+}
+
+fn read_pcap(buf: &[u8], big_endian: bool) -> Result<Vec<Datagram>> {
+    let field = |at| u32_at(buf, at, big_endian).ok_or_else(|| invalid("truncated header"));
+    let frac_nanos = if field(0)? == PCAP_NANOS { 1 } else { 1_000 };
+    // The upper bits may carry FCS information.
+    let link = field(20)? & 0xffff;
+    let mut datagrams = vec![];
+    let mut pos = 24;
+    while let (Some(secs), Some(frac), Some(len)) = (
+        u32_at(buf, pos, big_endian),
+        u32_at(buf, pos + 4, big_endian),
+        u32_at(buf, pos + 8, big_endian),
+    ) {
+        let start = pos + 16;
+        let Some(data) = buf.get(start..start + len as usize) else {
+            break;
+        };
+        let time = Duration::from_secs(u64::from(secs))
+            + Duration::from_nanos(u64::from(frac) * frac_nanos);
+        datagrams.extend(udp(link, data, time));
+        pos = start + len as usize;
+    }
+    Ok(datagrams)
+}
+
+fn read_pcapng(buf: &[u8]) -> Result<Vec<Datagram>> {
+    let mut big_endian = false;
+    // Link type and timestamp units per second, by interface id.
+    let mut interfaces: Vec<(u32, u64)> = vec![];
+    let mut datagrams = vec![];
+    let mut pos = 0;
+    while let Some(kind) = u32_at(buf, pos, big_endian) {
+        if kind == PCAPNG_SECTION {
+            big_endian = match u32_at(buf, pos + 8, false) {
+                Some(PCAPNG_BYTE_ORDER) => false,
+                Some(order) if order.swap_bytes() == PCAPNG_BYTE_ORDER => true,
+                _ => return Err(invalid("bad pcapng byte order")),
+            };
+            interfaces.clear();
+        }
+        let Some(len) = u32_at(buf, pos + 4, big_endian).map(|len| len as usize) else {
+            break;
+        };
+        if len < 12 || len % 4 != 0 {
+            return Err(invalid("bad pcapng block length"));
+        }
+        let Some(body) = buf.get(pos + 8..pos + len - 4) else {
+            break;
+        };
+        let field = |at| u32_at(body, at, big_endian);
+        match kind {
+            PCAPNG_INTERFACE => {
+                let link = u16_at(body, 0, big_endian).map_or(0, u32::from);
+                let resolution = options(body.get(8..).unwrap_or_default(), big_endian)
+                    .find(|(code, _)| *code == IF_TSRESOL)
+                    .and_then(|(_, value)| value.first().copied())
+                    .map_or(1_000_000, resolution);
+                interfaces.push((link, resolution));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let (Some(interface), Some(high), Some(low), Some(len)) =
+                    (field(0), field(4), field(8), field(12))
+                else {
+                    return Err(invalid("truncated packet block"));
+                };
+                let &(link, resolution) = interfaces
+                    .get(interface as usize)
+                    .ok_or_else(|| invalid("packet on an undescribed interface"))?;
+                let data = body
+                    .get(20..20 + len as usize)
+                    .ok_or_else(|| invalid("truncated packet block"))?;
+                let ticks = u64::from(high) << 32 | u64::from(low);
+                datagrams.extend(udp(link, data, ticks_to_duration(ticks, resolution)));
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                // No timestamp, and always from the first interface.
+                let len = field(0).unwrap_or_default() as usize;
+                let data = body.get(4..).unwrap_or_default();
+                let data = &data[..len.min(data.len())];
+                if let Some(&(link, _)) = interfaces.first() {
+                    datagrams.extend(udp(link, data, Duration::ZERO));
+                }
+            }
+            _ => {}
+        }
+        pos += len;
+    }
+    Ok(datagrams)
+}
+
+/// The options of a pcapng block, as codes and values.
+fn options(mut buf: &[u8], big_endian: bool) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let code = u16_at(buf, 0, big_endian)?;
+        let len = usize::from(u16_at(buf, 2, big_endian)?);
+        if code == 0 {
+            return None;
+        }
+        let value = buf.get(4..4 + len)?;
+        buf = buf.get(4 + len.next_multiple_of(4)..).unwrap_or_default();
+        Some((code, value))
+    })
+}
+
+/// Timestamp units per second for an `if_tsresol` value: a power of ten,
+/// or of two when the high bit is set.
+fn resolution(tsresol: u8) -> u64 {
+    let exponent = u32::from(tsresol & 0x7f);
+    let base: u64 = if tsresol & 0x80 != 0 { 2 } else { 10 };
+    base.checked_pow(exponent).unwrap_or(u64::MAX)
+}
+
+fn ticks_to_duration(ticks: u64, per_second: u64) -> Duration {
+    let nanos = u128::from(ticks % per_second) * 1_000_000_000 / u128::from(per_second);
+    Duration::new(ticks / per_second, nanos as u32)
+}
+
+/// The UDP datagram in a captured frame, if there is one.
+fn udp(link: u32, frame: &[u8], time: Duration) -> Option<Datagram> {
+    let packet = match link {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            // Skip 802.1Q and 802.1ad tags.
+            while matches!(u16_at(frame, offset, true)?, 0x8100 | 0x88a8) {
+                offset += 4;
+            }
+            frame.get(offset + 2..)?
+        }
+        LINKTYPE_NULL | LINKTYPE_LOOP => frame.get(4..)?,
+        LINKTYPE_LINUX_SLL => frame.get(16..)?,
+        LINKTYPE_LINUX_SLL2 => frame.get(20..)?,
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => frame,
+        _ => return None,
+    };
+    let (src, dst, segment) = match packet.first()? >> 4 {
+        4 => {
+            let header = usize::from(packet[0] & 0x0f) * 4;
+            let total = usize::from(u16_at(packet, 2, true)?);
+            // More fragments, or a fragment offset.
+            let fragment = u16_at(packet, 6, true)? & 0x3fff != 0;
+            if *packet.get(9)? != UDP || fragment {
+                return None;
+            }
+            let src: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            (
+                IpAddr::from(Ipv4Addr::from(src)),
+                IpAddr::from(Ipv4Addr::from(dst)),
+                packet.get(header..total.min(packet.len()))?,
+            )
+        }
+        6 => {
+            let len = usize::from(u16_at(packet, 4, true)?);
+            if *packet.get(6)? != UDP {
+                return None;
+            }
+            let src: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            (
+                IpAddr::from(Ipv6Addr::from(src)),
+                IpAddr::from(Ipv6Addr::from(dst)),
+                packet.get(40..(40 + len).min(packet.len()))?,
+            )
+        }
+        _ => return None,
+    };
+    let len = usize::from(u16_at(segment, 4, true)?);
+    Some(Datagram {
+        time,
+        src: SocketAddr::new(src, u16_at(segment, 0, true)?),
+        dst: SocketAddr::new(dst, u16_at(segment, 2, true)?),
+        payload: segment.get(8..len.min(segment.len()))?.to_vec(),
+    })
+}
+
+fn u16_at(buf: &[u8], at: usize, big_endian: bool) -> Option<u16> {
+    let bytes = buf.get(at..at + 2)?.try_into().ok()?;
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn u32_at(buf: &[u8], at: usize, big_endian: bool) -> Option<u32> {
+    let bytes = buf.get(at..at + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidCapture(msg.to_string())
+}
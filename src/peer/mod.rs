@@ -0,0 +1,162 @@
+//! The BitTorrent peer wire protocol (BEP 3) with the extension protocol
+//! (BEP 10), as far as an indexer needs it to talk to peers.
+
+pub mod ut_metadata;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use crate::errors::{Error, Result};
+use crate::server::route_table::Key;
+
+const PROTOCOL: &[u8] = b"BitTorrent protocol";
+const HANDSHAKE_LENGTH: usize = 1 + 19 + 8 + 20 + 20;
+/// Reserved byte and bit announcing BEP 10 support.
+const EXTENSION_BYTE: usize = 5;
+const EXTENSION_BIT: u8 = 0x10;
+/// Big enough for a bitfield of a million pieces.
+const MAX_MESSAGE_LENGTH: usize = 1 << 20;
+
+/// Message id of extension protocol messages.
+pub const EXTENDED: u8 = 20;
+/// Extended message id of the extension handshake.
+pub const EXTENDED_HANDSHAKE: u8 = 0;
+
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    KeepAlive,
+    Extended {
+        id: u8,
+        payload: Vec<u8>,
+    },
+    /// Any message we do not act on, such as `bitfield` or `have`.
+    Other {
+        id: u8,
+        payload: Vec<u8>,
+    },
+}
+
+/// A TCP connection to a peer that has completed the handshake.
+pub struct PeerConnection {
+    stream: TcpStream,
+    peer_id: [u8; 20],
+    extensions: bool,
+}
+
+impl PeerConnection {
+    /// Connect to `addr` and handshake for `info_hash`, announcing support
+    /// for the extension protocol. `timeout` applies to the connect and to
+    /// every later read and write.
+    pub fn connect(
+        addr: SocketAddr,
+        info_hash: Key,
+        peer_id: [u8; 20],
+        timeout: Duration,
+    ) -> Result<Self> {
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Self::handshake(stream, info_hash, peer_id)
+    }
+
+    fn handshake(mut stream: TcpStream, info_hash: Key, peer_id: [u8; 20]) -> Result<Self> {
+        let mut reserved = [0; 8];
+        reserved[EXTENSION_BYTE] |= EXTENSION_BIT;
+        let mut buf = Vec::with_capacity(HANDSHAKE_LENGTH);
+        buf.push(PROTOCOL.len() as u8);
+        buf.extend_from_slice(PROTOCOL);
+        buf.extend_from_slice(&reserved);
+        buf.extend_from_slice(info_hash.as_bytes());
+        buf.extend_from_slice(&peer_id);
+        stream.write_all(&buf)?;
+
+        let mut reply = [0; HANDSHAKE_LENGTH];
+        stream.read_exact(&mut reply)?;
+        if reply[0] as usize != PROTOCOL.len() || &reply[1..20] != PROTOCOL {
+            return Err(invalid("not a BitTorrent handshake"));
+        }
+        if &reply[28..48] != info_hash.as_bytes() {
+            return Err(invalid("peer answered for another info hash"));
+        }
+        Ok(Self {
+            stream,
+            peer_id: reply[48..].try_into()?,
+            extensions: reply[20 + EXTENSION_BYTE] & EXTENSION_BIT != 0,
+        })
+    }
+
+    pub fn peer_id(&self) -> [u8; 20] {
+        self.peer_id
+    }
+
+    /// Whether the peer set the extension protocol bit in its handshake.
+    pub fn supports_extensions(&self) -> bool {
+        self.extensions
+    }
+
+    pub fn send(&mut self, msg: &Message) -> Result<()> {
+        let mut buf = Vec::new();
+        match msg {
+            Message::KeepAlive => buf.extend_from_slice(&0u32.to_be_bytes()),
+            Message::Extended { id, payload } => {
+                buf.extend_from_slice(&(payload.len() as u32 + 2).to_be_bytes());
+                buf.extend_from_slice(&[EXTENDED, *id]);
+                buf.extend_from_slice(payload);
+            }
+            Message::Other { id, payload } => {
+                buf.extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+                buf.push(*id);
+                buf.extend_from_slice(payload);
+            }
+        }
+        self.stream.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Receive the next message, failing once `deadline` has passed even
+    /// if the peer keeps sending.
+    pub fn recv_before(&mut self, deadline: Instant) -> Result<Message> {
+        let left = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|left| !left.is_zero())
+            .ok_or_else(|| invalid("timed out"))?;
+        self.stream.set_read_timeout(Some(left))?;
+        self.recv().map_err(|e| {
+            if Instant::now() >= deadline {
+                invalid("timed out")
+            } else {
+                e
+            }
+        })
+    }
+
+    pub fn recv(&mut self) -> Result<Message> {
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 {
+            return Ok(Message::KeepAlive);
+        }
+        if len > MAX_MESSAGE_LENGTH {
+            return Err(invalid(&format!("message of {} bytes is too long", len)));
+        }
+        let mut buf = vec![0; len];
+        self.stream.read_exact(&mut buf)?;
+        let mut payload = buf.split_off(1);
+        Ok(match buf[0] {
+            EXTENDED => {
+                if payload.is_empty() {
+                    return Err(invalid("extended message without an id"));
+                }
+                let id = payload.remove(0);
+                Message::Extended { id, payload }
+            }
+            id => Message::Other { id, payload },
+        })
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::PeerProtocol(msg.into())
+}
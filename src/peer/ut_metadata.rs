@@ -0,0 +1,153 @@
+//! Fetching a torrent's info dict from a peer (BEP 9).
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use sha1::{Digest, Sha1};
+
+use super::{invalid, Message, PeerConnection, EXTENDED_HANDSHAKE};
+use crate::errors::Result;
+use crate::metainfo::Metainfo;
+use crate::server::route_table::Key;
+use crate::util::bencode::{Decoder, Encoder, Limits, ValueRef};
+
+/// The id we ask peers to use for `ut_metadata` messages sent to us.
+pub const LOCAL_ID: u8 = 1;
+pub const PIECE_SIZE: usize = 16 * 1024;
+/// Refuse metadata larger than this, whatever the peer claims.
+pub const MAX_METADATA_SIZE: usize = 8 * 1024 * 1024;
+
+const REQUEST: i64 = 0;
+const DATA: i64 = 1;
+const REJECT: i64 = 2;
+
+/// Download the info dict for `info_hash` from the peer at `addr`, verify it
+/// against the hash and parse it, all within `timeout`.
+pub fn fetch(
+    addr: SocketAddr,
+    info_hash: Key,
+    peer_id: [u8; 20],
+    timeout: Duration,
+) -> Result<Metainfo> {
+    let deadline = Instant::now() + timeout;
+    let mut conn = PeerConnection::connect(addr, info_hash, peer_id, timeout)?;
+    if !conn.supports_extensions() {
+        return Err(invalid("peer does not support the extension protocol"));
+    }
+    conn.send(&Message::Extended {
+        id: EXTENDED_HANDSHAKE,
+        payload: extension_handshake()?,
+    })?;
+
+    let (remote_id, size) = loop {
+        if let Message::Extended {
+            id: EXTENDED_HANDSHAKE,
+            payload,
+        } = conn.recv_before(deadline)?
+        {
+            break parse_extension_handshake(&payload)?;
+        }
+    };
+    if size == 0 || size > MAX_METADATA_SIZE {
+        return Err(invalid(&format!("metadata size {} out of range", size)));
+    }
+
+    let pieces = size.div_ceil(PIECE_SIZE);
+    for piece in 0..pieces {
+        conn.send(&Message::Extended {
+            id: remote_id,
+            payload: request(piece)?,
+        })?;
+    }
+
+    let mut metadata = vec![0; size];
+    let mut received = vec![false; pieces];
+    let mut remaining = pieces;
+    while remaining > 0 {
+        let payload = match conn.recv_before(deadline)? {
+            Message::Extended {
+                id: LOCAL_ID,
+                payload,
+            } => payload,
+            _ => continue,
+        };
+        let (msg_type, piece, data) = parse_message(&payload)?;
+        match msg_type {
+            DATA => {}
+            REJECT => return Err(invalid(&format!("peer rejected piece {}", piece))),
+            _ => continue,
+        }
+        if piece >= pieces || data.len() != PIECE_SIZE.min(size - piece * PIECE_SIZE) {
+            return Err(invalid(&format!("unexpected metadata piece {}", piece)));
+        }
+        let start = piece * PIECE_SIZE;
+        metadata[start..start + data.len()].copy_from_slice(data);
+        if !received[piece] {
+            received[piece] = true;
+            remaining -= 1;
+        }
+    }
+
+    let digest: [u8; 20] = Sha1::digest(&metadata).into();
+    if Key::from(digest) != info_hash {
+        return Err(invalid("metadata does not match the info hash"));
+    }
+    Metainfo::from_info_bytes(&metadata)
+}
+
+fn extension_handshake() -> Result<Vec<u8>> {
+    let mut e = Encoder::new(Vec::new());
+    e.begin_dict()?;
+    e.write_str("m")?;
+    e.begin_dict()?;
+    e.write_str("ut_metadata")?;
+    e.write_int(LOCAL_ID)?;
+    e.end()?;
+    e.end()?;
+    Ok(e.into_inner())
+}
+
+/// The peer's `ut_metadata` id and the metadata size it advertises.
+fn parse_extension_handshake(payload: &[u8]) -> Result<(u8, usize)> {
+    let mut decoder = Decoder::with_limits(payload, Limits::datagram())?;
+    let handshake = decoder.decode()?;
+    let id = handshake
+        .get(b"m")
+        .and_then(|m| m.get(b"ut_metadata"))
+        .and_then(ValueRef::as_int)
+        .and_then(|id| u8::try_from(id).ok())
+        .filter(|id| *id != 0)
+        .ok_or_else(|| invalid("peer does not support ut_metadata"))?;
+    let size = handshake
+        .get(b"metadata_size")
+        .and_then(ValueRef::as_int)
+        .and_then(|size| usize::try_from(size).ok())
+        .ok_or_else(|| invalid("missing metadata_size"))?;
+    Ok((id, size))
+}
+
+fn request(piece: usize) -> Result<Vec<u8>> {
+    let mut e = Encoder::new(Vec::new());
+    e.begin_dict()?;
+    e.write_str("msg_type")?;
+    e.write_int(REQUEST)?;
+    e.write_str("piece")?;
+    e.write_int(piece as u64)?;
+    e.end()?;
+    Ok(e.into_inner())
+}
+
+/// Split a `ut_metadata` message into its type, piece and trailing data.
+fn parse_message(payload: &[u8]) -> Result<(i64, usize, &[u8])> {
+    let mut decoder = Decoder::with_limits(payload, Limits::datagram())?;
+    let header = decoder.decode()?;
+    let field = |key: &[u8]| {
+        header
+            .get(key)
+            .and_then(ValueRef::as_int)
+            .ok_or_else(|| invalid("malformed ut_metadata message"))
+    };
+    let piece =
+        usize::try_from(field(b"piece")?).map_err(|_| invalid("malformed ut_metadata message"))?;
+    Ok((field(b"msg_type")?, piece, &payload[decoder.position()..]))
+}
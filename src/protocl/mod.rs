@@ -1,95 +1,361 @@
 use std::collections::BTreeMap;
+use std::io::Write;
 use util::bencode;
 
 use crate::{
     errors::Error,
     errors::Result,
-    hashmap,
-    util::{self, bencode::Value},
+    util::{
+        self,
+        bencode::{Decoder, Encoder, Limits, Value, ValueRef},
+    },
 };
 
+/// Ids, hashes, tokens and compact addresses are raw bytes on the wire and
+/// are kept that way; most are not valid utf-8.
 #[derive(Debug, PartialEq)]
 pub enum DHTQuery {
     Ping {
-        id: String,
+        id: Vec<u8>,
     },
     FindNode {
-        id: String,
-        target: String,
+        id: Vec<u8>,
+        target: Vec<u8>,
     },
+    /// `scrape` asks for bloom filters of the swarm and `noseed` for
+    /// leechers only (BEP 33); both are sent only when set.
     GetPeers {
-        id: String,
-        info_hash: String,
+        id: Vec<u8>,
+        info_hash: Vec<u8>,
+        scrape: bool,
+        noseed: bool,
     },
+    /// `implied_port` is optional (BEP 5) and decodes as 0 when absent.
     AnnouncePeer {
-        id: String,
+        id: Vec<u8>,
         impiled_port: u8,
         port: u64,
-        info_hash: String,
-        token: String,
+        info_hash: Vec<u8>,
+        token: Vec<u8>,
+        seed: bool,
+    },
+    /// Fetch the immutable item `target` (BEP 44).
+    Get {
+        id: Vec<u8>,
+        target: Vec<u8>,
+    },
+    /// Store an immutable item; `value` is the item's bencoding.
+    Put {
+        id: Vec<u8>,
+        token: Vec<u8>,
+        value: Vec<u8>,
     },
 }
 
 #[derive(Debug, PartialEq)]
 pub enum DHTResponse {
     ID {
-        id: String,
+        id: Vec<u8>,
     },
     FindNode {
-        id: String,
-        nodes: String,
+        id: Vec<u8>,
+        nodes: Vec<u8>,
     },
+    /// Carries `values` when the node knows peers and closer `nodes`
+    /// otherwise, plus the `BFsd`/`BFpe` bloom filters of seeds and peers
+    /// when scraped; an empty field is left out of the message.
     GetPeers {
-        id: String,
-        token: String,
-        values: Vec<String>,
+        id: Vec<u8>,
+        token: Vec<u8>,
+        values: Vec<Vec<u8>>,
+        nodes: Vec<u8>,
+        seeds: Vec<u8>,
+        peers: Vec<u8>,
+    },
+    /// Answers `get` with the bencoded item in `value` if the node has it.
+    /// Without one the message cannot be told apart from a `get_peers`
+    /// response, and decodes as one.
+    Get {
+        id: Vec<u8>,
+        token: Vec<u8>,
+        nodes: Vec<u8>,
+        value: Vec<u8>,
     },
 }
 
+/// KRPC error code for a malformed packet or invalid arguments.
+pub const PROTOCOL_ERROR: u64 = 203;
+
 #[derive(Debug, PartialEq)]
 pub enum KRPC {
-    Query(String, DHTQuery),
-    Response(String, DHTResponse),
-    Error(u64, String),
+    /// Transaction id, query and whether the sender is a read-only node
+    /// (BEP 43), which should not be added to routing tables.
+    Query(Vec<u8>, DHTQuery, bool),
+    Response(Vec<u8>, DHTResponse),
+    /// Transaction id, error code and message.
+    Error(Vec<u8>, u64, String),
 }
 
-impl KRPC {
-    pub fn encode(self) -> Result<String> {
+impl DHTQuery {
+    /// The KRPC method name, sent as the `q` key.
+    pub fn method(&self) -> &'static str {
+        match self {
+            DHTQuery::Ping { .. } => "ping",
+            DHTQuery::FindNode { .. } => "find_node",
+            DHTQuery::GetPeers { .. } => "get_peers",
+            DHTQuery::AnnouncePeer { .. } => "announce_peer",
+            DHTQuery::Get { .. } => "get",
+            DHTQuery::Put { .. } => "put",
+        }
+    }
+
+    /// The querying node's id.
+    pub fn id(&self) -> &[u8] {
         match self {
-            KRPC::Query(t, q) => Self::encode_query(t, q),
-            KRPC::Response(t, response) => todo!(),
-            KRPC::Error(code, msg) => todo!(),
+            DHTQuery::Ping { id }
+            | DHTQuery::FindNode { id, .. }
+            | DHTQuery::GetPeers { id, .. }
+            | DHTQuery::AnnouncePeer { id, .. }
+            | DHTQuery::Get { id, .. }
+            | DHTQuery::Put { id, .. } => id,
         }
     }
 
-    fn encode_query(t: String, q: DHTQuery) -> Result<String> {
-        let mut map = BTreeMap::new();
-        map.insert("t".to_string(), Value::try_from(t.as_str())?);
-        map.insert("y".to_string(), Value::try_from("q")?);
-        //
-        match q {
+    fn encode_args<W: Write>(&self, e: &mut Encoder<W>) -> Result<()> {
+        e.begin_dict()?;
+        match self {
+            DHTQuery::Ping { id } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+            }
+            DHTQuery::FindNode { id, target } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+                e.write_str("target")?;
+                e.write_bytes(target)?;
+            }
+            DHTQuery::GetPeers {
+                id,
+                info_hash,
+                scrape,
+                noseed,
+            } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+                e.write_str("info_hash")?;
+                e.write_bytes(info_hash)?;
+                if *noseed {
+                    e.write_str("noseed")?;
+                    e.write_int(1)?;
+                }
+                if *scrape {
+                    e.write_str("scrape")?;
+                    e.write_int(1)?;
+                }
+            }
             DHTQuery::AnnouncePeer {
                 id,
                 impiled_port,
                 port,
                 info_hash,
                 token,
-            } => todo!(),
-            DHTQuery::Ping { id } => {
-                map.insert("q".into(), Value::try_from("ping")?);
-                map.insert(
-                    "a".into(),
-                    Value::Dict(hashmap!["id".to_string() => Value::try_from(id.as_str())? ]),
-                );
-                Value::Dict(map).encode()
+                seed,
+            } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+                e.write_str("implied_port")?;
+                e.write_int(u64::from(*impiled_port))?;
+                e.write_str("info_hash")?;
+                e.write_bytes(info_hash)?;
+                e.write_str("port")?;
+                e.write_int(*port)?;
+                if *seed {
+                    e.write_str("seed")?;
+                    e.write_int(1)?;
+                }
+                e.write_str("token")?;
+                e.write_bytes(token)?;
+            }
+            DHTQuery::Get { id, target } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+                e.write_str("target")?;
+                e.write_bytes(target)?;
             }
-            DHTQuery::FindNode { id, target } => todo!(),
-            DHTQuery::GetPeers { id, info_hash } => todo!(),
+            DHTQuery::Put { id, token, value } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+                e.write_str("token")?;
+                e.write_bytes(token)?;
+                e.write_str("v")?;
+                e.write_value_ref(&bencode::decode_ref(value)?)?;
+            }
+        }
+        e.end()
+    }
+}
+
+impl DHTResponse {
+    /// The responding node's id.
+    pub fn id(&self) -> &[u8] {
+        match self {
+            DHTResponse::ID { id }
+            | DHTResponse::FindNode { id, .. }
+            | DHTResponse::GetPeers { id, .. }
+            | DHTResponse::Get { id, .. } => id,
+        }
+    }
+
+    /// Compact info of nodes closer to the target, empty if there are
+    /// none.
+    pub fn nodes(&self) -> &[u8] {
+        match self {
+            DHTResponse::ID { .. } => &[],
+            DHTResponse::FindNode { nodes, .. }
+            | DHTResponse::GetPeers { nodes, .. }
+            | DHTResponse::Get { nodes, .. } => nodes,
         }
     }
 
+    fn encode_values<W: Write>(&self, e: &mut Encoder<W>) -> Result<()> {
+        e.begin_dict()?;
+        match self {
+            DHTResponse::ID { id } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+            }
+            DHTResponse::FindNode { id, nodes } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+                e.write_str("nodes")?;
+                e.write_bytes(nodes)?;
+            }
+            DHTResponse::GetPeers {
+                id,
+                token,
+                values,
+                nodes,
+                seeds,
+                peers,
+            } => {
+                if !peers.is_empty() {
+                    e.write_str("BFpe")?;
+                    e.write_bytes(peers)?;
+                }
+                if !seeds.is_empty() {
+                    e.write_str("BFsd")?;
+                    e.write_bytes(seeds)?;
+                }
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+                if !nodes.is_empty() {
+                    e.write_str("nodes")?;
+                    e.write_bytes(nodes)?;
+                }
+                e.write_str("token")?;
+                e.write_bytes(token)?;
+                if !values.is_empty() {
+                    e.write_str("values")?;
+                    e.begin_list()?;
+                    for v in values {
+                        e.write_bytes(v)?;
+                    }
+                    e.end()?;
+                }
+            }
+            DHTResponse::Get {
+                id,
+                token,
+                nodes,
+                value,
+            } => {
+                e.write_str("id")?;
+                e.write_bytes(id)?;
+                if !nodes.is_empty() {
+                    e.write_str("nodes")?;
+                    e.write_bytes(nodes)?;
+                }
+                e.write_str("token")?;
+                e.write_bytes(token)?;
+                if !value.is_empty() {
+                    e.write_str("v")?;
+                    e.write_value_ref(&bencode::decode_ref(value)?)?;
+                }
+            }
+        }
+        e.end()
+    }
+}
+
+impl KRPC {
+    /// Encode as a string, failing if any field is not utf-8. Use
+    /// [`encode_to`](KRPC::encode_to) for real traffic.
+    pub fn encode(self) -> Result<String> {
+        let mut buf = Vec::new();
+        self.encode_to(&mut buf)?;
+        String::from_utf8(buf).map_err(|_| Error::InvalidValue)
+    }
+
+    /// Encode into `w`, typically a send buffer reused across packets.
+    /// Keys are written in sorted order as bencode requires.
+    pub fn encode_to<W: Write>(&self, w: W) -> Result<()> {
+        self.write(w, false)
+    }
+
+    /// Encode like [`encode_to`](KRPC::encode_to), marking a query as
+    /// from a read-only node (BEP 43) whatever its flag says. Other
+    /// messages are encoded as usual.
+    pub fn encode_read_only_to<W: Write>(&self, w: W) -> Result<()> {
+        self.write(w, true)
+    }
+
+    fn write<W: Write>(&self, w: W, read_only: bool) -> Result<()> {
+        let mut e = Encoder::new(w);
+        e.begin_dict()?;
+        match self {
+            KRPC::Query(t, q, ro) => {
+                e.write_str("a")?;
+                q.encode_args(&mut e)?;
+                e.write_str("q")?;
+                e.write_str(q.method())?;
+                if read_only || *ro {
+                    e.write_str("ro")?;
+                    e.write_int(1)?;
+                }
+                e.write_str("t")?;
+                e.write_bytes(t)?;
+                e.write_str("y")?;
+                e.write_str("q")?;
+            }
+            KRPC::Response(t, r) => {
+                e.write_str("r")?;
+                r.encode_values(&mut e)?;
+                e.write_str("t")?;
+                e.write_bytes(t)?;
+                e.write_str("y")?;
+                e.write_str("r")?;
+            }
+            KRPC::Error(t, code, msg) => {
+                e.write_str("e")?;
+                e.begin_list()?;
+                e.write_int(*code)?;
+                e.write_str(msg)?;
+                e.end()?;
+                e.write_str("t")?;
+                e.write_bytes(t)?;
+                e.write_str("y")?;
+                e.write_str("e")?;
+            }
+        }
+        e.end()
+    }
+
     pub fn decode(s: &str) -> Result<Self> {
-        match bencode::decode(&mut s.chars().peekable())? {
+        let limits = Limits::datagram();
+        if s.len() > limits.max_input {
+            return Err(Error::BencodeInputTooLarge(limits.max_input));
+        }
+        match bencode::decode_with_limits(&mut s.chars().peekable(), limits)? {
             bencode::Value::Dict(ref mut dict) => match dict.get("y") {
                 Some(Value::String(q)) if q == "q" => Self::decode_query(dict),
                 Some(Value::String(e)) if e == "e" => Self::decode_error(dict),
@@ -100,13 +366,155 @@ impl KRPC {
         }
     }
 
+    /// Decode a KRPC message straight from a datagram, borrowing from `buf`
+    /// while walking the message instead of building an owned tree.
+    pub fn decode_bytes(buf: &[u8]) -> Result<Self> {
+        let decoded = Self::decode_message(buf);
+        if let Err(e) = &decoded {
+            tracing::trace!(len = buf.len(), error = %e, "undecodable KRPC message");
+        }
+        decoded
+    }
+
+    /// The transaction id of a query too malformed to decode, so its sender
+    /// can still get a protocol error back. `None` unless `buf` is a
+    /// dictionary with `y` set to `q` and a string `t`.
+    pub fn query_transaction(buf: &[u8]) -> Option<Vec<u8>> {
+        let mut decoder = Decoder::with_limits(buf, Limits::datagram()).ok()?;
+        let m = decoder.decode().ok()?;
+        match m.get(b"y").and_then(ValueRef::as_bytes) {
+            Some(b"q") => Some(m.get(b"t")?.as_bytes()?.to_vec()),
+            _ => None,
+        }
+    }
+
+    fn decode_message(buf: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::with_limits(buf, Limits::datagram())?;
+        let m = decoder.decode()?;
+        decoder.end()?;
+        match m.get(b"y").and_then(ValueRef::as_bytes) {
+            Some(b"q") => Self::decode_query_ref(&m),
+            Some(b"e") => Self::decode_error_ref(&m),
+            Some(b"r") => Self::decode_response_ref(&m),
+            _ => Err(Error::InvalidKRPC),
+        }
+    }
+
+    fn decode_query_ref(m: &ValueRef) -> Result<Self> {
+        let t = bytes(m.get(b"t"))?;
+        let a = match m.get(b"a") {
+            Some(a @ ValueRef::Dict(_)) => a,
+            _ => return Err(Error::InvalidKRPC),
+        };
+        let query = match m.get(b"q").and_then(ValueRef::as_bytes) {
+            Some(b"ping") => DHTQuery::Ping {
+                id: bytes(a.get(b"id"))?,
+            },
+            Some(b"find_node") => DHTQuery::FindNode {
+                id: bytes(a.get(b"id"))?,
+                target: bytes(a.get(b"target"))?,
+            },
+            Some(b"announce_peer") => DHTQuery::AnnouncePeer {
+                id: bytes(a.get(b"id"))?,
+                impiled_port: a.get(b"implied_port").map_or(Ok(0), |v| int(Some(v)))?,
+                port: int(a.get(b"port"))?,
+                info_hash: bytes(a.get(b"info_hash"))?,
+                token: bytes(a.get(b"token"))?,
+                seed: flag(a.get(b"seed"))?,
+            },
+            Some(b"get_peers") => DHTQuery::GetPeers {
+                id: bytes(a.get(b"id"))?,
+                info_hash: bytes(a.get(b"info_hash"))?,
+                scrape: flag(a.get(b"scrape"))?,
+                noseed: flag(a.get(b"noseed"))?,
+            },
+            Some(b"get") => DHTQuery::Get {
+                id: bytes(a.get(b"id"))?,
+                target: bytes(a.get(b"target"))?,
+            },
+            Some(b"put") => DHTQuery::Put {
+                id: bytes(a.get(b"id"))?,
+                token: bytes(a.get(b"token"))?,
+                value: encoded(a.get(b"v"))?,
+            },
+            _ => return Err(Error::InvalidKRPC),
+        };
+        Ok(Self::Query(t, query, flag(m.get(b"ro"))?))
+    }
+
+    fn decode_error_ref(m: &ValueRef) -> Result<Self> {
+        let t = bytes(m.get(b"t"))?;
+        match m.get(b"e").and_then(ValueRef::as_list) {
+            Some([code, msg]) => Ok(Self::Error(
+                t,
+                int(Some(code))?,
+                msg.as_str().ok_or(Error::InvalidValue)?.into(),
+            )),
+            _ => Err(Error::InvalidKRPC),
+        }
+    }
+
+    fn decode_response_ref(m: &ValueRef) -> Result<Self> {
+        let t = bytes(m.get(b"t"))?;
+        let r = match m.get(b"r") {
+            Some(r @ ValueRef::Dict(_)) => r,
+            _ => return Err(Error::InvalidKRPC),
+        };
+        let id = bytes(r.get(b"id"))?;
+        // get
+        if let Some(value) = r.get(b"v") {
+            let nodes = r.get(b"nodes").map(|n| bytes(Some(n))).transpose()?;
+            return Ok(Self::Response(
+                t,
+                DHTResponse::Get {
+                    id,
+                    token: bytes(r.get(b"token"))?,
+                    nodes: nodes.unwrap_or_default(),
+                    value: encoded(Some(value))?,
+                },
+            ));
+        }
+        // get_peers
+        if let Some(token) = r.get(b"token") {
+            let values = match r.get(b"values") {
+                Some(values) => values
+                    .as_list()
+                    .ok_or(Error::InvalidValue)?
+                    .iter()
+                    .filter_map(|v| v.as_bytes().map(<[u8]>::to_vec))
+                    .collect(),
+                None => vec![],
+            };
+            let optional = |key: &[u8]| r.get(key).map(|v| bytes(Some(v))).transpose();
+            return Ok(Self::Response(
+                t,
+                DHTResponse::GetPeers {
+                    id,
+                    token: bytes(Some(token))?,
+                    values,
+                    nodes: optional(b"nodes")?.unwrap_or_default(),
+                    seeds: optional(b"BFsd")?.unwrap_or_default(),
+                    peers: optional(b"BFpe")?.unwrap_or_default(),
+                },
+            ));
+        }
+        // find_nodes
+        if let Some(nodes) = r.get(b"nodes") {
+            let nodes = bytes(Some(nodes))?;
+            return Ok(Self::Response(t, DHTResponse::FindNode { id, nodes }));
+        }
+        Ok(Self::Response(t, DHTResponse::ID { id }))
+    }
+
     fn decode_query(m: &mut BTreeMap<String, Value>) -> Result<Self> {
         let t = m.remove("t").ok_or(Error::InvalidKRPC)?;
+        let ro = legacy_flag(m.remove("ro"))?;
         if let Some(Value::Dict(mut a)) = m.remove("a") {
             return match m.get("q") {
                 Some(Value::String(q)) if q == "ping" => {
                     if let Some(Value::String(id)) = a.remove("id") {
-                        return Ok(Self::Query(t.try_into()?, DHTQuery::Ping { id }));
+                        let id = id.into_bytes();
+                        return Ok(Self::Query(t.try_into()?, DHTQuery::Ping { id }, ro));
                     }
                     Err(Error::InvalidKRPC)
                 }
@@ -122,6 +530,7 @@ impl KRPC {
                             id: id.unwrap().try_into()?,
                             target: target.unwrap().try_into()?,
                         },
+                        ro,
                     ))
                 }
                 Some(Value::String(q)) if q == "announce_peer" => {
@@ -130,23 +539,20 @@ impl KRPC {
                     let info_hash = a.remove("info_hash");
                     let port = a.remove("port");
                     let implied_port = a.remove("implied_port");
-                    if id.is_none()
-                        || token.is_none()
-                        || info_hash.is_none()
-                        || port.is_none()
-                        || implied_port.is_none()
-                    {
+                    if id.is_none() || token.is_none() || info_hash.is_none() || port.is_none() {
                         return Err(Error::InvalidKRPC);
                     }
                     Ok(Self::Query(
                         t.try_into()?,
                         DHTQuery::AnnouncePeer {
                             id: id.unwrap().try_into()?,
-                            impiled_port: implied_port.unwrap().try_into()?,
+                            impiled_port: implied_port.map_or(Ok(0), TryInto::try_into)?,
                             port: port.unwrap().try_into()?,
                             info_hash: info_hash.unwrap().try_into()?,
                             token: token.unwrap().try_into()?,
+                            seed: legacy_flag(a.remove("seed"))?,
                         },
+                        ro,
                     ))
                 }
                 Some(Value::String(q)) if q == "get_peers" => {
@@ -160,7 +566,44 @@ impl KRPC {
                         DHTQuery::GetPeers {
                             id: id.unwrap().try_into()?,
                             info_hash: info_hash.unwrap().try_into()?,
+                            scrape: legacy_flag(a.remove("scrape"))?,
+                            noseed: legacy_flag(a.remove("noseed"))?,
+                        },
+                        ro,
+                    ))
+                }
+                Some(Value::String(q)) if q == "get" => {
+                    let id = a.remove("id");
+                    let target = a.remove("target");
+                    if id.is_none() || target.is_none() {
+                        return Err(Error::InvalidKRPC);
+                    }
+                    Ok(Self::Query(
+                        t.try_into()?,
+                        DHTQuery::Get {
+                            id: id.unwrap().try_into()?,
+                            target: target.unwrap().try_into()?,
                         },
+                        ro,
+                    ))
+                }
+                Some(Value::String(q)) if q == "put" => {
+                    let id = a.remove("id");
+                    let token = a.remove("token");
+                    let v = a.remove("v");
+                    if id.is_none() || token.is_none() || v.is_none() {
+                        return Err(Error::InvalidKRPC);
+                    }
+                    let mut value = Vec::new();
+                    v.unwrap().encode_to(&mut value)?;
+                    Ok(Self::Query(
+                        t.try_into()?,
+                        DHTQuery::Put {
+                            id: id.unwrap().try_into()?,
+                            token: token.unwrap().try_into()?,
+                            value,
+                        },
+                        ro,
                     ))
                 }
                 _ => Err(Error::InvalidKRPC),
@@ -170,9 +613,11 @@ impl KRPC {
     }
 
     fn decode_error(m: &mut BTreeMap<String, Value>) -> Result<Self> {
+        let t = m.remove("t").ok_or(Error::InvalidKRPC)?;
         if let Some(Value::List(ref mut list)) = m.remove("e") {
             if list.len() == 2 {
                 return Ok(Self::Error(
+                    t.try_into()?,
                     list.remove(0).try_into()?,
                     list.remove(0).try_into()?,
                 ));
@@ -189,6 +634,50 @@ impl KRPC {
             if id.is_none() {
                 return Err(Error::InvalidKRPC);
             }
+            // get
+            if let Some(v) = dict.remove("v") {
+                let token = dict.remove("token").ok_or(Error::InvalidKRPC)?;
+                let nodes = match dict.remove("nodes") {
+                    Some(nodes) => nodes.try_into()?,
+                    None => vec![],
+                };
+                let mut value = Vec::new();
+                v.encode_to(&mut value)?;
+                return Ok(Self::Response(
+                    t.try_into()?,
+                    DHTResponse::Get {
+                        id: id.unwrap().try_into()?,
+                        token: token.try_into()?,
+                        nodes,
+                        value,
+                    },
+                ));
+            }
+            // get_peers
+            if let Some(token) = dict.remove("token") {
+                let values = match dict.remove("values") {
+                    Some(values) => values.try_into()?,
+                    None => vec![],
+                };
+                let mut optional = |key| match dict.remove(key) {
+                    Some(v) => v.try_into(),
+                    None => Ok(vec![]),
+                };
+                let nodes = optional("nodes")?;
+                let seeds = optional("BFsd")?;
+                let peers = optional("BFpe")?;
+                return Ok(Self::Response(
+                    t.try_into()?,
+                    DHTResponse::GetPeers {
+                        id: id.unwrap().try_into()?,
+                        token: token.try_into()?,
+                        values,
+                        nodes,
+                        seeds,
+                        peers,
+                    },
+                ));
+            }
             // find_nodes
             if let Some(nodes) = dict.remove("nodes") {
                 return Ok(Self::Response(
@@ -199,19 +688,6 @@ impl KRPC {
                     },
                 ));
             }
-            // get_peers
-            if let Some(values) = dict.remove("values") {
-                if let Some(token) = dict.remove("token") {
-                    return Ok(Self::Response(
-                        t.try_into()?,
-                        DHTResponse::GetPeers {
-                            id: id.unwrap().try_into()?,
-                            token: token.try_into()?,
-                            values: values.try_into()?,
-                        },
-                    ));
-                }
-            }
 
             return Ok(Self::Response(
                 t.try_into()?,
@@ -223,3 +699,33 @@ impl KRPC {
         Err(Error::InvalidKRPC)
     }
 }
+
+/// A required string field of a borrowed message.
+fn bytes(v: Option<&ValueRef>) -> Result<Vec<u8>> {
+    let v = v.ok_or(Error::InvalidKRPC)?;
+    Ok(v.as_bytes().ok_or(Error::InvalidValue)?.to_vec())
+}
+
+/// A required field of any type, kept as its bencoding.
+fn encoded(v: Option<&ValueRef>) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    v.ok_or(Error::InvalidKRPC)?.encode_to(&mut buf)?;
+    Ok(buf)
+}
+
+/// A required integer field of a borrowed message.
+fn int<T: TryFrom<i64>>(v: Option<&ValueRef>) -> Result<T> {
+    let v = v.ok_or(Error::InvalidKRPC)?;
+    v.as_int()
+        .and_then(|i| T::try_from(i).ok())
+        .ok_or(Error::InvalidValue)
+}
+
+/// An optional 0/1 integer field of a borrowed message, false if absent.
+fn flag(v: Option<&ValueRef>) -> Result<bool> {
+    v.map_or(Ok(false), |v| int::<u8>(Some(v)).map(|i| i != 0))
+}
+
+fn legacy_flag(v: Option<Value>) -> Result<bool> {
+    v.map_or(Ok(false), |v| v.try_into().map(|i: u8| i != 0))
+}
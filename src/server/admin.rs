@@ -0,0 +1,47 @@
+use std::net::TcpListener;
+use std::sync::Arc;
+
+use super::Server;
+use crate::errors::Result;
+use crate::util::http;
+
+/// Admin requests served at once.
+const MAX_CONNECTIONS: usize = 8;
+/// The version of the Prometheus text format [`Stats::to_prometheus`]
+/// writes.
+///
+/// [`Stats::to_prometheus`]: super::metrics::Stats::to_prometheus
+const PROMETHEUS_TYPE: &str = "text/plain; version=0.0.4";
+
+impl Server {
+    /// Answer admin HTTP requests on `listener` until it fails. `GET
+    /// /metrics` returns [`stats`](Server::stats) for Prometheus to scrape
+    /// and `GET /table` the routing table as JSON, from
+    /// [`dump_table`](Server::dump_table). There is no authentication, so
+    /// `listener` should be on a local address.
+    pub fn serve_admin(self: &Arc<Self>, listener: TcpListener) -> Result<()> {
+        let server = Arc::clone(self);
+        http::serve(listener, MAX_CONNECTIONS, move |target, _| {
+            server.admin_response(target)
+        })
+    }
+
+    /// The status, content type and body answering `target`, the path and
+    /// query of an admin HTTP GET.
+    pub fn admin_response(&self, target: &str) -> http::Response {
+        let (path, _) = target.split_once('?').unwrap_or((target, ""));
+        match path {
+            "/metrics" => (
+                "200 OK",
+                PROMETHEUS_TYPE,
+                self.stats().to_prometheus().into_bytes(),
+            ),
+            "/table" => (
+                "200 OK",
+                "application/json",
+                self.dump_table().to_json().into_bytes(),
+            ),
+            _ => ("404 Not Found", "text/plain", vec![]),
+        }
+    }
+}
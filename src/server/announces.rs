@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::route_table::Key;
+use crate::errors::Result;
+use crate::util::random;
+
+/// The shortest time between announces of one torrent; each waits a
+/// random extra of up to the same again, so announces of many torrents
+/// spread out. Nodes keep announced peers for half an hour or so.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// How soon to try again after an announce fails.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How announcing one of our torrents is going.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TorrentStatus {
+    pub info_hash: Key,
+    pub port: u16,
+    /// When the last announce finished, whether or not it worked.
+    pub last_announce: Option<Instant>,
+    pub next_announce: Instant,
+    /// Nodes that accepted the last announce.
+    pub nodes: usize,
+    /// Peers the last announce's lookup found.
+    pub peers: usize,
+    /// Why the last announce failed, if it did.
+    pub error: Option<String>,
+}
+
+/// The torrents we announce ourselves for, and when each is next due.
+pub struct Announces {
+    interval: Duration,
+    torrents: Mutex<HashMap<Key, TorrentStatus>>,
+}
+
+impl Announces {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            torrents: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start announcing `info_hash` on `port`, right away. Adding a
+    /// torrent again changes its port and makes it due.
+    pub fn add(&self, info_hash: Key, port: u16) {
+        let mut torrents = self.torrents.lock().unwrap();
+        let status = torrents.entry(info_hash).or_insert(TorrentStatus {
+            info_hash,
+            port,
+            last_announce: None,
+            next_announce: Instant::now(),
+            nodes: 0,
+            peers: 0,
+            error: None,
+        });
+        status.port = port;
+        status.next_announce = Instant::now();
+    }
+
+    /// Stop announcing `info_hash`, returning whether it was announced.
+    pub fn remove(&self, info_hash: &Key) -> bool {
+        self.torrents.lock().unwrap().remove(info_hash).is_some()
+    }
+
+    pub fn status(&self, info_hash: &Key) -> Option<TorrentStatus> {
+        self.torrents.lock().unwrap().get(info_hash).cloned()
+    }
+
+    /// Every torrent, in no particular order.
+    pub fn all(&self) -> Vec<TorrentStatus> {
+        self.torrents.lock().unwrap().values().cloned().collect()
+    }
+
+    /// The torrents due for an announce, with their ports. They are not
+    /// due again until a full interval later, so a second caller does not
+    /// announce them too; [`done`](Announces::done) then reschedules them.
+    pub fn take_due(&self) -> Vec<(Key, u16)> {
+        let now = Instant::now();
+        let mut torrents = self.torrents.lock().unwrap();
+        torrents
+            .values_mut()
+            .filter(|status| status.next_announce <= now)
+            .map(|status| {
+                status.next_announce = now + self.interval;
+                (status.info_hash, status.port)
+            })
+            .collect()
+    }
+
+    /// Record how announcing `info_hash` went: the nodes that accepted it
+    /// and the peers found, or why it failed. Failed announces are tried
+    /// again after [`RETRY_INTERVAL`].
+    pub fn done(&self, info_hash: &Key, result: &Result<(usize, usize)>) {
+        let mut torrents = self.torrents.lock().unwrap();
+        // Removed while it was being announced.
+        let Some(status) = torrents.get_mut(info_hash) else {
+            return;
+        };
+        let now = Instant::now();
+        status.last_announce = Some(now);
+        match result {
+            Ok((nodes, peers)) => {
+                status.nodes = *nodes;
+                status.peers = *peers;
+                status.error = None;
+                status.next_announce = now + self.interval + self.jitter();
+            }
+            Err(e) => {
+                status.error = Some(e.to_string());
+                status.next_announce = now + RETRY_INTERVAL.min(self.interval);
+            }
+        }
+    }
+
+    /// When the next torrent is due, if there are any.
+    pub fn next_due(&self) -> Option<Instant> {
+        let torrents = self.torrents.lock().unwrap();
+        torrents.values().map(|status| status.next_announce).min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.torrents.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn jitter(&self) -> Duration {
+        let millis = self.interval.as_millis().max(1) as u64;
+        Duration::from_millis(random::u64() % millis)
+    }
+}
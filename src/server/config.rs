@@ -0,0 +1,435 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::rate_limit::{self, RateLimit};
+use super::route_table::{self, NodeLimits};
+use super::{announces, item_store, peer_store};
+use crate::errors::{Error, Result};
+
+/// Nodes returned by `find_node` and `get_peers`, how many closest nodes
+/// a lookup must hear from before it stops, and the bucket size.
+pub const DEFAULT_K: usize = route_table::BUCKET_SIZE;
+/// Queries a lookup keeps in flight at once, Kademlia's alpha.
+pub const DEFAULT_ALPHA: usize = 3;
+/// How long to wait for a node to answer a query.
+pub const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the token secret changes. Tokens made with the previous
+/// secret are still accepted, so a token lives one to two rotations.
+pub const DEFAULT_TOKEN_ROTATION: Duration = Duration::from_secs(5 * 60);
+pub const DEFAULT_MAX_TORRENTS: usize = 100_000;
+pub const DEFAULT_MAX_PEERS: usize = 2_000;
+pub const DEFAULT_MAX_ITEMS: usize = 10_000;
+/// Well-known routers to join the DHT through.
+pub const DEFAULT_BOOTSTRAP: &[&str] = &[
+    "router.bittorrent.com:6881",
+    "dht.transmissionbt.com:6881",
+    "router.utorrent.com:6881",
+];
+
+/// Everything about a [`Server`](super::Server) but LSD, built up from
+/// the defaults:
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use rdht::server::{config::ServerConfig, Server};
+/// let config = ServerConfig::default()
+///     .bind("0.0.0.0:6881")
+///     .alpha(8)
+///     .query_timeout(Duration::from_secs(1));
+/// let server = Server::with_config(config)?;
+/// # Ok::<(), rdht::errors::Error>(())
+/// ```
+///
+/// or read from a TOML file with [`from_file`](ServerConfig::from_file).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub(crate) bind: Option<String>,
+    pub(crate) trackers: Vec<String>,
+    pub(crate) k: usize,
+    pub(crate) alpha: usize,
+    pub(crate) node_limits: NodeLimits,
+    pub(crate) query_timeout: Duration,
+    pub(crate) token_rotation: Duration,
+    pub(crate) peer_ttl: Duration,
+    pub(crate) max_torrents: usize,
+    pub(crate) max_peers: usize,
+    pub(crate) item_ttl: Duration,
+    pub(crate) max_items: usize,
+    pub(crate) per_ip_limit: RateLimit,
+    pub(crate) global_limit: RateLimit,
+    pub(crate) ban_after: u32,
+    pub(crate) ban_duration: Duration,
+    pub(crate) read_only: bool,
+    pub(crate) blocklists: Vec<PathBuf>,
+    pub(crate) allow_private: bool,
+    pub(crate) bootstrap: Vec<String>,
+    pub(crate) state: Option<PathBuf>,
+    pub(crate) admin: Option<String>,
+    pub(crate) packet_trace: Option<PathBuf>,
+    pub(crate) announce_interval: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: None,
+            trackers: vec![],
+            k: DEFAULT_K,
+            alpha: DEFAULT_ALPHA,
+            node_limits: NodeLimits::default(),
+            query_timeout: DEFAULT_QUERY_TIMEOUT,
+            token_rotation: DEFAULT_TOKEN_ROTATION,
+            peer_ttl: peer_store::DEFAULT_TTL,
+            max_torrents: DEFAULT_MAX_TORRENTS,
+            max_peers: DEFAULT_MAX_PEERS,
+            item_ttl: item_store::DEFAULT_TTL,
+            max_items: DEFAULT_MAX_ITEMS,
+            per_ip_limit: rate_limit::DEFAULT_PER_IP,
+            global_limit: rate_limit::DEFAULT_GLOBAL,
+            ban_after: rate_limit::DEFAULT_BAN_AFTER,
+            ban_duration: rate_limit::DEFAULT_BAN_DURATION,
+            read_only: false,
+            blocklists: vec![],
+            allow_private: false,
+            bootstrap: DEFAULT_BOOTSTRAP.iter().map(|r| r.to_string()).collect(),
+            state: None,
+            admin: None,
+            packet_trace: None,
+            announce_interval: announces::DEFAULT_INTERVAL,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The address to bind the DHT socket to, any free port if unset.
+    pub fn bind(mut self, addr: &str) -> Self {
+        self.bind = Some(addr.to_string());
+        self
+    }
+
+    /// Tracker announce URLs for [`tracker_peers`](super::Server::tracker_peers).
+    pub fn trackers(mut self, trackers: Vec<String>) -> Self {
+        self.trackers = trackers;
+        self
+    }
+
+    /// Bucket size, and how many nodes lookups return and converge on.
+    pub fn k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Queries a lookup keeps in flight at once, at most `k`.
+    pub fn alpha(mut self, alpha: usize) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// How many nodes in the routing table may share an address or a
+    /// subnet. Nodes on one host, as in tests, need
+    /// [`NodeLimits::UNLIMITED`].
+    pub fn node_limits(mut self, limits: NodeLimits) -> Self {
+        self.node_limits = limits;
+        self
+    }
+
+    pub fn query_timeout(mut self, timeout: Duration) -> Self {
+        self.query_timeout = timeout;
+        self
+    }
+
+    pub fn token_rotation(mut self, rotation: Duration) -> Self {
+        self.token_rotation = rotation;
+        self
+    }
+
+    /// How long announced peers are kept, and how many torrents and peers
+    /// per torrent are stored.
+    pub fn peer_store(mut self, ttl: Duration, max_torrents: usize, max_peers: usize) -> Self {
+        self.peer_ttl = ttl;
+        self.max_torrents = max_torrents;
+        self.max_peers = max_peers;
+        self
+    }
+
+    /// How long immutable items are kept, and how many are stored.
+    pub fn item_store(mut self, ttl: Duration, max_items: usize) -> Self {
+        self.item_ttl = ttl;
+        self.max_items = max_items;
+        self
+    }
+
+    /// Limits on incoming queries from each source IP and from all of
+    /// them together.
+    pub fn rate_limits(mut self, per_ip: RateLimit, global: RateLimit) -> Self {
+        self.per_ip_limit = per_ip;
+        self.global_limit = global;
+        self
+    }
+
+    /// Ban a source for `duration` after `strikes` queries over its limit
+    /// or malformed packets.
+    pub fn ban(mut self, strikes: u32, duration: Duration) -> Self {
+        self.ban_after = strikes;
+        self.ban_duration = duration;
+        self
+    }
+
+    /// Take part only as a client (BEP 43): leave queries unanswered and
+    /// ask other nodes not to add us to their tables.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Never talk to the ranges in the blocklist at `path`, in any format
+    /// [`IpFilter::parse`](super::ip_filter::IpFilter::parse) reads.
+    pub fn blocklist(mut self, path: impl Into<PathBuf>) -> Self {
+        self.blocklists.push(path.into());
+        self
+    }
+
+    /// Talk to private, loopback and other addresses that do not belong
+    /// in the public DHT, as a DHT on a private network must.
+    pub fn allow_private(mut self, allow: bool) -> Self {
+        self.allow_private = allow;
+        self
+    }
+
+    /// The `host:port` nodes to join the DHT through.
+    pub fn bootstrap(mut self, nodes: Vec<String>) -> Self {
+        self.bootstrap = nodes;
+        self
+    }
+
+    /// Where the routing table is saved between runs.
+    pub fn state(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state = Some(path.into());
+        self
+    }
+
+    /// Serve metrics over HTTP on `addr`, which should be a local one.
+    /// See [`Server::serve_admin`](super::Server::serve_admin).
+    pub fn admin(mut self, addr: &str) -> Self {
+        self.admin = Some(addr.to_string());
+        self
+    }
+
+    /// Announce our torrents every `interval` to twice that, picked at
+    /// random each time.
+    pub fn announce_interval(mut self, interval: Duration) -> Self {
+        self.announce_interval = interval;
+        self
+    }
+
+    /// Log every KRPC message sent and received to `path` as JSON lines.
+    /// See [`PacketTrace`](super::packet_trace::PacketTrace).
+    pub fn packet_trace(mut self, path: impl Into<PathBuf>) -> Self {
+        self.packet_trace = Some(path.into());
+        self
+    }
+
+    pub fn bind_addr(&self) -> Option<&str> {
+        self.bind.as_deref()
+    }
+
+    pub fn bootstrap_nodes(&self) -> &[String] {
+        &self.bootstrap
+    }
+
+    pub fn state_path(&self) -> Option<&Path> {
+        self.state.as_deref()
+    }
+
+    pub fn admin_addr(&self) -> Option<&str> {
+        self.admin.as_deref()
+    }
+
+    /// The most torrents, and peers for each, the peer store keeps.
+    pub fn peer_limits(&self) -> (usize, usize) {
+        (self.max_torrents, self.max_peers)
+    }
+
+    pub fn packet_trace_path(&self) -> Option<&Path> {
+        self.packet_trace.as_deref()
+    }
+
+    /// Check the settings make sense together.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |msg: String| Err(Error::Config(msg));
+        if let Some(bind) = self.bind.as_ref().filter(|bind| !is_host_port(bind)) {
+            return invalid(format!("bind address {} is not host:port", bind));
+        }
+        if let Some(admin) = self.admin.as_ref().filter(|admin| !is_host_port(admin)) {
+            return invalid(format!("admin address {} is not host:port", admin));
+        }
+        if self.k == 0 {
+            return invalid("k must be at least 1".into());
+        }
+        if self.alpha == 0 || self.alpha > self.k {
+            return invalid(format!("alpha must be between 1 and k ({})", self.k));
+        }
+        let durations = [
+            ("query_timeout", self.query_timeout),
+            ("token_rotation", self.token_rotation),
+            ("peer_ttl", self.peer_ttl),
+            ("item_ttl", self.item_ttl),
+            ("ban_duration", self.ban_duration),
+            ("announce_interval", self.announce_interval),
+        ];
+        for (name, duration) in durations {
+            if duration.is_zero() {
+                return invalid(format!("{} must be positive", name));
+            }
+        }
+        let limits = [
+            ("max_torrents", self.max_torrents),
+            ("max_peers", self.max_peers),
+            ("max_items", self.max_items),
+            ("nodes_per_ip", self.node_limits.per_ip),
+            ("nodes_per_subnet", self.node_limits.per_subnet),
+            (
+                "nodes_per_bucket_subnet",
+                self.node_limits.per_bucket_subnet,
+            ),
+            ("per_ip_rate", self.per_ip_limit.rate as usize),
+            ("per_ip_burst", self.per_ip_limit.burst as usize),
+            ("global_rate", self.global_limit.rate as usize),
+            ("global_burst", self.global_limit.burst as usize),
+            ("ban_after", self.ban_after as usize),
+        ];
+        for (name, limit) in limits {
+            if limit == 0 {
+                return invalid(format!("{} must be at least 1", name));
+            }
+        }
+        if let Some(node) = self.bootstrap.iter().find(|node| !is_host_port(node)) {
+            return invalid(format!("bootstrap node {} is not host:port", node));
+        }
+        Ok(())
+    }
+
+    /// Read a TOML config file. See [`from_toml`](ServerConfig::from_toml).
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Parse and validate a TOML config. Every key is optional, durations
+    /// are in seconds:
+    ///
+    /// ```toml
+    /// bind = "0.0.0.0:6881"
+    /// trackers = ["udp://tracker.example.org:6969/announce"]
+    /// k = 8
+    /// alpha = 3
+    /// nodes_per_ip = 1
+    /// nodes_per_subnet = 4
+    /// nodes_per_bucket_subnet = 1
+    /// one_id_per_addr = true
+    /// query_timeout = 2
+    /// token_rotation = 300
+    /// peer_ttl = 1800
+    /// max_torrents = 100000
+    /// max_peers = 2000
+    /// item_ttl = 7200
+    /// max_items = 10000
+    /// per_ip_rate = 10
+    /// per_ip_burst = 50
+    /// global_rate = 2000
+    /// global_burst = 4000
+    /// ban_after = 20
+    /// ban_duration = 300
+    /// read_only = false
+    /// blocklists = ["ipfilter.dat"]
+    /// allow_private = false
+    /// bootstrap = ["router.bittorrent.com:6881"]
+    /// state = "dht.state"
+    /// admin = "127.0.0.1:9881"
+    /// packet_trace = "packets.jsonl"
+    /// announce_interval = 900
+    /// ```
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let table: toml::Table = s.parse().map_err(|e| Error::Config(format!("{}", e)))?;
+        let mut config = Self::default();
+        for (key, value) in &table {
+            let wrong = |kind: &str| Error::Config(format!("{} must be {}", key, kind));
+            let count = || {
+                value
+                    .as_integer()
+                    .and_then(|i| usize::try_from(i).ok())
+                    .ok_or_else(|| wrong("a non-negative integer"))
+            };
+            let rate = || {
+                value
+                    .as_integer()
+                    .and_then(|i| u32::try_from(i).ok())
+                    .ok_or_else(|| wrong("a non-negative 32-bit integer"))
+            };
+            let seconds = || match value {
+                toml::Value::Integer(i) => u64::try_from(*i).ok().map(Duration::from_secs),
+                toml::Value::Float(f) => Duration::try_from_secs_f64(*f).ok(),
+                _ => None,
+            };
+            let seconds = || seconds().ok_or_else(|| wrong("a non-negative number of seconds"));
+            let string = || value.as_str().ok_or_else(|| wrong("a string"));
+            let boolean = || value.as_bool().ok_or_else(|| wrong("a bool"));
+            let strings = || {
+                value
+                    .as_array()
+                    .and_then(|items| {
+                        items
+                            .iter()
+                            .map(|i| i.as_str().map(str::to_string))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(|| wrong("a list of strings"))
+            };
+            match key.as_str() {
+                "bind" => config.bind = Some(string()?.to_string()),
+                "k" => config.k = count()?,
+                "alpha" => config.alpha = count()?,
+                "nodes_per_ip" => config.node_limits.per_ip = count()?,
+                "nodes_per_subnet" => config.node_limits.per_subnet = count()?,
+                "nodes_per_bucket_subnet" => config.node_limits.per_bucket_subnet = count()?,
+                "one_id_per_addr" => config.node_limits.one_id_per_addr = boolean()?,
+                "query_timeout" => config.query_timeout = seconds()?,
+                "token_rotation" => config.token_rotation = seconds()?,
+                "peer_ttl" => config.peer_ttl = seconds()?,
+                "max_torrents" => config.max_torrents = count()?,
+                "max_peers" => config.max_peers = count()?,
+                "item_ttl" => config.item_ttl = seconds()?,
+                "max_items" => config.max_items = count()?,
+                "per_ip_rate" => config.per_ip_limit.rate = rate()?,
+                "per_ip_burst" => config.per_ip_limit.burst = rate()?,
+                "global_rate" => config.global_limit.rate = rate()?,
+                "global_burst" => config.global_limit.burst = rate()?,
+                "ban_after" => config.ban_after = rate()?,
+                "ban_duration" => config.ban_duration = seconds()?,
+                "read_only" => config.read_only = boolean()?,
+                "blocklists" => {
+                    config.blocklists = strings()?.into_iter().map(PathBuf::from).collect();
+                }
+                "allow_private" => config.allow_private = boolean()?,
+                "trackers" => config.trackers = strings()?,
+                "bootstrap" => config.bootstrap = strings()?,
+                "state" => config.state = Some(string()?.into()),
+                "admin" => config.admin = Some(string()?.to_string()),
+                "packet_trace" => config.packet_trace = Some(string()?.into()),
+                "announce_interval" => config.announce_interval = seconds()?,
+                _ => return Err(Error::Config(format!("unknown key {}", key))),
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Whether `addr` looks like `host:port`, leaving it to the resolver to
+/// find out whether the host exists.
+fn is_host_port(addr: &str) -> bool {
+    match addr.rsplit_once(':') {
+        Some((host, port)) => !host.is_empty() && port.parse::<u16>().is_ok(),
+        None => false,
+    }
+}
@@ -0,0 +1,76 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::route_table::{Key, Node};
+
+/// Events a subscriber may fall behind by before further ones are dropped
+/// for it.
+pub const CAPACITY: usize = 1024;
+
+/// Something the DHT learned, from [`Server::subscribe`].
+///
+/// [`Server::subscribe`]: super::Server::subscribe
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A node joined the routing table.
+    NodeAdded(Node),
+    /// A node was dropped from the routing table for not responding.
+    NodeRemoved(Node),
+    /// A peer announced itself to us for `info_hash`.
+    PeerAnnounced {
+        info_hash: Key,
+        peer: SocketAddr,
+        seed: bool,
+    },
+    /// An iterative lookup of `target` finished, with answers from
+    /// `answered` nodes.
+    LookupCompleted {
+        method: &'static str,
+        target: Key,
+        answered: usize,
+        elapsed: Duration,
+    },
+    /// Enough of the nodes we query agree our address is now `ip`.
+    ExternalIpChanged(IpAddr),
+    /// [`Server::bootstrap`](super::Server::bootstrap) finished with `nodes`
+    /// in the routing table.
+    BootstrapFinished { nodes: usize },
+}
+
+/// The senders of every subscriber, and how many events were dropped for
+/// subscribers that fell behind.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    senders: Mutex<Vec<SyncSender<Event>>>,
+    dropped: AtomicU64,
+}
+
+impl Subscribers {
+    /// A new subscriber, with room for [`CAPACITY`] events.
+    pub(crate) fn add(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+        self.senders.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Send `event` to every subscriber without waiting, dropping it for
+    /// those that are full and forgetting those that hung up.
+    pub(crate) fn emit(&self, event: Event) {
+        let mut senders = self.senders.lock().unwrap();
+        senders.retain(|sender| match sender.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
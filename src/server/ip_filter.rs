@@ -0,0 +1,254 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+use crate::errors::{Error, Result};
+
+/// Address ranges the DHT must not talk to: blocklists, and optionally
+/// the private and reserved addresses that have no business being in the
+/// public DHT.
+#[derive(Debug, Default, Clone)]
+pub struct IpFilter {
+    /// Sorted, non-overlapping, inclusive ranges.
+    v4: Vec<(u32, u32)>,
+    v6: Vec<(u128, u128)>,
+    block_bogons: bool,
+}
+
+impl IpFilter {
+    /// A filter blocking nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also block the addresses [`is_bogon`] matches.
+    pub fn block_bogons(mut self, block: bool) -> Self {
+        self.block_bogons = block;
+        self
+    }
+
+    /// Block `start` to `end` inclusive, which must be the same family.
+    pub fn block(&mut self, start: IpAddr, end: IpAddr) -> Result<()> {
+        self.push(start, end)?;
+        self.normalize();
+        Ok(())
+    }
+
+    /// Block a range written as `addr/prefix`, or a single address.
+    pub fn block_cidr(&mut self, cidr: &str) -> Result<()> {
+        let (start, end) = parse_cidr(cidr)?;
+        self.block(start, end)
+    }
+
+    /// Block the ranges in a blocklist file. See
+    /// [`parse`](IpFilter::parse) for the formats understood.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        // Lists often carry latin-1 descriptions.
+        let text = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+        self.parse(&text)
+    }
+
+    /// Block the ranges in a blocklist, one per line, returning how many
+    /// there were. Lines may be CIDR ranges or single addresses, eMule
+    /// `ipfilter.dat` lines (`first - last , level , description`, where
+    /// levels above 127 are allowed), or PeerGuardian `.p2p` lines
+    /// (`description:first-last`). Blank lines and lines starting with
+    /// `#` or `//` are skipped.
+    pub fn parse(&mut self, text: &str) -> Result<usize> {
+        let mut count = 0;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+            let invalid = || Error::InvalidNetAddr(format!("line {}: {}", n + 1, line));
+            let Some((start, end)) = parse_line(line).map_err(|_| invalid())? else {
+                continue;
+            };
+            self.push(start, end).map_err(|_| invalid())?;
+            count += 1;
+        }
+        self.normalize();
+        Ok(count)
+    }
+
+    /// Whether `ip` is in a blocked range, or a bogon when those are
+    /// blocked.
+    pub fn is_blocked(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        if self.block_bogons && is_bogon(ip) {
+            return true;
+        }
+        match ip {
+            IpAddr::V4(ip) => contains(&self.v4, u32::from(ip)),
+            IpAddr::V6(ip) => contains(&self.v6, u128::from(ip)),
+        }
+    }
+
+    /// Number of blocked ranges, after merging overlapping ones.
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&mut self, start: IpAddr, end: IpAddr) -> Result<()> {
+        match (start.to_canonical(), end.to_canonical()) {
+            (IpAddr::V4(s), IpAddr::V4(e)) if s <= e => self.v4.push((s.into(), e.into())),
+            (IpAddr::V6(s), IpAddr::V6(e)) if s <= e => self.v6.push((s.into(), e.into())),
+            _ => {
+                return Err(Error::InvalidNetAddr(format!(
+                    "invalid range {} - {}",
+                    start, end
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn normalize(&mut self) {
+        merge(&mut self.v4);
+        merge(&mut self.v6);
+    }
+}
+
+/// Addresses that cannot be a node on the public internet: private,
+/// loopback, link-local, shared, documentation, multicast and reserved
+/// ranges.
+pub fn is_bogon(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_unspecified()
+                || ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || ip.is_broadcast()
+                || a == 0
+                || a >= 240
+                // Carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking, 198.18.0.0/15.
+                || (a == 198 && (b == 18 || b == 19))
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local, fc00::/7.
+                || first & 0xfe00 == 0xfc00
+                // Link-local, fe80::/10.
+                || first & 0xffc0 == 0xfe80
+                // Documentation, 2001:db8::/32.
+                || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+        }
+    }
+}
+
+fn contains<T: Ord + Copy>(ranges: &[(T, T)], ip: T) -> bool {
+    // The last range starting at or before `ip` is the only candidate.
+    let i = ranges.partition_point(|(start, _)| *start <= ip);
+    i > 0 && ip <= ranges[i - 1].1
+}
+
+fn merge<T: Ord + Copy + Into<u128>>(ranges: &mut Vec<(T, T)>) {
+    ranges.sort_unstable();
+    let mut merged: Vec<(T, T)> = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            // Overlapping or adjacent.
+            Some(last) if start.into() <= last.1.into().saturating_add(1) => {
+                last.1 = last.1.max(end);
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    *ranges = merged;
+}
+
+/// The range on one blocklist line, or `None` for an eMule range that is
+/// allowed.
+fn parse_line(line: &str) -> Result<Option<(IpAddr, IpAddr)>> {
+    // PeerGuardian: the description may itself contain colons, but the
+    // range never does unless it is IPv6, which the format does not have.
+    if let Some((_, range)) = line.rsplit_once(':') {
+        if let Some((start, end)) = range.split_once('-') {
+            if let (Ok(start), Ok(end)) = (parse_ip(start), parse_ip(end)) {
+                return Ok(Some((start, end)));
+            }
+        }
+    }
+    // eMule: `first - last , level , description`.
+    let mut fields = line.split(',');
+    let range = fields.next().unwrap_or_default();
+    if let Some((start, end)) = range.split_once('-') {
+        if let Some(level) = fields.next() {
+            let level: u32 = level.trim().parse()?;
+            if level > 127 {
+                return Ok(None);
+            }
+        }
+        return Ok(Some((parse_ip(start)?, parse_ip(end)?)));
+    }
+    parse_cidr(line).map(Some)
+}
+
+fn parse_cidr(cidr: &str) -> Result<(IpAddr, IpAddr)> {
+    let invalid = || Error::InvalidNetAddr(cidr.to_string());
+    let (ip, prefix) = match cidr.split_once('/') {
+        Some((ip, prefix)) => (
+            parse_ip(ip)?,
+            Some(prefix.parse::<u32>().map_err(|_| invalid())?),
+        ),
+        None => (parse_ip(cidr)?, None),
+    };
+    match ip {
+        IpAddr::V4(ip) => {
+            let prefix = prefix.unwrap_or(32);
+            if prefix > 32 {
+                return Err(invalid());
+            }
+            let mask = u32::MAX.checked_shr(prefix).unwrap_or(0);
+            let start = u32::from(ip) & !mask;
+            Ok((
+                Ipv4Addr::from(start).into(),
+                Ipv4Addr::from(start | mask).into(),
+            ))
+        }
+        IpAddr::V6(ip) => {
+            let prefix = prefix.unwrap_or(128);
+            if prefix > 128 {
+                return Err(invalid());
+            }
+            let mask = u128::MAX.checked_shr(prefix).unwrap_or(0);
+            let start = u128::from(ip) & !mask;
+            Ok((
+                Ipv6Addr::from(start).into(),
+                Ipv6Addr::from(start | mask).into(),
+            ))
+        }
+    }
+}
+
+/// Parse an address, allowing the zero-padded IPv4 octets eMule lists
+/// use, such as `001.002.003.004`.
+fn parse_ip(s: &str) -> Result<IpAddr> {
+    let s = s.trim();
+    if let Ok(ip) = s.parse() {
+        return Ok(ip);
+    }
+    let octets: Vec<u8> = s
+        .split('.')
+        .map(|octet| octet.parse::<u8>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| Error::InvalidNetAddr(s.to_string()))?;
+    let octets: [u8; 4] = octets
+        .try_into()
+        .map_err(|_| Error::InvalidNetAddr(s.to_string()))?;
+    Ok(Ipv4Addr::from(octets).into())
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sha1::{Digest, Sha1};
+
+use super::route_table::Key;
+use crate::errors::{Error, Result};
+use crate::util::bencode;
+
+/// How long an item is kept without being put again (BEP 44).
+pub const DEFAULT_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+/// Largest bencoded item BEP 44 allows.
+pub const MAX_ITEM_LENGTH: usize = 1000;
+
+/// Immutable items (BEP 44) stored on this node, keyed by the SHA-1 of
+/// their bencoding.
+pub struct ItemStore {
+    ttl: Duration,
+    max_items: usize,
+    items: Mutex<HashMap<Key, (Vec<u8>, Instant)>>,
+}
+
+impl Default for ItemStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+impl ItemStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_items: usize::MAX,
+            items: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Store at most `max_items` items. New items past the limit are
+    /// refused until expiry makes room.
+    pub fn with_limit(mut self, max_items: usize) -> Self {
+        self.max_items = max_items;
+        self
+    }
+
+    /// The key an item is stored and looked up under.
+    pub fn target(value: &[u8]) -> Key {
+        let hash: [u8; 20] = Sha1::digest(value).into();
+        hash.into()
+    }
+
+    /// Store the bencoded `value`, returning its target.
+    pub fn put(&self, value: Vec<u8>) -> Result<Key> {
+        let target = validate_item(&value)?;
+        let mut items = self.items.lock().unwrap();
+        if !items.contains_key(&target) && items.len() >= self.max_items {
+            return Err(Error::Dht(format!(
+                "item store is full with {} items",
+                items.len()
+            )));
+        }
+        items.insert(target, (value, Instant::now()));
+        Ok(target)
+    }
+
+    pub fn get(&self, target: &Key) -> Option<Vec<u8>> {
+        let items = self.items.lock().unwrap();
+        let (value, stored) = items.get(target)?;
+        (stored.elapsed() < self.ttl).then(|| value.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop items that have not been put again within the ttl.
+    pub fn expire(&self) {
+        self.items
+            .lock()
+            .unwrap()
+            .retain(|_, (_, stored)| stored.elapsed() < self.ttl);
+    }
+}
+
+/// Check that `value` is an item nodes will store, strictly bencoded and
+/// no larger than BEP 44 allows, returning its target.
+pub fn validate_item(value: &[u8]) -> Result<Key> {
+    if value.len() > MAX_ITEM_LENGTH {
+        return Err(Error::Dht(format!(
+            "item of {} bytes is larger than {}",
+            value.len(),
+            MAX_ITEM_LENGTH
+        )));
+    }
+    bencode::decode_strict(value)?;
+    Ok(ItemStore::target(value))
+}
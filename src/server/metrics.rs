@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::rate_limit::DropStats;
+
+/// Upper bounds of the lookup latency buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// A latency histogram over [`LATENCY_BUCKETS`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Histogram {
+    /// Observations per bucket, not cumulative. The last one counts
+    /// those above every bound.
+    pub counts: [u64; LATENCY_BUCKETS.len() + 1],
+    pub sum: Duration,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let i = LATENCY_BUCKETS.partition_point(|bound| *bound < secs);
+        self.counts[i] += 1;
+        self.sum += latency;
+        self.count += 1;
+    }
+}
+
+/// Counters for the traffic of one server.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    queries_in: Mutex<BTreeMap<&'static str, u64>>,
+    queries_out: Mutex<BTreeMap<&'static str, u64>>,
+    responses_in: AtomicU64,
+    responses_out: AtomicU64,
+    errors_in: Mutex<BTreeMap<&'static str, u64>>,
+    timeouts: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    lookups: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+impl Metrics {
+    pub(crate) fn query_in(&self, method: &'static str) {
+        *self.queries_in.lock().unwrap().entry(method).or_default() += 1;
+    }
+
+    pub(crate) fn query_out(&self, method: &'static str) {
+        *self.queries_out.lock().unwrap().entry(method).or_default() += 1;
+    }
+
+    pub(crate) fn response_in(&self) {
+        self.responses_in.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn response_out(&self) {
+        self.responses_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count an error answering one of our queries. Codes outside the
+    /// four BEP 5 defines are counted together.
+    pub(crate) fn error_in(&self, code: u64) {
+        let label = match code {
+            201 => "201",
+            202 => "202",
+            203 => "203",
+            204 => "204",
+            _ => "other",
+        };
+        *self.errors_in.lock().unwrap().entry(label).or_default() += 1;
+    }
+
+    pub(crate) fn timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_in(&self, len: usize) {
+        self.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_out(&self, len: usize) {
+        self.bytes_out.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn lookup(&self, method: &'static str, latency: Duration) {
+        let mut lookups = self.lookups.lock().unwrap();
+        lookups.entry(method).or_default().observe(latency);
+    }
+
+    /// The counters, for the rest of a [`Stats`] to be filled in.
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            queries_in: self.queries_in.lock().unwrap().clone(),
+            queries_out: self.queries_out.lock().unwrap().clone(),
+            responses_in: self.responses_in.load(Ordering::Relaxed),
+            responses_out: self.responses_out.load(Ordering::Relaxed),
+            errors_in: self.errors_in.lock().unwrap().clone(),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            lookups: self.lookups.lock().unwrap().clone(),
+            ..Stats::default()
+        }
+    }
+}
+
+/// A snapshot of what a server has been doing, from
+/// [`Server::stats`](super::Server::stats).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// Queries received, by method.
+    pub queries_in: BTreeMap<&'static str, u64>,
+    /// Queries sent, by method.
+    pub queries_out: BTreeMap<&'static str, u64>,
+    pub responses_in: u64,
+    pub responses_out: u64,
+    /// Error messages answering our queries, by code, with codes outside
+    /// 201-204 as `other`.
+    pub errors_in: BTreeMap<&'static str, u64>,
+    /// Our queries that got no response in time.
+    pub timeouts: u64,
+    /// Bytes of KRPC received and sent, without UDP and IP headers.
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Routing table nodes by the depth of their bucket.
+    pub nodes: BTreeMap<usize, usize>,
+    pub torrents: usize,
+    pub peers: usize,
+    pub items: usize,
+    /// Lookup latency, by the method of the lookup's queries.
+    pub lookups: BTreeMap<&'static str, Histogram>,
+    pub drops: DropStats,
+    /// Events not sent to subscribers that had fallen behind.
+    pub events_dropped: u64,
+}
+
+impl Stats {
+    /// The stats in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            let _ = writeln!(out, "# HELP rdht_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE rdht_{} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "rdht_{}{} {}", name, labels, value);
+            }
+        };
+        let by = |label: &str, counts: &BTreeMap<&'static str, u64>| {
+            counts
+                .iter()
+                .map(|(key, n)| (format!("{{{}=\"{}\"}}", label, key), n.to_string()))
+                .collect()
+        };
+        let one = |value: String| vec![(String::new(), value)];
+
+        metric(
+            "queries_received_total",
+            "counter",
+            "Queries received by method.",
+            by("method", &self.queries_in),
+        );
+        metric(
+            "queries_sent_total",
+            "counter",
+            "Queries sent by method.",
+            by("method", &self.queries_out),
+        );
+        metric(
+            "responses_received_total",
+            "counter",
+            "Responses received.",
+            one(self.responses_in.to_string()),
+        );
+        metric(
+            "responses_sent_total",
+            "counter",
+            "Responses sent.",
+            one(self.responses_out.to_string()),
+        );
+        let errors = self
+            .errors_in
+            .iter()
+            .map(|(code, n)| (format!("{{code=\"{}\"}}", code), n.to_string()))
+            .collect();
+        metric(
+            "errors_received_total",
+            "counter",
+            "Error messages received by code.",
+            errors,
+        );
+        metric(
+            "query_timeouts_total",
+            "counter",
+            "Queries sent that timed out.",
+            one(self.timeouts.to_string()),
+        );
+        metric(
+            "received_bytes_total",
+            "counter",
+            "KRPC bytes received.",
+            one(self.bytes_in.to_string()),
+        );
+        metric(
+            "sent_bytes_total",
+            "counter",
+            "KRPC bytes sent.",
+            one(self.bytes_out.to_string()),
+        );
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(depth, n)| (format!("{{depth=\"{}\"}}", depth), n.to_string()))
+            .collect();
+        metric(
+            "routing_table_nodes",
+            "gauge",
+            "Routing table nodes by bucket depth.",
+            nodes,
+        );
+        metric(
+            "torrents",
+            "gauge",
+            "Torrents with announced peers.",
+            one(self.torrents.to_string()),
+        );
+        metric(
+            "peers",
+            "gauge",
+            "Announced peers stored.",
+            one(self.peers.to_string()),
+        );
+        metric(
+            "items",
+            "gauge",
+            "Immutable items stored.",
+            one(self.items.to_string()),
+        );
+        let drops = [
+            ("rate_limited", self.drops.rate_limited),
+            ("global_limited", self.drops.global_limited),
+            ("banned", self.drops.banned),
+            ("malformed", self.drops.malformed),
+        ];
+        let drops = drops
+            .iter()
+            .map(|(reason, n)| (format!("{{reason=\"{}\"}}", reason), n.to_string()))
+            .collect();
+        metric(
+            "dropped_packets_total",
+            "counter",
+            "Packets dropped by reason.",
+            drops,
+        );
+        metric(
+            "bans_total",
+            "counter",
+            "Sources banned.",
+            one(self.drops.bans.to_string()),
+        );
+        metric(
+            "dropped_events_total",
+            "counter",
+            "Events dropped for subscribers that fell behind.",
+            one(self.events_dropped.to_string()),
+        );
+
+        let mut samples = vec![];
+        for (method, histogram) in &self.lookups {
+            let mut cumulative = 0;
+            let bounds = LATENCY_BUCKETS
+                .iter()
+                .map(f64::to_string)
+                .chain(["+Inf".to_string()]);
+            for (bound, count) in bounds.zip(histogram.counts) {
+                cumulative += count;
+                samples.push((
+                    format!("_bucket{{method=\"{}\",le=\"{}\"}}", method, bound),
+                    cumulative.to_string(),
+                ));
+            }
+            samples.push((
+                format!("_sum{{method=\"{}\"}}", method),
+                histogram.sum.as_secs_f64().to_string(),
+            ));
+            samples.push((
+                format!("_count{{method=\"{}\"}}", method),
+                histogram.count.to_string(),
+            ));
+        }
+        metric(
+            "lookup_duration_seconds",
+            "histogram",
+            "Lookup latency by query method.",
+            samples,
+        );
+        out
+    }
+}
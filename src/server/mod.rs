@@ -1,44 +1,1019 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use self::route_table::RouteTable;
-use crate::errors::Result;
+use sha1::{Digest, Sha1};
+use tracing::{debug, debug_span, info, info_span, trace, Span};
 
+use self::announces::{Announces, TorrentStatus};
+use self::config::ServerConfig;
+use self::events::{Event, Subscribers};
+use self::ip_filter::IpFilter;
+use self::item_store::{validate_item, ItemStore};
+use self::metrics::{Metrics, Stats};
+use self::packet_trace::{Direction, PacketTrace};
+use self::peer_store::PeerStore;
+use self::rate_limit::RateLimiter;
+use self::route_table::{Key, Node, RouteTable, TableDump, GOOD_FOR};
+use crate::errors::{Error, Result};
+use crate::lsd::Lsd;
+use crate::protocl::{DHTQuery, DHTResponse, KRPC, PROTOCOL_ERROR};
+use crate::tracker::{self, AnnounceRequest, ScrapeStats};
+use crate::util::bencode::{self, Encoder, ValueRef};
+use crate::util::bloom::BloomFilter;
+use crate::util::{compact, hex, random};
+
+mod admin;
+pub mod announces;
+pub mod config;
+pub mod events;
+pub mod ip_filter;
+pub mod item_store;
+pub mod metrics;
+pub mod packet_trace;
+pub mod peer_store;
+pub mod rate_limit;
 pub mod route_table;
 
+/// Peers returned by one `get_peers`, keeping the reply within one packet.
+const MAX_VALUES: usize = 50;
+const TOKEN_LENGTH: usize = 8;
+/// How long `run` blocks on the socket before doing housekeeping.
+const TICK: Duration = Duration::from_secs(1);
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Nodes that must report the same new external address before we
+/// believe it.
+const MIN_IP_VOTES: usize = 3;
+/// Voters remembered before the votes start over, bounding the memory
+/// nodes reporting nonsense can take.
+const MAX_IP_VOTERS: usize = 64;
+
 pub struct Server {
-    table: RouteTable,
+    config: ServerConfig,
+    socket: UdpSocket,
+    table: Mutex<RouteTable>,
+    peers: Arc<PeerStore>,
+    items: ItemStore,
     trackers: HashSet<String>,
+    tokens: Mutex<Tokens>,
+    limiter: RateLimiter,
+    filter: Arc<IpFilter>,
+    metrics: Metrics,
+    packet_trace: Option<PacketTrace>,
+    subscribers: Subscribers,
+    /// Our own torrents, announced again and again.
+    announces: Announces,
+    external_ip: Mutex<ExternalIp>,
+    /// Our queries waiting for a response, by transaction id.
+    pending: Mutex<HashMap<Vec<u8>, Pending>>,
+    next_transaction: AtomicU16,
+    lsd: Option<Arc<Lsd>>,
+}
+
+/// Where a query went, which its response must come from, and who is
+/// waiting for it.
+type Pending = (SocketAddr, Sender<DHTResponse>);
+
+/// Secrets for `get_peers` tokens (BEP 5).
+struct Tokens {
+    current: [u8; 16],
+    previous: [u8; 16],
+    rotated: Instant,
+}
+
+impl Tokens {
+    fn new() -> Self {
+        Self {
+            current: random::bytes(),
+            previous: random::bytes(),
+            rotated: Instant::now(),
+        }
+    }
+
+    fn rotate_if_due(&mut self, every: Duration) {
+        if self.rotated.elapsed() >= every {
+            self.previous = self.current;
+            self.current = random::bytes();
+            self.rotated = Instant::now();
+        }
+    }
+
+    fn make(secret: &[u8; 16], ip: IpAddr) -> Vec<u8> {
+        let mut hasher = Sha1::new();
+        hasher.update(secret);
+        match ip {
+            IpAddr::V4(ip) => hasher.update(ip.octets()),
+            IpAddr::V6(ip) => hasher.update(ip.octets()),
+        }
+        hasher.finalize()[..TOKEN_LENGTH].to_vec()
+    }
+
+    fn token(&self, ip: IpAddr) -> Vec<u8> {
+        Self::make(&self.current, ip)
+    }
+
+    fn is_valid(&self, token: &[u8], ip: IpAddr) -> bool {
+        token == Self::make(&self.current, ip) || token == Self::make(&self.previous, ip)
+    }
+}
+
+/// Votes from the nodes we query on our external address, which they
+/// report in the `ip` key of their responses (BEP 42).
+#[derive(Default)]
+struct ExternalIp {
+    current: Option<IpAddr>,
+    /// Who voted for each address other than the current one.
+    votes: HashMap<IpAddr, HashSet<IpAddr>>,
+}
+
+impl ExternalIp {
+    /// Count `voter` saying our address is `ip`, returning `ip` if that
+    /// makes it our new address.
+    fn vote(&mut self, voter: IpAddr, ip: IpAddr) -> Option<IpAddr> {
+        if self.current == Some(ip) {
+            return None;
+        }
+        if self.votes.values().map(HashSet::len).sum::<usize>() >= MAX_IP_VOTERS {
+            self.votes.clear();
+        }
+        let voters = self.votes.entry(ip).or_default();
+        voters.insert(voter);
+        if voters.len() < MIN_IP_VOTES {
+            return None;
+        }
+        self.current = Some(ip);
+        self.votes.clear();
+        Some(ip)
+    }
 }
 
 impl Server {
+    /// Bind the DHT socket to `addr`, with the default config otherwise.
     pub fn new(addr: &str, trackers: Vec<String>) -> Result<Self> {
-        Ok(Server {
-            table: RouteTable::new(addr)?,
-            trackers: trackers.into_iter().collect(),
-        })
+        Self::with_config(ServerConfig::default().bind(addr).trackers(trackers))
+    }
+
+    /// Check `config`, load its blocklists, bind the DHT socket, open the
+    /// packet trace, and restore the routing table from the state file if
+    /// the config names one that exists.
+    pub fn with_config(config: ServerConfig) -> Result<Self> {
+        config.validate()?;
+        let mut filter = IpFilter::new().block_bogons(!config.allow_private);
+        for path in &config.blocklists {
+            filter.load(path)?;
+        }
+        let filter = Arc::new(filter);
+        let socket = UdpSocket::bind(config.bind_addr().unwrap_or("0.0.0.0:0"))?;
+        let local = socket.local_addr()?.to_string();
+        let server = Server {
+            socket,
+            table: Mutex::new(
+                RouteTable::new(&local)?
+                    .with_bucket_size(config.k)
+                    .with_filter(Arc::clone(&filter))
+                    .with_limits(config.node_limits),
+            ),
+            peers: Arc::new(
+                PeerStore::new(config.peer_ttl).with_limits(config.max_torrents, config.max_peers),
+            ),
+            items: ItemStore::new(config.item_ttl).with_limit(config.max_items),
+            trackers: config.trackers.iter().cloned().collect(),
+            tokens: Mutex::new(Tokens::new()),
+            limiter: RateLimiter::new(
+                config.per_ip_limit,
+                config.global_limit,
+                config.ban_after,
+                config.ban_duration,
+            ),
+            filter,
+            metrics: Metrics::default(),
+            subscribers: Subscribers::default(),
+            announces: Announces::new(config.announce_interval),
+            external_ip: Mutex::new(ExternalIp::default()),
+            packet_trace: config
+                .packet_trace_path()
+                .map(PacketTrace::open)
+                .transpose()?,
+            pending: Mutex::new(HashMap::new()),
+            next_transaction: AtomicU16::new(random::u64() as u16),
+            lsd: None,
+            config,
+        };
+        if let Some(path) = server.config.state_path().filter(|path| path.exists()) {
+            server.load_state(path)?;
+        }
+        Ok(server)
+    }
+
+    /// Also report peers found by local service discovery from
+    /// [`get_peers`](Server::get_peers). The caller runs `lsd`.
+    pub fn with_lsd(mut self, lsd: Arc<Lsd>) -> Self {
+        self.lsd = Some(lsd);
+        self
     }
 
+    /// The LSD member from [`with_lsd`](Server::with_lsd), if any.
+    pub fn lsd(&self) -> Option<&Arc<Lsd>> {
+        self.lsd.as_ref()
+    }
+
+    pub fn id(&self) -> Key {
+        *self.table.lock().unwrap().id()
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// The rate limiter, to see how much it dropped or to change bans.
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    /// What the server has been doing since it started, and what it
+    /// holds now.
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.metrics.snapshot();
+        stats.nodes = self.table.lock().unwrap().depths();
+        stats.torrents = self.peers.torrents();
+        stats.peers = self.peers.peer_count();
+        stats.items = self.items.len();
+        stats.drops = self.limiter.stats();
+        stats.events_dropped = self.subscribers.dropped();
+        stats
+    }
+
+    /// Events from now on, for as long as the receiver is kept. Events are
+    /// not sent for the nodes [`load_state`](Server::load_state) brings.
+    /// A receiver more than [`events::CAPACITY`] events behind misses the
+    /// ones after, which [`Stats::events_dropped`] counts.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.subscribers.add()
+    }
+
+    /// Our address as most recently agreed on by the nodes we query, if
+    /// they have agreed on one.
+    pub fn external_ip(&self) -> Option<IpAddr> {
+        self.external_ip.lock().unwrap().current
+    }
+
+    /// The peers announced to this node, for a tracker to share.
+    pub fn peer_store(&self) -> Arc<PeerStore> {
+        Arc::clone(&self.peers)
+    }
+
+    /// Answer queries, and deliver responses to our own, until the socket
+    /// fails.
     pub fn run(&self) -> Result<()> {
-        todo!()
+        self.socket.set_read_timeout(Some(TICK))?;
+        let mut buf = vec![0; 65_535];
+        let mut expired = Instant::now();
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) => {
+                    if let Some(reply) = self.handle(&buf[..len], from) {
+                        // A node that went away is not a reason to stop.
+                        if self.socket.send_to(&reply, from).is_ok() {
+                            self.metrics.bytes_out(reply.len());
+                        }
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.into()),
+            }
+            self.tokens
+                .lock()
+                .unwrap()
+                .rotate_if_due(self.config.token_rotation);
+            if expired.elapsed() >= EXPIRY_INTERVAL {
+                self.peers.expire();
+                self.items.expire();
+                self.limiter.expire();
+                expired = Instant::now();
+            }
+        }
+    }
+
+    /// The encoded reply to one datagram, if it is a query. A malformed
+    /// query is answered with a protocol error and counts as a strike
+    /// against its source. A read-only node drops every query, and any
+    /// node drops packets from blocked or banned sources and queries over
+    /// the rate limits.
+    pub fn handle(&self, packet: &[u8], from: SocketAddr) -> Option<Vec<u8>> {
+        self.metrics.bytes_in(packet.len());
+        if self.filter.is_blocked(from.ip()) || self.limiter.is_banned(from.ip()) {
+            trace!(%from, "dropped packet from blocked or banned source");
+            return None;
+        }
+        let Ok(message) = KRPC::decode_bytes(packet) else {
+            self.limiter.malformed(from.ip());
+            return self.protocol_error(packet, from);
+        };
+        self.trace_packet(Direction::In, from, packet);
+        match message {
+            KRPC::Query(..) if self.config.read_only => None,
+            KRPC::Query(..) if !self.limiter.allow(from.ip()) => {
+                trace!(%from, "dropped query over the rate limit");
+                None
+            }
+            KRPC::Query(t, query, read_only) => {
+                let method = query.method();
+                self.metrics.query_in(method);
+                let _span = debug_span!("incoming", t = %hex::encode(&t), %from, method).entered();
+                let Some(response) = self.handle_query(query, from, read_only) else {
+                    debug!("query not answered");
+                    return None;
+                };
+                let mut buf = Vec::new();
+                KRPC::Response(t, response).encode_to(&mut buf).ok()?;
+                self.metrics.response_out();
+                self.trace_packet(Direction::Out, from, &buf);
+                trace!("answered");
+                Some(buf)
+            }
+            KRPC::Response(t, response) => {
+                self.metrics.response_in();
+                self.handle_response(t, response, from, reported_ip(packet));
+                None
+            }
+            KRPC::Error(t, code, msg) => {
+                let pending = self.pending.lock().unwrap();
+                if !matches!(pending.get(&t), Some((addr, _)) if *addr == from) {
+                    trace!(t = %hex::encode(&t), %from, "dropped unexpected error");
+                    return None;
+                }
+                debug!(%from, code, msg, "error received");
+                self.metrics.error_in(code);
+                None
+            }
+        }
+    }
+
+    /// The error answering a malformed packet, if it is still recognisably
+    /// a query and would have been answered.
+    fn protocol_error(&self, packet: &[u8], from: SocketAddr) -> Option<Vec<u8>> {
+        let Some(t) = KRPC::query_transaction(packet) else {
+            trace!(%from, "dropped malformed packet");
+            return None;
+        };
+        // The strike may just have banned the source.
+        if self.config.read_only
+            || self.limiter.is_banned(from.ip())
+            || !self.limiter.allow(from.ip())
+        {
+            return None;
+        }
+        let mut buf = Vec::new();
+        KRPC::Error(t, PROTOCOL_ERROR, "Protocol Error".into())
+            .encode_to(&mut buf)
+            .ok()?;
+        self.trace_packet(Direction::Out, from, &buf);
+        trace!(%from, "answered malformed query");
+        Some(buf)
+    }
+
+    /// Pass a response to the query waiting for it, counting the external
+    /// address it reports as a vote. Responses nobody is waiting for, or
+    /// from another address, are dropped.
+    fn handle_response(
+        &self,
+        t: Vec<u8>,
+        response: DHTResponse,
+        from: SocketAddr,
+        ip: Option<IpAddr>,
+    ) {
+        let waiting = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(&t) {
+                Some((addr, _)) if *addr == from => pending.remove(&t),
+                _ => None,
+            }
+        };
+        let Some((_, reply)) = waiting else {
+            trace!(t = %hex::encode(&t), %from, "dropped unexpected response");
+            return;
+        };
+        if let Ok(id) = Key::try_from(response.id()) {
+            let node = Node::from_parts(id, from);
+            if let Ok(true) = self.table.lock().unwrap().put(node.clone()) {
+                self.subscribers.emit(Event::NodeAdded(node));
+            }
+        }
+        let changed = ip.and_then(|ip| self.external_ip.lock().unwrap().vote(from.ip(), ip));
+        if let Some(ip) = changed {
+            info!(%ip, "external address changed");
+            self.subscribers.emit(Event::ExternalIpChanged(ip));
+        }
+        // The query may have timed out meanwhile.
+        let _ = reply.send(response);
+    }
+
+    /// Answer `query` from `from`, adding the sender to the routing table
+    /// unless it is `read_only` (BEP 43) and so cannot answer us.
+    fn handle_query(
+        &self,
+        query: DHTQuery,
+        from: SocketAddr,
+        read_only: bool,
+    ) -> Option<DHTResponse> {
+        let id = Key::try_from(query.id()).ok()?;
+        let mut table = self.table.lock().unwrap();
+        let node = Node::from_parts(id, from);
+        if !read_only && table.put(node.clone()).ok()? {
+            self.subscribers.emit(Event::NodeAdded(node));
+        }
+        let own = table.id().as_bytes().to_vec();
+        let k = self.config.k;
+        let closest = |table: &RouteTable, target: &Key| {
+            let nodes = table.closest(target, k * 2);
+            // Compact node info only has room for IPv4 addresses.
+            Node::encode_compact(nodes.into_iter().filter(|n| n.addr().is_ipv4()).take(k))
+        };
+        Some(match query {
+            DHTQuery::Ping { .. } => DHTResponse::ID { id: own },
+            DHTQuery::FindNode { target, .. } => DHTResponse::FindNode {
+                id: own,
+                nodes: closest(&table, &Key::try_from(target.as_slice()).ok()?),
+            },
+            DHTQuery::GetPeers {
+                info_hash,
+                scrape,
+                noseed,
+                ..
+            } => {
+                let info_hash = Key::try_from(info_hash.as_slice()).ok()?;
+                let peers = if noseed {
+                    self.peers.leechers(&info_hash, MAX_VALUES)
+                } else {
+                    self.peers.peers(&info_hash, MAX_VALUES)
+                };
+                let values: Vec<_> = peers
+                    .iter()
+                    .filter(|peer| peer.is_ipv4() == from.is_ipv4())
+                    .map(compact::encode_peer)
+                    .collect();
+                let (seeds, peers) = if scrape {
+                    let (seeds, peers) = self.peers.bloom_filters(&info_hash);
+                    (seeds.as_bytes().to_vec(), peers.as_bytes().to_vec())
+                } else {
+                    (vec![], vec![])
+                };
+                DHTResponse::GetPeers {
+                    id: own,
+                    token: self.tokens.lock().unwrap().token(from.ip()),
+                    nodes: if values.is_empty() {
+                        closest(&table, &info_hash)
+                    } else {
+                        vec![]
+                    },
+                    values,
+                    seeds,
+                    peers,
+                }
+            }
+            DHTQuery::AnnouncePeer {
+                impiled_port,
+                port,
+                info_hash,
+                token,
+                seed,
+                ..
+            } => {
+                if !self.tokens.lock().unwrap().is_valid(&token, from.ip()) {
+                    return None;
+                }
+                let port = if impiled_port != 0 {
+                    from.port()
+                } else {
+                    u16::try_from(port).ok()?
+                };
+                let info_hash = Key::try_from(info_hash.as_slice()).ok()?;
+                let peer = SocketAddr::new(from.ip(), port);
+                if self.peers.announce(info_hash, peer, seed) {
+                    self.subscribers.emit(Event::PeerAnnounced {
+                        info_hash,
+                        peer,
+                        seed,
+                    });
+                }
+                DHTResponse::ID { id: own }
+            }
+            DHTQuery::Get { target, .. } => {
+                let target = Key::try_from(target.as_slice()).ok()?;
+                DHTResponse::Get {
+                    id: own,
+                    token: self.tokens.lock().unwrap().token(from.ip()),
+                    nodes: closest(&table, &target),
+                    value: self.items.get(&target).unwrap_or_default(),
+                }
+            }
+            DHTQuery::Put { token, value, .. } => {
+                if !self.tokens.lock().unwrap().is_valid(&token, from.ip()) {
+                    return None;
+                }
+                self.items.put(value).ok()?;
+                DHTResponse::ID { id: own }
+            }
+        })
+    }
+
+    /// Look up a random id in every bucket that has not changed for
+    /// [`GOOD_FOR`], so the table keeps up with the DHT where we see no
+    /// traffic. Returns the number of lookups that succeeded.
+    pub fn refresh(&self) -> usize {
+        let targets = self.table.lock().unwrap().stale_buckets(GOOD_FOR);
+        let _span = debug_span!("refresh", buckets = targets.len()).entered();
+        targets
+            .iter()
+            .filter(|target| self.find_node(target).is_ok())
+            .count()
+    }
+
+    /// Join the DHT through `nodes`, such as well-known routers, by pinging
+    /// them and then looking up our own id. Nodes already in the table,
+    /// for instance from [`load_state`](Server::load_state), are used too.
+    /// Returns the size of the table afterwards.
+    pub fn bootstrap(&self, nodes: &[SocketAddr]) -> Result<usize> {
+        let _span = info_span!("bootstrap", routers = nodes.len()).entered();
+        let own = self.id();
+        let pings = nodes
+            .iter()
+            .map(|addr| {
+                let id = own.as_bytes().to_vec();
+                (*addr, DHTQuery::Ping { id })
+            })
+            .collect();
+        self.query_all(pings);
+        if self.table.lock().unwrap().is_empty() {
+            return Err(Error::Dht("no bootstrap node responded".into()));
+        }
+        self.find_node(&own)?;
+        let nodes = self.table.lock().unwrap().len();
+        info!(nodes, "bootstrapped");
+        self.subscribers.emit(Event::BootstrapFinished { nodes });
+        Ok(nodes)
+    }
+
+    /// The nodes closest to `target` found by a lookup through the DHT,
+    /// closest first.
+    pub fn find_node(&self, target: &Key) -> Result<Vec<Node>> {
+        let answers = self.lookup(target, |id| DHTQuery::FindNode {
+            id,
+            target: target.as_bytes().to_vec(),
+        })?;
+        Ok(answers
+            .into_iter()
+            .map(|(node, _)| node)
+            .take(self.config.k)
+            .collect())
+    }
+
+    /// Peers for `info_hash` found by a lookup through the DHT, and on the
+    /// local network if LSD is enabled. The lookup failing is only an
+    /// error if LSD found nobody either.
+    pub fn get_peers(&self, info_hash: &Key) -> Result<Vec<SocketAddr>> {
+        let mut peers: BTreeSet<_> = self
+            .lsd
+            .iter()
+            .flat_map(|lsd| lsd.peers(info_hash))
+            .collect();
+        match self.lookup(info_hash, |id| get_peers(id, info_hash, false)) {
+            Ok(answers) => peers.extend(self.peers_in(&answers)),
+            Err(e) if peers.is_empty() => return Err(e),
+            Err(_) => {}
+        }
+        Ok(peers.into_iter().collect())
+    }
+
+    /// Estimate the swarm of `info_hash` by merging the bloom filters of
+    /// the closest nodes that support scrapes (BEP 33). The DHT does not
+    /// know about completed downloads, so `completed` is always zero.
+    pub fn scrape(&self, info_hash: &Key) -> Result<ScrapeStats> {
+        let mut seeds = BloomFilter::new();
+        let mut peers = BloomFilter::new();
+        let filters = self
+            .lookup(info_hash, |id| get_peers(id, info_hash, true))?
+            .into_iter()
+            .filter_map(|(_, response)| match response {
+                DHTResponse::GetPeers { seeds, peers, .. } => Some((
+                    BloomFilter::try_from(seeds.as_slice()).ok()?,
+                    BloomFilter::try_from(peers.as_slice()).ok()?,
+                )),
+                _ => None,
+            })
+            .take(self.config.k);
+        for (s, p) in filters {
+            seeds.union(&s);
+            peers.union(&p);
+        }
+        Ok(ScrapeStats {
+            seeders: seeds.estimate().round() as u32,
+            completed: 0,
+            leechers: peers.estimate().round() as u32,
+        })
     }
 
-    pub fn refersh(&self) -> Result<()> {
-        todo!()
+    /// Announce that we have `info_hash` on `port` to the closest nodes
+    /// that gave us a token, returning how many accepted. Peers forget
+    /// announces after a while; [`add_torrent`](Server::add_torrent) keeps
+    /// them fresh.
+    pub fn announce_peer(&self, info_hash: &Key, port: u16) -> Result<usize> {
+        Ok(self.announce(info_hash, port)?.0)
     }
 
-    pub fn find_node(&self) -> Result<()> {
-        todo!()
+    /// Keep announcing `info_hash` on `port`, starting now, whenever
+    /// [`announce_due`](Server::announce_due) runs.
+    pub fn add_torrent(&self, info_hash: Key, port: u16) {
+        self.announces.add(info_hash, port);
     }
 
-    pub fn get_peers(&self) -> Result<()> {
-        todo!()
+    /// Stop announcing `info_hash`, returning whether it was announced.
+    /// Nodes keep the last announce until it expires.
+    pub fn remove_torrent(&self, info_hash: &Key) -> bool {
+        self.announces.remove(info_hash)
     }
 
-    pub fn announce_peer(&self) -> Result<()> {
-        todo!()
+    pub fn torrent_status(&self, info_hash: &Key) -> Option<TorrentStatus> {
+        self.announces.status(info_hash)
     }
 
-    pub fn ping(&self) -> Result<()> {
-        todo!()
+    /// How announcing each of our torrents is going.
+    pub fn torrents(&self) -> Vec<TorrentStatus> {
+        self.announces.all()
+    }
+
+    /// Announce the torrents that are due, one after the other, returning
+    /// how many were. An announce no node accepts counts as failed and is
+    /// tried again soon.
+    pub fn announce_due(&self) -> usize {
+        let due = self.announces.take_due();
+        for (info_hash, port) in &due {
+            let result = self.announce(info_hash, *port).and_then(|(nodes, peers)| {
+                if nodes == 0 {
+                    return Err(Error::Dht("no node accepted the announce".into()));
+                }
+                Ok((nodes, peers))
+            });
+            if let Err(e) = &result {
+                debug!(info_hash = %hex::encode(info_hash.as_bytes()), error = %e, "announce failed");
+            }
+            self.announces.done(info_hash, &result);
+        }
+        due.len()
+    }
+
+    /// Announce our torrents as they come due, forever. Like queries, this
+    /// needs [`run`](Server::run) running on another thread.
+    pub fn run_announces(&self) {
+        loop {
+            self.announce_due();
+            thread::sleep(TICK);
+        }
+    }
+
+    /// Announce `info_hash` on `port` to the closest nodes that gave us a
+    /// token, returning how many accepted and how many peers the lookup
+    /// found.
+    fn announce(&self, info_hash: &Key, port: u16) -> Result<(usize, usize)> {
+        let answers = self.lookup(info_hash, |id| get_peers(id, info_hash, false))?;
+        let peers = self.peers_in(&answers).count();
+        let announces = answers
+            .into_iter()
+            .filter_map(|(node, response)| match response {
+                DHTResponse::GetPeers { token, .. } => Some((
+                    node.addr(),
+                    DHTQuery::AnnouncePeer {
+                        id: self.id().as_bytes().to_vec(),
+                        impiled_port: 0,
+                        port: u64::from(port),
+                        info_hash: info_hash.as_bytes().to_vec(),
+                        token,
+                        seed: false,
+                    },
+                )),
+                _ => None,
+            })
+            .take(self.config.k)
+            .collect();
+        let accepted = self.query_all(announces).iter().flatten().count();
+        Ok((accepted, peers))
+    }
+
+    /// The distinct, unblocked peers in `get_peers` answers.
+    fn peers_in<'a>(
+        &'a self,
+        answers: &'a [(Node, DHTResponse)],
+    ) -> impl Iterator<Item = SocketAddr> + 'a {
+        let peers: BTreeSet<_> = answers
+            .iter()
+            .flat_map(|(_, response)| match response {
+                DHTResponse::GetPeers { values, .. } => values.as_slice(),
+                _ => &[],
+            })
+            .filter_map(|value| compact::decode_peer(value).ok())
+            .filter(|peer| !self.filter.is_blocked(peer.ip()))
+            .collect();
+        peers.into_iter()
+    }
+
+    /// Store the bencoded `value` as an immutable item (BEP 44) on the
+    /// nodes closest to its target, which is returned.
+    pub fn put(&self, value: &[u8]) -> Result<Key> {
+        let target = validate_item(value)?;
+        let puts = self
+            .lookup(&target, |id| DHTQuery::Get {
+                id,
+                target: target.as_bytes().to_vec(),
+            })?
+            .into_iter()
+            .filter_map(|(node, response)| match response {
+                DHTResponse::Get { token, .. } | DHTResponse::GetPeers { token, .. } => Some((
+                    node.addr(),
+                    DHTQuery::Put {
+                        id: self.id().as_bytes().to_vec(),
+                        token,
+                        value: value.to_vec(),
+                    },
+                )),
+                _ => None,
+            })
+            .take(self.config.k)
+            .collect();
+        if self.query_all(puts).iter().flatten().count() == 0 {
+            return Err(Error::Dht("no node stored the item".into()));
+        }
+        Ok(target)
+    }
+
+    /// The bencoded immutable item stored under `target`, if any node
+    /// near it has one.
+    pub fn get(&self, target: &Key) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.items.get(target) {
+            return Ok(Some(value));
+        }
+        let answers = self.lookup(target, |id| DHTQuery::Get {
+            id,
+            target: target.as_bytes().to_vec(),
+        })?;
+        // Values that do not hash to the target are forged.
+        Ok(answers
+            .into_iter()
+            .find_map(|(_, response)| match response {
+                DHTResponse::Get { value, .. } if ItemStore::target(&value) == *target => {
+                    Some(value)
+                }
+                _ => None,
+            }))
+    }
+
+    /// Every node in the routing table.
+    pub fn nodes(&self) -> Vec<Node> {
+        let table = self.table.lock().unwrap();
+        table.nodes().into_iter().cloned().collect()
+    }
+
+    /// A snapshot of the routing table's buckets, with how each node has
+    /// been answering.
+    pub fn dump_table(&self) -> TableDump {
+        self.table.lock().unwrap().dump()
+    }
+
+    /// Write our id and the IPv4 nodes we know to `path`, so a restart
+    /// can rejoin the DHT without bootstrap routers.
+    pub fn save_state(&self, path: impl AsRef<Path>) -> Result<()> {
+        let table = self.table.lock().unwrap();
+        let nodes = table.nodes().into_iter().filter(|n| n.addr().is_ipv4());
+        let mut e = Encoder::new(Vec::new());
+        e.begin_dict()?;
+        e.write_str("id")?;
+        e.write_bytes(table.id().as_bytes())?;
+        e.write_str("nodes")?;
+        e.write_bytes(&Node::encode_compact(nodes))?;
+        e.end()?;
+        fs::write(path, e.into_inner())?;
+        Ok(())
+    }
+
+    /// Take the id and nodes written by [`save_state`](Server::save_state),
+    /// replacing the routing table. The nodes are not contacted until the
+    /// next lookup or [`bootstrap`](Server::bootstrap).
+    pub fn load_state(&self, path: impl AsRef<Path>) -> Result<()> {
+        let buf = fs::read(path)?;
+        let state = bencode::decode_ref(&buf)?;
+        let field = |key: &[u8]| state.get(key).and_then(ValueRef::as_bytes);
+        let id = Key::try_from(field(b"id").ok_or(Error::InvalidValue)?)?;
+        let mut table = RouteTable::with_id(id, &self.local_addr()?.to_string())?
+            .with_bucket_size(self.config.k)
+            .with_filter(Arc::clone(&self.filter))
+            .with_limits(self.config.node_limits);
+        for node in Node::decode_compact(field(b"nodes").unwrap_or_default())? {
+            // Nodes blocked since the state was saved are left out.
+            let _ = table.put(node);
+        }
+        debug!(nodes = table.len(), "loaded state");
+        *self.table.lock().unwrap() = table;
+        Ok(())
+    }
+
+    /// An iterative lookup of `target`: send the query made by `query`
+    /// from our id to the closest nodes known, alpha at a time, and learn
+    /// closer ones from the answers until the k closest nodes seen have
+    /// all been asked. Returns the answers, closest node first.
+    fn lookup(
+        &self,
+        target: &Key,
+        query: impl Fn(Vec<u8>) -> DHTQuery,
+    ) -> Result<Vec<(Node, DHTResponse)>> {
+        let own = self.id();
+        let method = query(own.as_bytes().to_vec()).method();
+        let _span =
+            info_span!("lookup", method, target = %hex::encode(target.as_bytes())).entered();
+        let started = Instant::now();
+        let mut candidates: BTreeMap<Key, Node> = self
+            .table
+            .lock()
+            .unwrap()
+            .closest(target, self.config.k)
+            .into_iter()
+            .map(|node| (node.id().distance(target), node.clone()))
+            .collect();
+        if candidates.is_empty() {
+            debug!("no nodes to ask");
+            return Err(Error::Dht("no nodes to ask".into()));
+        }
+        let mut asked = HashSet::new();
+        let mut answers = BTreeMap::new();
+        loop {
+            let batch: Vec<(Key, Node)> = candidates
+                .iter()
+                .take(self.config.k)
+                .filter(|(distance, _)| !asked.contains(*distance))
+                .take(self.config.alpha)
+                .map(|(distance, node)| (*distance, node.clone()))
+                .collect();
+            if batch.is_empty() {
+                break;
+            }
+            let queries = batch
+                .iter()
+                .map(|(_, node)| (node.addr(), query(own.as_bytes().to_vec())))
+                .collect();
+            let results = self.query_all(queries);
+            for ((distance, node), result) in batch.into_iter().zip(results) {
+                asked.insert(distance);
+                let Ok(response) = result else {
+                    candidates.remove(&distance);
+                    continue;
+                };
+                for found in Node::decode_compact(response.nodes()).unwrap_or_default() {
+                    if *found.id() != own && !self.filter.is_blocked(found.addr().ip()) {
+                        candidates
+                            .entry(found.id().distance(target))
+                            .or_insert(found);
+                    }
+                }
+                answers.insert(distance, (node, response));
+            }
+        }
+        self.metrics.lookup(method, started.elapsed());
+        self.subscribers.emit(Event::LookupCompleted {
+            method,
+            target: *target,
+            answered: answers.len(),
+            elapsed: started.elapsed(),
+        });
+        debug!(
+            asked = asked.len(),
+            answered = answers.len(),
+            elapsed = ?started.elapsed(),
+            "lookup done"
+        );
+        Ok(answers.into_values().collect())
+    }
+
+    /// Send all `queries` at once and wait for their results, in order.
+    fn query_all(&self, queries: Vec<(SocketAddr, DHTQuery)>) -> Vec<Result<DHTResponse>> {
+        thread::scope(|s| {
+            let queries: Vec<_> = queries
+                .into_iter()
+                .map(|(addr, query)| {
+                    // Keep the caller's span, such as its lookup's.
+                    let span = Span::current();
+                    s.spawn(move || span.in_scope(|| self.query(addr, query)))
+                })
+                .collect();
+            queries
+                .into_iter()
+                .map(|q| q.join().expect("query thread panicked"))
+                .collect()
+        })
+    }
+
+    /// Send `query` to `addr` and wait for the response, which
+    /// [`run`](Server::run) must be running on another thread to receive.
+    /// Blocked addresses are refused without sending anything.
+    pub fn query(&self, addr: SocketAddr, query: DHTQuery) -> Result<DHTResponse> {
+        if self.filter.is_blocked(addr.ip()) {
+            return Err(Error::Dht(format!("{} is blocked", addr)));
+        }
+        let t = self
+            .next_transaction
+            .fetch_add(1, Ordering::Relaxed)
+            .to_be_bytes()
+            .to_vec();
+        let (reply, response) = mpsc::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(t.clone(), (addr, reply));
+        let mut buf = Vec::new();
+        let method = query.method();
+        let _span = debug_span!("transaction", t = %hex::encode(&t), %addr, method).entered();
+        let encoded = KRPC::Query(t.clone(), query, self.config.read_only).encode_to(&mut buf);
+        let sent = Instant::now();
+        let result = encoded
+            .and_then(|_| {
+                self.trace_packet(Direction::Out, addr, &buf);
+                Ok(self.socket.send_to(&buf, addr)?)
+            })
+            .and_then(|len| {
+                self.metrics.query_out(method);
+                self.metrics.bytes_out(len);
+                trace!("sent");
+                response
+                    .recv_timeout(self.config.query_timeout)
+                    .map_err(|_| {
+                        self.metrics.timeout();
+                        let removed = self.table.lock().unwrap().failed(addr);
+                        if let Some(node) = removed {
+                            self.subscribers.emit(Event::NodeRemoved(node));
+                        }
+                        Error::Dht(format!("{} did not respond", addr))
+                    })
+            })
+            .inspect(|_| {
+                let mut table = self.table.lock().unwrap();
+                table.answered(addr, sent.elapsed());
+            });
+        match &result {
+            Ok(_) => trace!("answered"),
+            Err(e) => debug!(error = %e, "failed"),
+        }
+        self.pending.lock().unwrap().remove(&t);
+        result
+    }
+
+    /// Announce to every configured tracker and collect the peers they
+    /// return. Trackers that fail are skipped, as the DHT may still find
+    /// peers.
+    pub fn tracker_peers(&self, req: &AnnounceRequest, timeout: Duration) -> Vec<SocketAddr> {
+        let peers: BTreeSet<_> = self
+            .trackers
+            .iter()
+            .filter_map(|url| tracker::announce(url, req, timeout).ok())
+            .flat_map(|resp| resp.peers)
+            .collect();
+        peers.into_iter().collect()
+    }
+
+    fn trace_packet(&self, dir: Direction, addr: SocketAddr, packet: &[u8]) {
+        if let Some(trace) = &self.packet_trace {
+            trace.record(dir, addr, packet);
+        }
+    }
+
+    /// Ping `addr`, adding it to the routing table if it answers, and
+    /// return its id.
+    pub fn ping(&self, addr: SocketAddr) -> Result<Key> {
+        let id = self.id().as_bytes().to_vec();
+        let response = self.query(addr, DHTQuery::Ping { id })?;
+        Key::try_from(response.id())
+    }
+}
+
+/// The external address a response reports in its `ip` key (BEP 42).
+fn reported_ip(packet: &[u8]) -> Option<IpAddr> {
+    let message = bencode::decode_ref(packet).ok()?;
+    let ip = message.get(b"ip").and_then(ValueRef::as_bytes)?;
+    compact::decode_peer(ip).ok().map(|addr| addr.ip())
+}
+
+fn get_peers(id: Vec<u8>, info_hash: &Key, scrape: bool) -> DHTQuery {
+    DHTQuery::GetPeers {
+        id,
+        info_hash: info_hash.as_bytes().to_vec(),
+        scrape,
+        noseed: false,
     }
 }
@@ -0,0 +1,115 @@
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::Result;
+use crate::util::bencode::{self, ValueRef};
+use crate::util::hex;
+use crate::util::json::write_str;
+
+/// Which way a traced packet went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// A log of KRPC messages as JSON lines, one per packet, for looking at
+/// traffic offline:
+///
+/// ```json
+/// {"time":1700000000.123456,"dir":"in","addr":"1.2.3.4:6881","message":{"a":{"id":{"hex":"…"}},"q":"ping","t":"aa","y":"q"}}
+/// ```
+///
+/// The message is the bencoded dictionary as sent. Byte strings that are
+/// printable utf-8 are written as JSON strings and the rest, such as ids
+/// and compact nodes, as `{"hex": …}`.
+pub struct PacketTrace {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl PacketTrace {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Mutex::new(Box::new(out)),
+        }
+    }
+
+    /// Append to the file at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+
+    /// Write the line for `packet`, a KRPC message sent to or received
+    /// from `addr`. Packets that are not bencode are skipped, and so are
+    /// write errors: tracing must not get in the way of the DHT.
+    pub fn record(&self, dir: Direction, addr: SocketAddr, packet: &[u8]) {
+        let Ok(message) = bencode::decode_ref(packet) else {
+            return;
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut line = format!(
+            "{{\"time\":{}.{:06},\"dir\":\"{}\",\"addr\":\"{}\",\"message\":",
+            time.as_secs(),
+            time.subsec_micros(),
+            match dir {
+                Direction::In => "in",
+                Direction::Out => "out",
+            },
+            addr
+        );
+        write_json(&mut line, &message);
+        line.push_str("}\n");
+        let mut out = self.out.lock().unwrap();
+        if out.write_all(line.as_bytes()).is_ok() {
+            let _ = out.flush();
+        }
+    }
+}
+
+fn write_json(out: &mut String, value: &ValueRef) {
+    match value {
+        ValueRef::Bytes(bytes) => write_bytes(out, bytes),
+        ValueRef::Integer(i) => {
+            let _ = write!(out, "{}", i);
+        }
+        ValueRef::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, item);
+            }
+            out.push(']');
+        }
+        ValueRef::Dict(entries) => {
+            out.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_str(out, &String::from_utf8_lossy(key));
+                out.push(':');
+                write_json(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_bytes(out: &mut String, bytes: &[u8]) {
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.chars().any(char::is_control) => write_str(out, s),
+        _ => {
+            let _ = write!(out, "{{\"hex\":\"{}\"}}", hex::encode(bytes));
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::route_table::Key;
+use crate::tracker::ScrapeStats;
+use crate::util::bloom::BloomFilter;
+
+/// How long an announce is remembered, per BEP 5's suggestion.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Peers announced for each torrent, shared by the DHT and the tracker.
+pub struct PeerStore {
+    ttl: Duration,
+    max_torrents: usize,
+    max_peers: usize,
+    swarms: Mutex<HashMap<Key, Swarm>>,
+}
+
+#[derive(Default)]
+struct Swarm {
+    peers: HashMap<SocketAddr, Peer>,
+    /// Number of `completed` events seen.
+    completed: u32,
+}
+
+struct Peer {
+    seed: bool,
+    announced: Instant,
+}
+
+impl Default for PeerStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+impl PeerStore {
+    /// A store forgetting peers that have not announced for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_torrents: usize::MAX,
+            max_peers: usize::MAX,
+            swarms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Store at most `max_torrents` torrents and `max_peers` peers for
+    /// each, so announces cannot grow the store without bound. Announces
+    /// past the limits are dropped until expiry makes room.
+    pub fn with_limits(mut self, max_torrents: usize, max_peers: usize) -> Self {
+        self.max_torrents = max_torrents;
+        self.max_peers = max_peers;
+        self
+    }
+
+    /// Record that `addr` is in the swarm for `info_hash`, returning
+    /// whether there was room for it.
+    pub fn announce(&self, info_hash: Key, addr: SocketAddr, seed: bool) -> bool {
+        let mut swarms = self.swarms.lock().unwrap();
+        if !swarms.contains_key(&info_hash) && swarms.len() >= self.max_torrents {
+            return false;
+        }
+        let swarm = swarms.entry(info_hash).or_default();
+        if !swarm.peers.contains_key(&addr) && swarm.peers.len() >= self.max_peers {
+            return false;
+        }
+        swarm.peers.insert(
+            addr,
+            Peer {
+                seed,
+                announced: Instant::now(),
+            },
+        );
+        true
+    }
+
+    /// Record a finished download; `addr` is a seed from now on. Returns
+    /// whether there was room for it, like [`announce`](PeerStore::announce).
+    pub fn complete(&self, info_hash: Key, addr: SocketAddr) -> bool {
+        if !self.announce(info_hash, addr, true) {
+            return false;
+        }
+        let mut swarms = self.swarms.lock().unwrap();
+        if let Some(swarm) = swarms.get_mut(&info_hash) {
+            swarm.completed += 1;
+        }
+        true
+    }
+
+    pub fn remove(&self, info_hash: &Key, addr: &SocketAddr) {
+        let mut swarms = self.swarms.lock().unwrap();
+        if let Some(swarm) = swarms.get_mut(info_hash) {
+            swarm.peers.remove(addr);
+        }
+    }
+
+    /// When `addr` last announced `info_hash`, if it is still stored.
+    pub fn last_announce(&self, info_hash: &Key, addr: &SocketAddr) -> Option<Instant> {
+        let swarms = self.swarms.lock().unwrap();
+        let peer = swarms.get(info_hash)?.peers.get(addr)?;
+        Some(peer.announced).filter(|at| at.elapsed() < self.ttl)
+    }
+
+    /// Up to `max` live peers for `info_hash`.
+    pub fn peers(&self, info_hash: &Key, max: usize) -> Vec<SocketAddr> {
+        self.select(info_hash, max, true)
+    }
+
+    /// Up to `max` live peers for `info_hash` that are not seeds.
+    pub fn leechers(&self, info_hash: &Key, max: usize) -> Vec<SocketAddr> {
+        self.select(info_hash, max, false)
+    }
+
+    fn select(&self, info_hash: &Key, max: usize, seeds: bool) -> Vec<SocketAddr> {
+        let swarms = self.swarms.lock().unwrap();
+        swarms
+            .get(info_hash)
+            .map(|swarm| {
+                swarm
+                    .peers
+                    .iter()
+                    .filter(|(_, peer)| peer.announced.elapsed() < self.ttl)
+                    .filter(|(_, peer)| seeds || !peer.seed)
+                    .map(|(addr, _)| *addr)
+                    .take(max)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Bloom filters of the addresses of live seeds and of the other
+    /// peers for `info_hash`, answering DHT scrapes (BEP 33).
+    pub fn bloom_filters(&self, info_hash: &Key) -> (BloomFilter, BloomFilter) {
+        let mut seeds = BloomFilter::new();
+        let mut peers = BloomFilter::new();
+        let swarms = self.swarms.lock().unwrap();
+        let live = swarms
+            .get(info_hash)
+            .into_iter()
+            .flat_map(|swarm| &swarm.peers)
+            .filter(|(_, peer)| peer.announced.elapsed() < self.ttl);
+        for (addr, peer) in live {
+            if peer.seed {
+                seeds.insert(addr.ip());
+            } else {
+                peers.insert(addr.ip());
+            }
+        }
+        (seeds, peers)
+    }
+
+    pub fn stats(&self, info_hash: &Key) -> ScrapeStats {
+        let swarms = self.swarms.lock().unwrap();
+        let Some(swarm) = swarms.get(info_hash) else {
+            return ScrapeStats::default();
+        };
+        let mut stats = ScrapeStats {
+            completed: swarm.completed,
+            ..ScrapeStats::default()
+        };
+        for peer in swarm.peers.values() {
+            if peer.announced.elapsed() >= self.ttl {
+                continue;
+            }
+            if peer.seed {
+                stats.seeders += 1;
+            } else {
+                stats.leechers += 1;
+            }
+        }
+        stats
+    }
+
+    /// Number of torrents with at least one stored peer.
+    pub fn torrents(&self) -> usize {
+        self.swarms.lock().unwrap().len()
+    }
+
+    /// Number of stored peers across all torrents, including expired ones
+    /// not dropped yet.
+    pub fn peer_count(&self) -> usize {
+        let swarms = self.swarms.lock().unwrap();
+        swarms.values().map(|swarm| swarm.peers.len()).sum()
+    }
+
+    /// Drop expired peers, and torrents left without any.
+    pub fn expire(&self) {
+        let mut swarms = self.swarms.lock().unwrap();
+        for swarm in swarms.values_mut() {
+            swarm
+                .peers
+                .retain(|_, peer| peer.announced.elapsed() < self.ttl);
+        }
+        swarms.retain(|_, swarm| !swarm.peers.is_empty());
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A sustained rate of queries per second, with bursts of up to `burst`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub rate: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// No limit, as for replaying captured traffic.
+    pub const UNLIMITED: RateLimit = RateLimit {
+        rate: u32::MAX,
+        burst: u32::MAX,
+    };
+}
+
+/// Per source, a little above what a busy client sends during lookups.
+pub const DEFAULT_PER_IP: RateLimit = RateLimit {
+    rate: 10,
+    burst: 50,
+};
+pub const DEFAULT_GLOBAL: RateLimit = RateLimit {
+    rate: 2_000,
+    burst: 4_000,
+};
+/// Strikes, for queries over the limit or malformed packets, that get a
+/// source banned.
+pub const DEFAULT_BAN_AFTER: u32 = 20;
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(5 * 60);
+/// Sources tracked at most, so a flood from spoofed addresses cannot
+/// grow the table without bound.
+pub const DEFAULT_MAX_SOURCES: usize = 65_536;
+
+/// How many incoming packets were dropped, and why.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DropStats {
+    /// Queries over their source's limit.
+    pub rate_limited: u64,
+    /// Queries over the global limit.
+    pub global_limited: u64,
+    /// Packets from banned sources.
+    pub banned: u64,
+    /// Packets that are not valid KRPC.
+    pub malformed: u64,
+    /// Bans handed out.
+    pub bans: u64,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit) {
+        let elapsed = self.updated.elapsed().as_secs_f64();
+        self.tokens = (self.tokens + elapsed * f64::from(limit.rate)).min(f64::from(limit.burst));
+        self.updated = Instant::now();
+    }
+
+    fn take(&mut self, limit: RateLimit) -> bool {
+        if !self.has_token(limit) {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    fn has_token(&mut self, limit: RateLimit) -> bool {
+        self.refill(limit);
+        self.tokens >= 1.0
+    }
+}
+
+struct Source {
+    bucket: TokenBucket,
+    strikes: u32,
+    struck: Instant,
+    banned_until: Option<Instant>,
+}
+
+/// Token buckets for incoming queries, one per source IP and one for all
+/// of them, and a list of sources banned for misbehaving repeatedly.
+pub struct RateLimiter {
+    per_ip: RateLimit,
+    global: RateLimit,
+    ban_after: u32,
+    ban_duration: Duration,
+    max_sources: usize,
+    bucket: Mutex<TokenBucket>,
+    sources: Mutex<HashMap<IpAddr, Source>>,
+    rate_limited: AtomicU64,
+    global_limited: AtomicU64,
+    banned: AtomicU64,
+    malformed: AtomicU64,
+    bans: AtomicU64,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_PER_IP,
+            DEFAULT_GLOBAL,
+            DEFAULT_BAN_AFTER,
+            DEFAULT_BAN_DURATION,
+        )
+    }
+}
+
+impl RateLimiter {
+    /// A limiter banning a source for `ban_duration` once it collects
+    /// `ban_after` strikes. Strikes are forgotten after a quiet
+    /// `ban_duration`.
+    pub fn new(
+        per_ip: RateLimit,
+        global: RateLimit,
+        ban_after: u32,
+        ban_duration: Duration,
+    ) -> Self {
+        Self {
+            per_ip,
+            global,
+            ban_after,
+            ban_duration,
+            max_sources: DEFAULT_MAX_SOURCES,
+            bucket: Mutex::new(TokenBucket::new(global)),
+            sources: Mutex::new(HashMap::new()),
+            rate_limited: AtomicU64::new(0),
+            global_limited: AtomicU64::new(0),
+            banned: AtomicU64::new(0),
+            malformed: AtomicU64::new(0),
+            bans: AtomicU64::new(0),
+        }
+    }
+
+    /// Track at most `max_sources` sources instead of
+    /// [`DEFAULT_MAX_SOURCES`]. Past that, the sources least likely to be
+    /// abusing us are forgotten first.
+    pub fn with_max_sources(mut self, max_sources: usize) -> Self {
+        self.max_sources = max_sources.max(1);
+        self
+    }
+
+    /// Whether `ip` is banned, counting a dropped packet if it is.
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let sources = self.sources.lock().unwrap();
+        let banned = sources
+            .get(&ip)
+            .and_then(|source| source.banned_until)
+            .is_some_and(|until| until > Instant::now());
+        if banned {
+            self.banned.fetch_add(1, Ordering::Relaxed);
+        }
+        banned
+    }
+
+    /// Whether to answer a query from `ip`. A query over the source's
+    /// limit is a strike against it; one over the global limit is not,
+    /// and is dropped before the source is tracked at all.
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        if !self.bucket.lock().unwrap().has_token(self.global) {
+            self.global_limited.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        {
+            let mut sources = self.sources.lock().unwrap();
+            let source = self.source(&mut sources, ip);
+            if !source.bucket.take(self.per_ip) {
+                self.rate_limited.fetch_add(1, Ordering::Relaxed);
+                self.strike(source);
+                return false;
+            }
+        }
+        if !self.bucket.lock().unwrap().take(self.global) {
+            self.global_limited.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    /// Count a packet from `ip` that could not be decoded, as a strike.
+    /// Over the global limit only sources already tracked are struck.
+    pub fn malformed(&self, ip: IpAddr) {
+        self.malformed.fetch_add(1, Ordering::Relaxed);
+        let busy = !self.bucket.lock().unwrap().has_token(self.global);
+        let mut sources = self.sources.lock().unwrap();
+        if busy && !sources.contains_key(&ip) {
+            return;
+        }
+        let source = self.source(&mut sources, ip);
+        self.strike(source);
+    }
+
+    /// Ban `ip` for `duration` regardless of strikes.
+    pub fn ban(&self, ip: IpAddr, duration: Duration) {
+        let mut sources = self.sources.lock().unwrap();
+        self.source(&mut sources, ip).banned_until = Some(Instant::now() + duration);
+        self.bans.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn unban(&self, ip: IpAddr) {
+        if let Some(source) = self.sources.lock().unwrap().get_mut(&ip) {
+            source.banned_until = None;
+            source.strikes = 0;
+        }
+    }
+
+    /// Sources banned now, with how long their bans have left.
+    pub fn banned(&self) -> Vec<(IpAddr, Duration)> {
+        let now = Instant::now();
+        let sources = self.sources.lock().unwrap();
+        sources
+            .iter()
+            .filter_map(|(ip, source)| {
+                let until = source.banned_until.filter(|until| *until > now)?;
+                Some((*ip, until - now))
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> DropStats {
+        DropStats {
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+            global_limited: self.global_limited.load(Ordering::Relaxed),
+            banned: self.banned.load(Ordering::Relaxed),
+            malformed: self.malformed.load(Ordering::Relaxed),
+            bans: self.bans.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Forget sources with a full bucket and no ban or recent strikes,
+    /// which is the state a new source starts in anyway.
+    pub fn expire(&self) {
+        self.prune(&mut self.sources.lock().unwrap());
+    }
+
+    fn prune(&self, sources: &mut HashMap<IpAddr, Source>) {
+        let now = Instant::now();
+        sources.retain(|_, source| {
+            source.bucket.refill(self.per_ip);
+            let banned = source.banned_until.is_some_and(|until| until > now);
+            let struck = source.strikes > 0 && source.struck.elapsed() < self.ban_duration;
+            banned || struck || source.bucket.tokens < f64::from(self.per_ip.burst)
+        });
+    }
+
+    /// Make room for a new source: forget idle ones, and if that is not
+    /// enough, a tenth of the table, keeping bans, then strikes, then the
+    /// sources that used most of their bucket.
+    fn evict(&self, sources: &mut HashMap<IpAddr, Source>) {
+        self.prune(sources);
+        if sources.len() < self.max_sources {
+            return;
+        }
+        let now = Instant::now();
+        let mut ranked: Vec<_> = sources
+            .iter()
+            .map(|(ip, source)| {
+                let banned = source.banned_until.is_some_and(|until| until > now);
+                (banned, source.strikes, -source.bucket.tokens, *ip)
+            })
+            .collect();
+        ranked.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)).then(a.2.total_cmp(&b.2)));
+        let excess = sources.len() + 1 - self.max_sources;
+        let evicted = excess.max(self.max_sources / 10);
+        for (.., ip) in ranked.into_iter().take(evicted) {
+            sources.remove(&ip);
+        }
+    }
+
+    /// Number of sources being tracked.
+    pub fn len(&self) -> usize {
+        self.sources.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn source<'a>(&self, sources: &'a mut HashMap<IpAddr, Source>, ip: IpAddr) -> &'a mut Source {
+        if !sources.contains_key(&ip) && sources.len() >= self.max_sources {
+            self.evict(sources);
+        }
+        sources.entry(ip).or_insert_with(|| Source {
+            bucket: TokenBucket::new(self.per_ip),
+            strikes: 0,
+            struck: Instant::now(),
+            banned_until: None,
+        })
+    }
+
+    fn strike(&self, source: &mut Source) {
+        if source.struck.elapsed() >= self.ban_duration {
+            source.strikes = 0;
+        }
+        source.strikes += 1;
+        source.struck = Instant::now();
+        if source.strikes >= self.ban_after {
+            source.strikes = 0;
+            source.banned_until = Some(Instant::now() + self.ban_duration);
+            self.bans.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
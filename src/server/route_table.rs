@@ -1,14 +1,62 @@
+use super::ip_filter::IpFilter;
 use crate::errors::{Error, Result};
+use crate::util::{compact, hex, json, random};
 use sha1::{Digest, Sha1};
-use std::collections::HashMap;
+use std::collections::{hash_map, BTreeMap, HashMap};
 use std::convert::TryInto;
-use std::fmt::Display;
-use std::net::SocketAddr;
+use std::fmt::{Display, Write as _};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, trace};
 
-const BUCKET_SIZE: usize = 8;
+/// Nodes per bucket unless [`RouteTable::with_bucket_size`] says
+/// otherwise, Kademlia's k.
+pub const BUCKET_SIZE: usize = 8;
 const KEY_LENGTH: usize = 20;
 const KEY_SPACE: usize = 160;
-const MAX_PREFIX_LENGTH: usize = 10;
+/// Queries in a row a node may leave unanswered before it is dropped.
+pub const MAX_FAILURES: u32 = 3;
+/// How long a node that has answered us stays good without being heard
+/// from again (BEP 5).
+pub const GOOD_FOR: Duration = Duration::from_secs(15 * 60);
+
+/// Limits on where the nodes in a table come from, so one host cannot
+/// fill it with made-up ids (a Sybil attack). Subnets are /24 for IPv4
+/// and /64 for IPv6.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeLimits {
+    /// Nodes per IP address in the whole table.
+    pub per_ip: usize,
+    /// Nodes per subnet in the whole table.
+    pub per_subnet: usize,
+    /// Nodes per subnet in one bucket, and preferred at most in one
+    /// [`closest`](RouteTable::closest) result.
+    pub per_bucket_subnet: usize,
+    /// Refuse a node at an address already taken by another id.
+    pub one_id_per_addr: bool,
+}
+
+impl NodeLimits {
+    /// No limits, as for a table of nodes on one host.
+    pub const UNLIMITED: NodeLimits = NodeLimits {
+        per_ip: usize::MAX,
+        per_subnet: usize::MAX,
+        per_bucket_subnet: usize::MAX,
+        one_id_per_addr: false,
+    };
+}
+
+impl Default for NodeLimits {
+    fn default() -> Self {
+        Self {
+            per_ip: 1,
+            per_subnet: 4,
+            per_bucket_subnet: 1,
+            one_id_per_addr: true,
+        }
+    }
+}
 
 #[derive(Default)]
 struct Trie {
@@ -18,25 +66,86 @@ struct Trie {
 }
 
 impl Trie {
-    fn insert(&mut self, node: Node, i: usize) -> bool {
-        let root = if node.id.bit(i) == 0 {
+    /// Insert `node` at depth `i`, or move a known one to its address,
+    /// returning whether it was added or moved. As in Kademlia only the
+    /// bucket covering our own id is split when full; `own_path` tracks
+    /// whether this subtree does.
+    /// Buckets hold up to `k` nodes, `per_subnet` of them from one subnet.
+    fn insert(
+        &mut self,
+        node: Node,
+        own: &Key,
+        i: usize,
+        own_path: bool,
+        k: usize,
+        per_subnet: usize,
+    ) -> bool {
+        let bit = node.id.bit(i);
+        let root = if bit == 0 {
             &mut self.right
         } else {
             &mut self.left
         };
         match root {
-            Some(next) => next.insert(node, i + 1),
+            Some(next) => {
+                let own_path = own_path && own.bit(i) == bit;
+                next.insert(node, own, i + 1, own_path, k, per_subnet)
+            }
             None => {
-                if self.bucket.is_full() {
-                    self.split();
-                    self.insert(node, i)
-                } else {
-                    self.bucket.add(node)
+                if let Some(known) = self.bucket.nodes.get(&node.id) {
+                    let moved_subnet = subnet(known.addr.ip()) != subnet(node.addr.ip());
+                    if moved_subnet && self.bucket.in_subnet(node.addr.ip()) >= per_subnet {
+                        return false;
+                    }
+                    self.bucket.nodes.insert(node.id, node);
+                    self.bucket.last_changed = unix_time();
+                    return true;
                 }
+                if !self.bucket.is_full(k) {
+                    if self.bucket.in_subnet(node.addr.ip()) >= per_subnet {
+                        return false;
+                    }
+                    return self.bucket.add(node, k);
+                }
+                if own_path && i + 1 < KEY_SPACE {
+                    self.split(i);
+                    return self.insert(node, own, i, own_path, k, per_subnet);
+                }
+                self.bucket.replace(node, k);
+                false
+            }
+        }
+    }
+
+    fn remove(&mut self, id: &Key, i: usize) -> Option<Node> {
+        let root = if id.bit(i) == 0 {
+            &mut self.right
+        } else {
+            &mut self.left
+        };
+        match root {
+            Some(next) => next.remove(id, i + 1),
+            None => {
+                let node = self.bucket.nodes.remove(id)?;
+                self.bucket.last_changed = unix_time();
+                Some(node)
             }
         }
     }
 
+    /// The replacement cache of the bucket covering `id`.
+    fn replacements(&mut self, id: &Key, i: usize) -> &mut Vec<Node> {
+        let root = if id.bit(i) == 0 {
+            &mut self.right
+        } else {
+            &mut self.left
+        };
+        match root {
+            Some(next) => next.replacements(id, i + 1),
+            None => &mut self.bucket.replacements,
+        }
+    }
+
     fn get(&self, id: &Key, i: usize) -> Option<&Node> {
         let root = if id.bit(i) == 0 {
             &self.right
@@ -49,66 +158,589 @@ impl Trie {
         }
     }
 
-    fn split(&mut self) -> bool {
-        todo!()
+    /// Move the bucket's nodes and replacements into two children by bit
+    /// `i`.
+    fn split(&mut self, i: usize) {
+        debug!(depth = i, "splitting bucket");
+        let now = unix_time();
+        let mut left = Trie::default();
+        let mut right = Trie::default();
+        left.bucket.last_changed = now;
+        right.bucket.last_changed = now;
+        for (id, node) in self.bucket.nodes.drain() {
+            if id.bit(i) == 0 {
+                right.bucket.nodes.insert(id, node);
+            } else {
+                left.bucket.nodes.insert(id, node);
+            }
+        }
+        for node in self.bucket.replacements.drain(..) {
+            if node.id.bit(i) == 0 {
+                right.bucket.replacements.push(node);
+            } else {
+                left.bucket.replacements.push(node);
+            }
+        }
+        self.left = Some(Box::new(left));
+        self.right = Some(Box::new(right));
+    }
+
+    /// Call `f` with the prefix of every bucket, those with a 0 bit first.
+    fn buckets<'a>(&'a self, prefix: &mut String, f: &mut impl FnMut(&str, &'a Bucket)) {
+        if self.left.is_none() && self.right.is_none() {
+            f(prefix, &self.bucket);
+        }
+        for (bit, child) in [('0', &self.right), ('1', &self.left)] {
+            if let Some(child) = child {
+                prefix.push(bit);
+                child.buckets(prefix, f);
+                prefix.pop();
+            }
+        }
+    }
+
+    fn depths(&self, depth: usize, out: &mut BTreeMap<usize, usize>) {
+        if !self.bucket.nodes.is_empty() {
+            *out.entry(depth).or_default() += self.bucket.nodes.len();
+        }
+        for child in [&self.left, &self.right].into_iter().flatten() {
+            child.depths(depth + 1, out);
+        }
     }
 }
 
 pub struct RouteTable {
     self_node: Node,
     node_num: usize,
+    bucket_size: usize,
+    filter: Arc<IpFilter>,
+    limits: NodeLimits,
+    /// Nodes per IP and per subnet, and the id at each address, for
+    /// `limits`.
+    ips: HashMap<IpAddr, usize>,
+    subnets: HashMap<IpAddr, usize>,
+    ids: HashMap<SocketAddr, Key>,
+    /// How each node has been answering, by id.
+    contacts: HashMap<Key, Contact>,
     root: Box<Trie>,
 }
 
 impl RouteTable {
+    /// A table for a node listening on `addr`, with a random id.
     pub fn new(addr: &str) -> Result<Self> {
+        Self::with_id(Key::random(), addr)
+    }
+
+    /// A table for a node that keeps `id`, such as one restored from
+    /// saved state.
+    pub fn with_id(id: Key, addr: &str) -> Result<Self> {
         Ok(Self {
             self_node: Node {
-                id: Key::new(),
+                id,
                 addr: addr.parse()?,
             },
             node_num: 0,
+            bucket_size: BUCKET_SIZE,
+            filter: Arc::new(IpFilter::new()),
+            limits: NodeLimits::UNLIMITED,
+            ips: HashMap::new(),
+            subnets: HashMap::new(),
+            ids: HashMap::new(),
+            contacts: HashMap::new(),
             root: Box::new(Trie::default()),
         })
     }
 
-    pub fn put(&mut self, node: Node) -> Result<()> {
-        self.root.insert(node, 0);
-        self.node_num += 1;
+    /// Keep up to `k` nodes per bucket instead of [`BUCKET_SIZE`].
+    pub fn with_bucket_size(mut self, k: usize) -> Self {
+        self.bucket_size = k;
+        self
+    }
 
-        Ok(())
+    /// Refuse nodes whose address `filter` blocks.
+    pub fn with_filter(mut self, filter: Arc<IpFilter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Refuse nodes past `limits`, which are not applied to nodes already
+    /// in the table.
+    pub fn with_limits(mut self, limits: NodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Our own id, which the table is organised around.
+    pub fn id(&self) -> &Key {
+        &self.self_node.id
+    }
+
+    /// Add `node`, or update the address of a known one, returning
+    /// whether it is new to the table. A node that is heard from is no
+    /// longer counted as failing. Blocked nodes and those past the limits
+    /// are refused with an error; ones that do not fit in a full bucket
+    /// are not, and go to its replacement cache instead.
+    pub fn put(&mut self, node: Node) -> Result<bool> {
+        if node.id == self.self_node.id {
+            return Ok(false);
+        }
+        if self.filter.is_blocked(node.addr.ip()) {
+            trace!(addr = %node.addr, "refused blocked node");
+            return Err(Error::Dht(format!("node at {} is blocked", node.addr)));
+        }
+        let addr = node.addr;
+        let refused = |why: &str| {
+            trace!(%addr, why, "refused node");
+            Err(Error::Dht(format!("node at {} refused: {}", addr, why)))
+        };
+        if self.limits.one_id_per_addr && self.ids.get(&node.addr).is_some_and(|id| *id != node.id)
+        {
+            return refused("address has another id");
+        }
+        let known = self.get(&node.id).map(Node::addr);
+        if known == Some(node.addr) {
+            self.seen(node.id);
+            return Ok(false);
+        }
+        // A new node, or a known one at a new address. One that only
+        // changed port, or ip within its subnet, does not count against
+        // it twice.
+        let ip = node.addr.ip();
+        if known.map(|old| old.ip()) != Some(ip)
+            && self.ips.get(&ip).copied().unwrap_or(0) >= self.limits.per_ip
+        {
+            return refused("too many nodes at its ip");
+        }
+        if known.map(|old| subnet(old.ip())) != Some(subnet(ip))
+            && self.subnets.get(&subnet(ip)).copied().unwrap_or(0) >= self.limits.per_subnet
+        {
+            return refused("too many nodes in its subnet");
+        }
+        let own = self.self_node.id;
+        let (k, per_subnet) = (self.bucket_size, self.limits.per_bucket_subnet);
+        let id = node.id;
+        let inserted = self.root.insert(node, &own, 0, true, k, per_subnet);
+        match known {
+            Some(old) if inserted => {
+                trace!(id = %hex::encode(id.as_bytes()), from = %old, to = %addr, "node moved");
+                self.forget(old);
+            }
+            Some(_) => return refused("too many nodes in its subnet in the bucket"),
+            None if inserted => {
+                trace!(id = %hex::encode(id.as_bytes()), %addr, "node added");
+                self.node_num += 1;
+            }
+            None => return Ok(false),
+        }
+        *self.ips.entry(ip).or_default() += 1;
+        *self.subnets.entry(subnet(ip)).or_default() += 1;
+        self.ids.insert(addr, id);
+        self.seen(id);
+        Ok(known.is_none())
+    }
+
+    /// Drop the node with `id`, returning it if it was in the table. The
+    /// latest node in its bucket's replacement cache that is still
+    /// allowed takes its place.
+    pub fn remove(&mut self, id: &Key) -> Option<Node> {
+        let node = self.root.remove(id, 0)?;
+        trace!(id = %hex::encode(id.as_bytes()), addr = %node.addr, "node removed");
+        self.node_num -= 1;
+        self.forget(node.addr);
+        self.contacts.remove(id);
+        while let Some(replacement) = self.root.replacements(id, 0).pop() {
+            if let Ok(true) = self.put(replacement) {
+                break;
+            }
+        }
+        Some(node)
+    }
+
+    /// Record that the node at `addr` answered a query after `rtt`, which
+    /// is averaged into its round-trip time.
+    pub fn answered(&mut self, addr: SocketAddr, rtt: Duration) {
+        let Some(contact) = self.ids.get(&addr).and_then(|id| self.contacts.get_mut(id)) else {
+            return;
+        };
+        contact.rtt = Some(match contact.rtt {
+            Some(average) => (average * 7 + rtt) / 8,
+            None => rtt,
+        });
+    }
+
+    fn seen(&mut self, id: Key) {
+        let contact = self.contacts.entry(id).or_default();
+        contact.failures = 0;
+        contact.last_seen = Some(Instant::now());
+    }
+
+    /// Count a query to the node at `addr` that went unanswered, dropping
+    /// the node once it has failed [`MAX_FAILURES`] times in a row. Returns
+    /// the node if it was dropped.
+    pub fn failed(&mut self, addr: SocketAddr) -> Option<Node> {
+        let id = *self.ids.get(&addr)?;
+        let contact = self.contacts.entry(id).or_default();
+        contact.failures += 1;
+        if contact.failures < MAX_FAILURES {
+            return None;
+        }
+        self.remove(&id)
+    }
+
+    /// Drop the counts for a node that left `addr`.
+    fn forget(&mut self, addr: SocketAddr) {
+        let ip = addr.ip();
+        for (counts, key) in [(&mut self.ips, ip), (&mut self.subnets, subnet(ip))] {
+            if let Some(count) = counts.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&key);
+                }
+            }
+        }
+        self.ids.remove(&addr);
     }
 
     pub fn get(&self, id: &Key) -> Option<&Node> {
         self.root.get(id, 0)
     }
 
-    pub(crate) fn distance(&self, node: &Node) -> u64 {
-        todo!()
+    pub fn len(&self) -> usize {
+        self.node_num
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.node_num == 0
+    }
+
+    /// Every known node, in no particular order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            tries: vec![&self.root],
+            nodes: None,
+        }
+    }
+
+    /// Every known node, in no particular order.
+    pub fn nodes(&self) -> Vec<&Node> {
+        self.iter().collect()
+    }
+
+    /// Whether the node with `id` is good, questionable or bad, by BEP 5's
+    /// rules.
+    pub fn status(&self, id: &Key) -> Option<NodeStatus> {
+        self.get(id)?;
+        Some(self.contacts.get(id).cloned().unwrap_or_default().status())
+    }
+
+    /// A snapshot of every bucket, for looking at the table when something
+    /// goes wrong.
+    pub fn dump(&self) -> TableDump {
+        let mut buckets = vec![];
+        self.root
+            .buckets(&mut String::new(), &mut |prefix, bucket| {
+                let mut nodes: Vec<_> = bucket
+                    .nodes
+                    .values()
+                    .map(|node| {
+                        let contact = self.contacts.get(&node.id).cloned().unwrap_or_default();
+                        NodeDump {
+                            node: node.clone(),
+                            status: contact.status(),
+                            rtt: contact.rtt,
+                            failures: contact.failures,
+                        }
+                    })
+                    .collect();
+                nodes.sort_by_key(|dump| dump.node.id);
+                buckets.push(BucketDump {
+                    prefix: prefix.to_string(),
+                    depth: prefix.len(),
+                    last_changed: bucket.last_changed,
+                    nodes,
+                    replacements: bucket.replacements.iter().rev().cloned().collect(),
+                });
+            });
+        TableDump {
+            id: self.self_node.id,
+            nodes: self.node_num,
+            buckets,
+        }
+    }
+
+    /// A random id in the range of every bucket that no node has joined,
+    /// left or moved in for `age`; looking them up refreshes the buckets
+    /// (BEP 5).
+    pub fn stale_buckets(&self, age: Duration) -> Vec<Key> {
+        let since = unix_time().saturating_sub(age.as_secs());
+        let mut targets = vec![];
+        self.root
+            .buckets(&mut String::new(), &mut |prefix, bucket| {
+                if bucket.last_changed <= since {
+                    targets.push(Key::random_with_prefix(prefix));
+                }
+            });
+        targets
+    }
+
+    /// Number of nodes by the depth of their bucket in the trie, the
+    /// length of the id prefix the bucket covers.
+    pub fn depths(&self) -> BTreeMap<usize, usize> {
+        let mut depths = BTreeMap::new();
+        self.root.depths(0, &mut depths);
+        depths
+    }
+
+    /// Up to `n` known nodes closest to `target` by XOR distance,
+    /// closest first. Nodes from subnets that already have
+    /// `per_bucket_subnet` closer nodes in the result only fill the
+    /// places left over, so one network cannot take over a lookup.
+    pub fn closest(&self, target: &Key, n: usize) -> Vec<&Node> {
+        let mut nodes = self.nodes();
+        nodes.sort_by_key(|node| node.id.distance(target));
+        let mut per_subnet: HashMap<IpAddr, usize> = HashMap::new();
+        let (mut diverse, mut rest) = (Vec::with_capacity(n), vec![]);
+        for node in nodes {
+            if diverse.len() == n {
+                break;
+            }
+            let count = per_subnet.entry(subnet(node.addr.ip())).or_default();
+            if *count < self.limits.per_bucket_subnet {
+                *count += 1;
+                diverse.push(node);
+            } else {
+                rest.push(node);
+            }
+        }
+        let missing = n - diverse.len();
+        diverse.extend(rest.into_iter().take(missing));
+        diverse.sort_by_key(|node| node.id.distance(target));
+        diverse
+    }
+}
+
+impl<'a> IntoIterator for &'a RouteTable {
+    type Item = &'a Node;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// The nodes of a [`RouteTable`], from [`RouteTable::iter`].
+pub struct Iter<'a> {
+    tries: Vec<&'a Trie>,
+    nodes: Option<hash_map::Values<'a, Key, Node>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<&'a Node> {
+        loop {
+            if let Some(node) = self.nodes.as_mut().and_then(Iterator::next) {
+                return Some(node);
+            }
+            let trie = self.tries.pop()?;
+            self.nodes = Some(trie.bucket.nodes.values());
+            self.tries.extend(
+                [&trie.left, &trie.right]
+                    .into_iter()
+                    .flatten()
+                    .map(|t| &**t),
+            );
+        }
+    }
+}
+
+/// How a node has been answering our queries.
+#[derive(Debug, Clone, Default)]
+struct Contact {
+    /// Unanswered queries in a row.
+    failures: u32,
+    last_seen: Option<Instant>,
+    /// Smoothed round-trip time of its answers.
+    rtt: Option<Duration>,
+}
+
+impl Contact {
+    fn status(&self) -> NodeStatus {
+        let recent = self.last_seen.is_some_and(|seen| seen.elapsed() < GOOD_FOR);
+        if self.failures > 1 {
+            NodeStatus::Bad
+        } else if self.failures == 0 && self.rtt.is_some() && recent {
+            NodeStatus::Good
+        } else {
+            NodeStatus::Questionable
+        }
+    }
+}
+
+/// How much a node can be trusted to answer, after BEP 5: good nodes
+/// answered recently, bad ones left several queries in a row unanswered
+/// and the rest are questionable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeStatus {
+    Good,
+    Questionable,
+    Bad,
+}
+
+impl NodeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeStatus::Good => "good",
+            NodeStatus::Questionable => "questionable",
+            NodeStatus::Bad => "bad",
+        }
+    }
+}
+
+/// The buckets of a [`RouteTable`], from [`RouteTable::dump`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDump {
+    pub id: Key,
+    pub nodes: usize,
+    /// In order of prefix.
+    pub buckets: Vec<BucketDump>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketDump {
+    /// The bits, as `0` and `1`, every id in the bucket starts with.
+    pub prefix: String,
+    pub depth: usize,
+    /// When a node last joined, left or moved, in seconds since the Unix
+    /// epoch, or 0 if none ever has.
+    pub last_changed: u64,
+    /// By id.
+    pub nodes: Vec<NodeDump>,
+    /// Nodes waiting for a place in the bucket, latest first.
+    pub replacements: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeDump {
+    pub node: Node,
+    pub status: NodeStatus,
+    pub rtt: Option<Duration>,
+    pub failures: u32,
+}
+
+impl TableDump {
+    /// The dump as a JSON object, with ids in hex and round-trip times in
+    /// milliseconds.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"id\":");
+        json::write_str(&mut out, &hex::encode(self.id.as_bytes()));
+        let _ = write!(out, ",\"nodes\":{},\"buckets\":[", self.nodes);
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"prefix\":");
+            json::write_str(&mut out, &bucket.prefix);
+            let _ = write!(
+                out,
+                ",\"depth\":{},\"last_changed\":{},\"nodes\":[",
+                bucket.depth, bucket.last_changed
+            );
+            for (j, dump) in bucket.nodes.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                write_node(&mut out, &dump.node);
+                out.push_str(",\"status\":");
+                json::write_str(&mut out, dump.status.as_str());
+                let rtt = dump.rtt.map_or("null".to_string(), |rtt| {
+                    format!("{:.3}", rtt.as_secs_f64() * 1000.0)
+                });
+                let _ = write!(out, ",\"rtt_ms\":{},\"failures\":{}}}", rtt, dump.failures);
+            }
+            out.push_str("],\"replacements\":[");
+            for (j, node) in bucket.replacements.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                write_node(&mut out, node);
+                out.push('}');
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
     }
 }
 
 #[derive(Default)]
 pub struct Bucket {
+    /// Seconds since the Unix epoch.
     last_changed: u64,
     nodes: HashMap<Key, Node>,
+    /// Nodes heard from while the bucket was full, oldest first, up to
+    /// k of them.
+    replacements: Vec<Node>,
 }
 
 impl Bucket {
-    fn is_full(&self) -> bool {
-        BUCKET_SIZE == self.nodes.len()
+    fn is_full(&self, k: usize) -> bool {
+        self.nodes.len() >= k
     }
 
-    fn add(&mut self, node: Node) -> bool {
-        if self.is_full() {
+    /// Number of nodes in the subnet of `ip`.
+    fn in_subnet(&self, ip: IpAddr) -> usize {
+        let subnet = subnet(ip);
+        self.nodes
+            .values()
+            .filter(|node| self::subnet(node.addr.ip()) == subnet)
+            .count()
+    }
+
+    fn add(&mut self, node: Node, k: usize) -> bool {
+        if self.is_full(k) {
             return false;
         }
-        self.nodes.insert(node.id.clone(), node);
+        self.nodes.insert(node.id, node);
+        self.last_changed = unix_time();
         true
     }
+
+    /// Keep `node` as the latest replacement, dropping the oldest past
+    /// `k`.
+    fn replace(&mut self, node: Node, k: usize) {
+        self.replacements.retain(|known| known.id != node.id);
+        self.replacements.push(node);
+        if self.replacements.len() > k {
+            self.replacements.remove(0);
+        }
+    }
+}
+
+/// Open a JSON object for `node` with its id and address, for the
+/// caller to add to and close.
+fn write_node(out: &mut String, node: &Node) {
+    out.push_str("{\"id\":");
+    json::write_str(out, &hex::encode(node.id.as_bytes()));
+    out.push_str(",\"addr\":");
+    json::write_str(out, &node.addr.to_string());
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// The /24 (IPv4) or /64 (IPv6) network `ip` is in.
+fn subnet(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => Ipv4Addr::from(u32::from(ip) & !0xff).into(),
+        IpAddr::V6(ip) => Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX)).into(),
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Node {
     id: Key,
     addr: SocketAddr,
@@ -121,19 +753,62 @@ impl Node {
             addr: addr.parse()?,
         })
     }
+
+    pub fn from_parts(id: Key, addr: SocketAddr) -> Self {
+        Node { id, addr }
+    }
+
+    pub fn id(&self) -> &Key {
+        &self.id
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Compact node info (BEP 5): each node's id followed by its compact
+    /// address.
+    pub fn encode_compact<'a>(nodes: impl IntoIterator<Item = &'a Node>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for node in nodes {
+            buf.extend_from_slice(node.id.as_bytes());
+            buf.extend(compact::encode_peer(&node.addr));
+        }
+        buf
+    }
+
+    /// Decode IPv4 compact node info.
+    pub fn decode_compact(buf: &[u8]) -> Result<Vec<Node>> {
+        const LENGTH: usize = KEY_LENGTH + compact::PEER_V4_LENGTH;
+        if !buf.len().is_multiple_of(LENGTH) {
+            return Err(Error::InvalidNetAddr(format!(
+                "compact node info of {} bytes",
+                buf.len()
+            )));
+        }
+        buf.chunks_exact(LENGTH)
+            .map(|c| {
+                Ok(Node {
+                    id: Key::try_from(&c[..KEY_LENGTH])?,
+                    addr: compact::decode_peer(&c[KEY_LENGTH..])?,
+                })
+            })
+            .collect()
+    }
 }
 
 impl Display for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!(
             "Node {{ id: '{}' addr: '{}' }}",
-            self.id.to_string(),
+            self.id,
             self.addr
         ))
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+/// Keys order as big-endian numbers, so distances compare directly.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
 pub struct Key {
     data: [u8; KEY_LENGTH],
 }
@@ -144,13 +819,53 @@ impl Key {
         a.into()
     }
 
+    pub fn random() -> Key {
+        random::bytes().into()
+    }
+
+    /// A random key starting with the bits of `prefix`, a string of `0`s
+    /// and `1`s.
+    fn random_with_prefix(prefix: &str) -> Key {
+        let mut key = Key::random();
+        for (i, bit) in prefix.bytes().enumerate() {
+            let mask = 1 << (7 - i % 8);
+            if bit == b'1' {
+                key.data[i / 8] |= mask;
+            } else {
+                key.data[i / 8] &= !mask;
+            }
+        }
+        key
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_LENGTH] {
+        &self.data
+    }
+
+    /// Bit `i`, counting from the most significant bit of the first byte.
     pub fn bit(&self, i: usize) -> u8 {
-        let div = i << 3;
-        self.data[div] & 1 << 7
+        self.data[i / 8] >> (7 - i % 8) & 1
+    }
+
+    /// The XOR metric of Kademlia.
+    pub fn distance(&self, other: &Key) -> Key {
+        let mut data = self.data;
+        for (d, o) in data.iter_mut().zip(other.data) {
+            *d ^= o;
+        }
+        data.into()
     }
+}
 
-    fn to_string(&self) -> String {
-        String::from_utf8_lossy(&self.data).to_string()
+impl Default for Key {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.data))
     }
 }
 
@@ -167,6 +882,16 @@ impl TryFrom<&str> for Key {
     }
 }
 
+impl TryFrom<&[u8]> for Key {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        Ok(Self {
+            data: value.try_into()?,
+        })
+    }
+}
+
 impl From<[u8; KEY_LENGTH]> for Key {
     fn from(data: [u8; KEY_LENGTH]) -> Self {
         Self { data }
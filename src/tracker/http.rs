@@ -0,0 +1,106 @@
+//! HTTP(S) tracker announces (BEP 3), with compact peer lists (BEP 23) and
+//! IPv6 peers (BEP 7).
+
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use super::{AnnounceRequest, AnnounceResponse};
+use crate::errors::{Error, Result};
+use crate::util::bencode::{decode_ref, ValueRef};
+use crate::util::{compact, url};
+
+/// Tracker responses are small; anything bigger is not worth decoding.
+const MAX_RESPONSE_LENGTH: u64 = 1 << 20;
+
+pub fn announce(
+    tracker: &str,
+    req: &AnnounceRequest,
+    timeout: Duration,
+) -> Result<AnnounceResponse> {
+    let response = ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .build()
+        .get(&announce_url(tracker, req))
+        .call()
+        .map_err(|e| Error::Tracker(e.to_string()))?;
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_RESPONSE_LENGTH)
+        .read_to_end(&mut body)?;
+    parse_response(&body)
+}
+
+/// The announce url with the request in its query string. Binary fields are
+/// percent-encoded byte for byte.
+pub fn announce_url(tracker: &str, req: &AnnounceRequest) -> String {
+    let mut url = format!(
+        "{}{}info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&compact=1",
+        tracker,
+        if tracker.contains('?') { '&' } else { '?' },
+        url::encode(req.info_hash.as_bytes()),
+        url::encode(&req.peer_id),
+        req.port,
+        req.uploaded,
+        req.downloaded,
+        req.left,
+    );
+    if let Some(event) = req.event {
+        url.push_str("&event=");
+        url.push_str(event.as_str());
+    }
+    if let Some(num_want) = req.num_want {
+        url.push_str(&format!("&numwant={}", num_want));
+    }
+    url
+}
+
+/// Parse a bencoded announce response, accepting both compact and
+/// dictionary peer lists.
+pub fn parse_response(body: &[u8]) -> Result<AnnounceResponse> {
+    let resp = decode_ref(body)?;
+    if let Some(reason) = resp.get(b"failure reason") {
+        let reason = reason.as_bytes().unwrap_or_default();
+        return Err(Error::Tracker(String::from_utf8_lossy(reason).into_owned()));
+    }
+    let mut peers = match resp.get(b"peers") {
+        Some(ValueRef::Bytes(b)) => compact::decode_peers_v4(b)?,
+        Some(ValueRef::List(l)) => l.iter().filter_map(dict_peer).collect(),
+        Some(_) => return Err(invalid("peers")),
+        None => vec![],
+    };
+    if let Some(peers6) = resp.get(b"peers6") {
+        let peers6 = peers6.as_bytes().ok_or_else(|| invalid("peers6"))?;
+        peers.extend(compact::decode_peers_v6(peers6)?);
+    }
+    Ok(AnnounceResponse {
+        interval: uint(&resp, b"interval")?.ok_or_else(|| invalid("interval"))?,
+        min_interval: uint(&resp, b"min interval")?,
+        seeders: uint(&resp, b"complete")?,
+        leechers: uint(&resp, b"incomplete")?,
+        peers,
+    })
+}
+
+fn invalid(field: &str) -> Error {
+    Error::Tracker(format!("invalid or missing '{}' in response", field))
+}
+
+fn uint(resp: &ValueRef, key: &[u8]) -> Result<Option<u32>> {
+    resp.get(key)
+        .map(|v| {
+            v.as_int()
+                .and_then(|i| u32::try_from(i).ok())
+                .ok_or_else(|| invalid(&String::from_utf8_lossy(key)))
+        })
+        .transpose()
+}
+
+/// A peer in the original `{ip, port, peer id}` form. The ip may also be a
+/// DNS name, which we skip rather than resolve.
+fn dict_peer(peer: &ValueRef) -> Option<SocketAddr> {
+    let ip = peer.get(b"ip")?.as_str()?.parse::<IpAddr>().ok()?;
+    let port = u16::try_from(peer.get(b"port")?.as_int()?).ok()?;
+    Some(SocketAddr::new(ip, port))
+}
@@ -0,0 +1,90 @@
+//! Tracker clients, so peer discovery is not limited to the DHT, and a
+//! tracker server sharing the DHT's peer store.
+
+pub mod http;
+pub mod server;
+pub mod udp;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::errors::{Error, Result};
+use crate::server::route_table::Key;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Event {
+    Started,
+    Stopped,
+    Completed,
+}
+
+impl Event {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::Started => "started",
+            Event::Stopped => "stopped",
+            Event::Completed => "completed",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AnnounceRequest {
+    pub info_hash: Key,
+    pub peer_id: [u8; 20],
+    pub port: u16,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    /// `None` for the regular announces between `Started` and `Stopped`.
+    pub event: Option<Event>,
+    pub num_want: Option<u32>,
+}
+
+impl AnnounceRequest {
+    /// A regular announce with no transfer statistics, which is all an
+    /// indexer that never downloads has to report.
+    pub fn new(info_hash: Key, peer_id: [u8; 20], port: u16) -> Self {
+        Self {
+            info_hash,
+            peer_id,
+            port,
+            uploaded: 0,
+            downloaded: 0,
+            left: 0,
+            event: None,
+            num_want: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AnnounceResponse {
+    /// Seconds to wait before the next regular announce.
+    pub interval: u32,
+    pub min_interval: Option<u32>,
+    pub seeders: Option<u32>,
+    pub leechers: Option<u32>,
+    pub peers: Vec<SocketAddr>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ScrapeStats {
+    pub seeders: u32,
+    /// Number of times the torrent has been downloaded to completion.
+    pub completed: u32,
+    pub leechers: u32,
+}
+
+/// Announce to the tracker at `url`, picking the protocol from its scheme.
+/// This makes a single attempt waiting up to `timeout`; use
+/// [`udp::UdpTracker`] directly for BEP 15 retransmission.
+pub fn announce(url: &str, req: &AnnounceRequest, timeout: Duration) -> Result<AnnounceResponse> {
+    match url.split_once("://").map(|(scheme, _)| scheme) {
+        Some("http" | "https") => http::announce(url, req, timeout),
+        Some("udp") => udp::UdpTracker::new(url)?
+            .with_backoff(timeout, 0)
+            .announce(req),
+        _ => Err(Error::Tracker(format!("unsupported tracker url '{}'", url))),
+    }
+}
@@ -0,0 +1,381 @@
+//! A small HTTP and UDP tracker answering from the DHT's peer store.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::udp::{ANNOUNCE, CONNECT, ERROR, PROTOCOL_ID, SCRAPE};
+use super::{AnnounceRequest, AnnounceResponse, Event, ScrapeStats};
+use crate::errors::{Error, Result};
+use crate::server::peer_store::PeerStore;
+use crate::server::route_table::Key;
+use crate::util::bencode::Encoder;
+use crate::util::{compact, http, url};
+
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    /// Interval clients are told to wait between announces.
+    pub interval: Duration,
+    /// Regular announces sooner than this after the last one are refused.
+    pub min_interval: Duration,
+    /// Most peers returned by one announce.
+    pub max_peers: usize,
+    /// HTTP connections served at once; more are closed right away.
+    pub max_connections: usize,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30 * 60),
+            min_interval: Duration::from_secs(5 * 60),
+            max_peers: 50,
+            max_connections: 64,
+        }
+    }
+}
+
+pub struct TrackerServer {
+    store: Arc<PeerStore>,
+    config: TrackerConfig,
+    /// Keys the hash that makes UDP connection ids.
+    secret: RandomState,
+}
+
+impl TrackerServer {
+    pub fn new(store: Arc<PeerStore>, config: TrackerConfig) -> Self {
+        Self {
+            store,
+            config,
+            secret: RandomState::new(),
+        }
+    }
+
+    /// Handle an announce from `from`, whose address is used for the peer
+    /// instead of anything the client claims.
+    pub fn announce(&self, req: &AnnounceRequest, from: SocketAddr) -> Result<AnnounceResponse> {
+        let addr = SocketAddr::new(from.ip(), req.port);
+        if req.event.is_none() {
+            let last = self.store.last_announce(&req.info_hash, &addr);
+            if last.is_some_and(|at| at.elapsed() < self.config.min_interval) {
+                return Err(Error::Tracker("announce interval too short".into()));
+            }
+        }
+        let stored = match req.event {
+            Some(Event::Stopped) => {
+                self.store.remove(&req.info_hash, &addr);
+                true
+            }
+            Some(Event::Completed) => self.store.complete(req.info_hash, addr),
+            _ => self.store.announce(req.info_hash, addr, req.left == 0),
+        };
+        if !stored {
+            return Err(Error::Tracker("tracker is full".into()));
+        }
+        let want = req
+            .num_want
+            .map_or(self.config.max_peers, |n| n as usize)
+            .min(self.config.max_peers);
+        let stats = self.store.stats(&req.info_hash);
+        Ok(AnnounceResponse {
+            interval: self.config.interval.as_secs() as u32,
+            min_interval: Some(self.config.min_interval.as_secs() as u32),
+            seeders: Some(stats.seeders),
+            leechers: Some(stats.leechers),
+            peers: self
+                .store
+                .peers(&req.info_hash, want + 1)
+                .into_iter()
+                .filter(|peer| *peer != addr)
+                .take(want)
+                .collect(),
+        })
+    }
+
+    pub fn scrape(&self, info_hashes: &[Key]) -> Vec<ScrapeStats> {
+        info_hashes.iter().map(|h| self.store.stats(h)).collect()
+    }
+
+    /// Accept HTTP connections until the listener fails, serving each on
+    /// its own thread. Past [`max_connections`] at once, new connections
+    /// are closed unanswered.
+    ///
+    /// [`max_connections`]: TrackerConfig::max_connections
+    pub fn serve_http(self: &Arc<Self>, listener: TcpListener) -> Result<()> {
+        let server = Arc::clone(self);
+        let max_connections = self.config.max_connections;
+        http::serve(listener, max_connections, move |target, from| {
+            let (status, body) = server.http_response(target, from);
+            (status, "text/plain", body)
+        })
+    }
+
+    /// The status and body answering `target`, the path and query of an
+    /// HTTP GET.
+    pub fn http_response(&self, target: &str, from: SocketAddr) -> (&'static str, Vec<u8>) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let body = match path {
+            "/announce" => parse_http_announce(query)
+                .and_then(|req| self.announce(&req, from))
+                .and_then(|resp| encode_http_announce(&resp)),
+            "/scrape" => parse_info_hashes(query).and_then(|hashes| {
+                let stats = self.scrape(&hashes);
+                encode_http_scrape(&hashes, &stats)
+            }),
+            _ => return ("404 Not Found", vec![]),
+        };
+        // Trackers report errors in a 200 response for clients to show.
+        let body = body.or_else(|e| {
+            let mut enc = Encoder::new(Vec::new());
+            enc.begin_dict()?;
+            enc.write_str("failure reason")?;
+            enc.write_str(&reason(e))?;
+            enc.end()?;
+            Ok::<_, Error>(enc.into_inner())
+        });
+        ("200 OK", body.unwrap_or_default())
+    }
+
+    /// Answer UDP tracker packets until the socket fails.
+    pub fn serve_udp(&self, socket: UdpSocket) -> Result<()> {
+        let mut buf = [0; 1500];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf)?;
+            if let Some(resp) = self.handle_udp(&buf[..len], from) {
+                // One unreachable client must not stop the tracker.
+                let _ = socket.send_to(&resp, from);
+            }
+        }
+    }
+
+    /// The response to one BEP 15 packet, or `None` for packets that are
+    /// not worth answering.
+    pub fn handle_udp(&self, packet: &[u8], from: SocketAddr) -> Option<Vec<u8>> {
+        if packet.len() < 16 {
+            return None;
+        }
+        let connection_id = u64::from_be_bytes(packet[..8].try_into().ok()?);
+        let action = u32::from_be_bytes(packet[8..12].try_into().ok()?);
+        let tid = &packet[12..16];
+        let body = &packet[16..];
+        let mut resp = Vec::new();
+        let result = match action {
+            CONNECT if connection_id == PROTOCOL_ID => {
+                resp.extend_from_slice(&self.connection_id(from, 0).to_be_bytes());
+                Ok(())
+            }
+            _ if !self.valid_connection_id(connection_id, from) => {
+                Err(Error::Tracker("invalid connection id".into()))
+            }
+            ANNOUNCE => parse_udp_announce(body)
+                .and_then(|req| self.announce(&req, from))
+                .map(|r| encode_udp_announce(&r, from, &mut resp)),
+            SCRAPE => {
+                let hashes = body
+                    .chunks_exact(20)
+                    .map(Key::try_from)
+                    .collect::<Result<Vec<_>>>();
+                hashes.map(|hashes| {
+                    for stats in self.scrape(&hashes) {
+                        for v in [stats.seeders, stats.completed, stats.leechers] {
+                            resp.extend_from_slice(&v.to_be_bytes());
+                        }
+                    }
+                })
+            }
+            _ => return None,
+        };
+        let (action, resp) = match result {
+            Ok(()) => (action, resp),
+            Err(e) => (ERROR, reason(e).into_bytes()),
+        };
+        let mut packet = action.to_be_bytes().to_vec();
+        packet.extend_from_slice(tid);
+        packet.extend(resp);
+        Some(packet)
+    }
+
+    /// Connection ids are a keyed hash of the client address and the
+    /// current minute, so they need no state and expire on their own.
+    fn connection_id(&self, from: SocketAddr, minutes_ago: u64) -> u64 {
+        let minute = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / 60;
+        self.secret
+            .hash_one((from, minute.wrapping_sub(minutes_ago)))
+    }
+
+    /// Ids stay valid for one to two minutes, as BEP 15 asks.
+    fn valid_connection_id(&self, id: u64, from: SocketAddr) -> bool {
+        id == self.connection_id(from, 0) || id == self.connection_id(from, 1)
+    }
+}
+
+/// The message shown to clients for a failed request.
+fn reason(e: Error) -> String {
+    match e {
+        Error::Tracker(msg) => msg,
+        e => e.to_string(),
+    }
+}
+
+/// Split a query string into decoded key/value pairs.
+fn query_pairs(query: &str) -> Result<Vec<(&str, Vec<u8>)>> {
+    query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (k, v) = p.split_once('=').unwrap_or((p, ""));
+            Ok((k, url::decode(v)?))
+        })
+        .collect()
+}
+
+fn parse_http_announce(query: &str) -> Result<AnnounceRequest> {
+    let pairs = query_pairs(query)?;
+    let field = |name: &str| {
+        pairs
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v.as_slice())
+            .ok_or_else(|| Error::Tracker(format!("missing {}", name)))
+    };
+    let number = |name: &str| -> Result<u64> {
+        std::str::from_utf8(field(name)?)
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| Error::Tracker(format!("invalid {}", name)))
+    };
+    let info_hash = Key::try_from(field("info_hash")?)
+        .map_err(|_| Error::Tracker("invalid info_hash".into()))?;
+    let peer_id = field("peer_id")?
+        .try_into()
+        .map_err(|_| Error::Tracker("invalid peer_id".into()))?;
+    let port = u16::try_from(number("port")?).map_err(|_| Error::Tracker("invalid port".into()))?;
+    let mut req = AnnounceRequest::new(info_hash, peer_id, port);
+    req.uploaded = number("uploaded").unwrap_or(0);
+    req.downloaded = number("downloaded").unwrap_or(0);
+    // Without it a leecher would count as a seed.
+    req.left = number("left")?;
+    req.event = match field("event").unwrap_or_default() {
+        b"started" => Some(Event::Started),
+        b"stopped" => Some(Event::Stopped),
+        b"completed" => Some(Event::Completed),
+        _ => None,
+    };
+    req.num_want = number("numwant").ok().and_then(|n| u32::try_from(n).ok());
+    Ok(req)
+}
+
+fn parse_info_hashes(query: &str) -> Result<Vec<Key>> {
+    query_pairs(query)?
+        .into_iter()
+        .filter(|(k, _)| *k == "info_hash")
+        .map(|(_, v)| {
+            Key::try_from(v.as_slice()).map_err(|_| Error::Tracker("invalid info_hash".into()))
+        })
+        .collect()
+}
+
+fn encode_http_announce(resp: &AnnounceResponse) -> Result<Vec<u8>> {
+    let (v4, v6): (Vec<_>, Vec<_>) = resp.peers.iter().partition(|p| p.is_ipv4());
+    let mut e = Encoder::new(Vec::new());
+    e.begin_dict()?;
+    e.write_str("complete")?;
+    e.write_int(resp.seeders.unwrap_or(0))?;
+    e.write_str("incomplete")?;
+    e.write_int(resp.leechers.unwrap_or(0))?;
+    e.write_str("interval")?;
+    e.write_int(resp.interval)?;
+    if let Some(min_interval) = resp.min_interval {
+        e.write_str("min interval")?;
+        e.write_int(min_interval)?;
+    }
+    e.write_str("peers")?;
+    e.write_bytes(
+        &v4.into_iter()
+            .flat_map(compact::encode_peer)
+            .collect::<Vec<_>>(),
+    )?;
+    if !v6.is_empty() {
+        e.write_str("peers6")?;
+        e.write_bytes(
+            &v6.into_iter()
+                .flat_map(compact::encode_peer)
+                .collect::<Vec<_>>(),
+        )?;
+    }
+    e.end()?;
+    Ok(e.into_inner())
+}
+
+fn encode_http_scrape(hashes: &[Key], stats: &[ScrapeStats]) -> Result<Vec<u8>> {
+    let mut files: Vec<_> = hashes.iter().zip(stats).collect();
+    files.sort_by_key(|(hash, _)| **hash);
+    files.dedup_by_key(|(hash, _)| **hash);
+    let mut e = Encoder::new(Vec::new());
+    e.begin_dict()?;
+    e.write_str("files")?;
+    e.begin_dict()?;
+    for (hash, stats) in files {
+        e.write_bytes(hash.as_bytes())?;
+        e.begin_dict()?;
+        e.write_str("complete")?;
+        e.write_int(stats.seeders)?;
+        e.write_str("downloaded")?;
+        e.write_int(stats.completed)?;
+        e.write_str("incomplete")?;
+        e.write_int(stats.leechers)?;
+        e.end()?;
+    }
+    e.end()?;
+    e.end()?;
+    Ok(e.into_inner())
+}
+
+fn parse_udp_announce(body: &[u8]) -> Result<AnnounceRequest> {
+    // info_hash, peer_id, downloaded, left, uploaded, event, ip, key,
+    // num_want, port.
+    if body.len() < 82 {
+        return Err(Error::Tracker("truncated announce".into()));
+    }
+    let u64_at = |i: usize| u64::from_be_bytes(body[i..i + 8].try_into().unwrap_or_default());
+    let u32_at = |i: usize| u32::from_be_bytes(body[i..i + 4].try_into().unwrap_or_default());
+    let mut req = AnnounceRequest::new(
+        Key::try_from(&body[..20])?,
+        body[20..40].try_into()?,
+        u16::from_be_bytes([body[80], body[81]]),
+    );
+    req.downloaded = u64_at(40);
+    req.left = u64_at(48);
+    req.uploaded = u64_at(56);
+    req.event = match u32_at(64) {
+        1 => Some(Event::Completed),
+        2 => Some(Event::Started),
+        3 => Some(Event::Stopped),
+        _ => None,
+    };
+    // -1 asks for the default.
+    req.num_want = u32::try_from(u32_at(76) as i32).ok();
+    Ok(req)
+}
+
+/// Peers in the address family the request came in on, as BEP 15 asks.
+fn encode_udp_announce(resp: &AnnounceResponse, from: SocketAddr, out: &mut Vec<u8>) {
+    for v in [
+        resp.interval,
+        resp.leechers.unwrap_or(0),
+        resp.seeders.unwrap_or(0),
+    ] {
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+    for peer in &resp.peers {
+        if peer.is_ipv4() == from.is_ipv4() {
+            out.extend(compact::encode_peer(peer));
+        }
+    }
+}
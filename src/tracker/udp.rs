@@ -0,0 +1,232 @@
+//! UDP tracker protocol (BEP 15).
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::{AnnounceRequest, AnnounceResponse, Event, ScrapeStats};
+use crate::errors::{Error, Result};
+use crate::server::route_table::Key;
+use crate::util::{compact, random};
+
+pub(super) const PROTOCOL_ID: u64 = 0x0417_2710_1980;
+pub(super) const CONNECT: u32 = 0;
+pub(super) const ANNOUNCE: u32 = 1;
+pub(super) const SCRAPE: u32 = 2;
+pub(super) const ERROR: u32 = 3;
+
+/// BEP 15 waits `15 * 2^n` seconds for the n-th retransmission, up to 8.
+pub const BASE_TIMEOUT: Duration = Duration::from_secs(15);
+pub const MAX_RETRIES: u32 = 8;
+/// The longest wait for one response, BEP 15's last.
+const MAX_TIMEOUT: Duration = Duration::from_secs(15 << MAX_RETRIES);
+/// How long a tracker honours a connection id.
+const CONNECTION_LIFETIME: Duration = Duration::from_secs(60);
+/// Info hashes per scrape request, so the packet fits the usual MTU.
+pub const MAX_SCRAPE: usize = 74;
+const MAX_PACKET_LENGTH: usize = 65_535;
+
+/// A client for one UDP tracker, caching its connection id between
+/// requests.
+pub struct UdpTracker {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    connection: Option<(u64, Instant)>,
+    base_timeout: Duration,
+    max_retries: u32,
+}
+
+impl UdpTracker {
+    /// Resolve a `udp://host:port[/path]` url and bind a socket of the
+    /// matching address family.
+    pub fn new(url: &str) -> Result<Self> {
+        let host = url
+            .strip_prefix("udp://")
+            .map(|rest| rest.split('/').next().unwrap_or_default())
+            .ok_or_else(|| Error::Tracker(format!("not a udp tracker url '{}'", url)))?;
+        let addr = host
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::InvalidNetAddr(host.to_string()))?;
+        Self::with_addr(addr)
+    }
+
+    pub fn with_addr(addr: SocketAddr) -> Result<Self> {
+        let local: SocketAddr = if addr.is_ipv4() {
+            "0.0.0.0:0".parse()?
+        } else {
+            "[::]:0".parse()?
+        };
+        Ok(Self {
+            socket: UdpSocket::bind(local)?,
+            addr,
+            connection: None,
+            base_timeout: BASE_TIMEOUT,
+            max_retries: MAX_RETRIES,
+        })
+    }
+
+    /// Wait `base * 2^n` for the n-th retransmission instead of BEP 15's
+    /// 15 seconds, giving up after `max_retries` retransmissions. Retries
+    /// are capped at [`MAX_RETRIES`] and each wait at BEP 15's longest.
+    pub fn with_backoff(mut self, base: Duration, max_retries: u32) -> Self {
+        self.base_timeout = base;
+        self.max_retries = max_retries.min(MAX_RETRIES);
+        self
+    }
+
+    pub fn announce(&mut self, req: &AnnounceRequest) -> Result<AnnounceResponse> {
+        let connection_id = self.connection_id()?;
+        let resp = self.transact(ANNOUNCE, |tid| {
+            let mut buf = header(connection_id, ANNOUNCE, tid);
+            buf.extend_from_slice(req.info_hash.as_bytes());
+            buf.extend_from_slice(&req.peer_id);
+            buf.extend_from_slice(&req.downloaded.to_be_bytes());
+            buf.extend_from_slice(&req.left.to_be_bytes());
+            buf.extend_from_slice(&req.uploaded.to_be_bytes());
+            buf.extend_from_slice(&event_id(req.event).to_be_bytes());
+            // Let the tracker use the address the packet came from.
+            buf.extend_from_slice(&0u32.to_be_bytes());
+            buf.extend_from_slice(&(random::u64() as u32).to_be_bytes());
+            let num_want = req.num_want.and_then(|n| i32::try_from(n).ok());
+            buf.extend_from_slice(&num_want.unwrap_or(-1).to_be_bytes());
+            buf.extend_from_slice(&req.port.to_be_bytes());
+            buf
+        })?;
+        if resp.len() < 12 {
+            return Err(truncated());
+        }
+        // Peers come in the address family of the tracker connection.
+        let peers = &resp[12..];
+        Ok(AnnounceResponse {
+            interval: u32_at(&resp, 0),
+            min_interval: None,
+            leechers: Some(u32_at(&resp, 4)),
+            seeders: Some(u32_at(&resp, 8)),
+            peers: if self.addr.is_ipv4() {
+                compact::decode_peers_v4(peers)?
+            } else {
+                compact::decode_peers_v6(peers)?
+            },
+        })
+    }
+
+    /// Scrape up to [`MAX_SCRAPE`] torrents, returning their stats in the
+    /// order asked for.
+    pub fn scrape(&mut self, info_hashes: &[Key]) -> Result<Vec<ScrapeStats>> {
+        if info_hashes.len() > MAX_SCRAPE {
+            return Err(Error::Tracker(format!(
+                "cannot scrape more than {} torrents at once",
+                MAX_SCRAPE
+            )));
+        }
+        let connection_id = self.connection_id()?;
+        let resp = self.transact(SCRAPE, |tid| {
+            let mut buf = header(connection_id, SCRAPE, tid);
+            for info_hash in info_hashes {
+                buf.extend_from_slice(info_hash.as_bytes());
+            }
+            buf
+        })?;
+        if resp.len() != info_hashes.len() * 12 {
+            return Err(truncated());
+        }
+        Ok(resp
+            .chunks_exact(12)
+            .map(|c| ScrapeStats {
+                seeders: u32_at(c, 0),
+                completed: u32_at(c, 4),
+                leechers: u32_at(c, 8),
+            })
+            .collect())
+    }
+
+    /// The cached connection id, or a fresh one once it has expired.
+    fn connection_id(&mut self) -> Result<u64> {
+        if let Some((id, at)) = self.connection {
+            if at.elapsed() < CONNECTION_LIFETIME {
+                return Ok(id);
+            }
+        }
+        let resp = self.transact(CONNECT, |tid| header(PROTOCOL_ID, CONNECT, tid))?;
+        let id = resp
+            .get(..8)
+            .ok_or_else(truncated)?
+            .try_into()
+            .map(u64::from_be_bytes)?;
+        self.connection = Some((id, Instant::now()));
+        Ok(id)
+    }
+
+    /// Send the packet built by `request` until a response to it arrives,
+    /// backing off exponentially, and return the response body after the
+    /// action and transaction id.
+    fn transact(&mut self, action: u32, request: impl Fn(u32) -> Vec<u8>) -> Result<Vec<u8>> {
+        let tid = random::u64() as u32;
+        let packet = request(tid);
+        let mut buf = vec![0; MAX_PACKET_LENGTH];
+        for n in 0..=self.max_retries {
+            self.socket.send_to(&packet, self.addr)?;
+            let timeout = self.base_timeout.saturating_mul(1 << n).min(MAX_TIMEOUT);
+            let deadline = Instant::now() + timeout;
+            while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                if wait.is_zero() {
+                    break;
+                }
+                self.socket.set_read_timeout(Some(wait))?;
+                let (len, from) = match self.socket.recv_from(&mut buf) {
+                    Ok(r) => r,
+                    Err(e) if is_timeout(&e) => break,
+                    Err(e) => return Err(e.into()),
+                };
+                if from != self.addr || len < 8 || u32_at(&buf, 4) != tid {
+                    continue;
+                }
+                return match u32_at(&buf, 0) {
+                    a if a == action => Ok(buf[8..len].to_vec()),
+                    ERROR => {
+                        // The error may be about our connection id, so the
+                        // next request gets a fresh one.
+                        self.connection = None;
+                        Err(Error::Tracker(
+                            String::from_utf8_lossy(&buf[8..len]).into_owned(),
+                        ))
+                    }
+                    a => Err(Error::Tracker(format!("unexpected action {}", a))),
+                };
+            }
+        }
+        Err(Error::Tracker(format!("{} did not respond", self.addr)))
+    }
+}
+
+fn header(connection_id: u64, action: u32, tid: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(98);
+    buf.extend_from_slice(&connection_id.to_be_bytes());
+    buf.extend_from_slice(&action.to_be_bytes());
+    buf.extend_from_slice(&tid.to_be_bytes());
+    buf
+}
+
+fn event_id(event: Option<Event>) -> u32 {
+    match event {
+        None => 0,
+        Some(Event::Completed) => 1,
+        Some(Event::Started) => 2,
+        Some(Event::Stopped) => 3,
+    }
+}
+
+fn u32_at(buf: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]])
+}
+
+fn truncated() -> Error {
+    Error::Tracker("truncated response".into())
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::de::{self, Deserialize, DeserializeSeed, IntoDeserializer, Visitor};
+
+use super::{Decoder, Limits, Value};
+use crate::errors::{Error, Result};
+
+/// Deserialize a `T` from a complete bencoded buffer.
+///
+/// Strings and byte buffers are borrowed from `input` where the target type
+/// allows it. Trailing bytes after the value are an error.
+pub fn from_bytes<'de, T: Deserialize<'de>>(input: &'de [u8]) -> Result<T> {
+    let mut de = Deserializer::new(input);
+    let value = T::deserialize(&mut de)?;
+    de.end()?;
+    Ok(value)
+}
+
+pub struct Deserializer<'de> {
+    decoder: Decoder<'de>,
+}
+
+impl<'de> Deserializer<'de> {
+    pub fn new(input: &'de [u8]) -> Self {
+        Self {
+            decoder: Decoder::new(input),
+        }
+    }
+
+    /// A deserializer enforcing `limits`, see [`Decoder::with_limits`].
+    pub fn with_limits(input: &'de [u8], limits: Limits) -> Result<Self> {
+        Ok(Self {
+            decoder: Decoder::with_limits(input, limits)?,
+        })
+    }
+
+    /// Only accept canonical bencode, see [`Decoder::strict`].
+    pub fn strict(self) -> Self {
+        Self {
+            decoder: self.decoder.strict(),
+        }
+    }
+
+    /// Check that the whole input has been consumed.
+    pub fn end(&self) -> Result<()> {
+        self.decoder.end()
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        self.decoder.error(msg)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.decoder.peek()
+    }
+
+    fn parse_int(&mut self) -> Result<i128> {
+        self.decoder.parse_int()
+    }
+
+    fn parse_bytes(&mut self) -> Result<&'de [u8]> {
+        self.decoder.parse_bytes()
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.peek() {
+            Some(b'i') => {
+                let v = self.parse_int()?;
+                if let Ok(v) = u64::try_from(v) {
+                    return visitor.visit_u64(v);
+                }
+                let v = i64::try_from(v).map_err(|_| self.error("integer out of range"))?;
+                visitor.visit_i64(v)
+            }
+            Some(b'0'..=b'9') => {
+                let bytes = self.parse_bytes()?;
+                match std::str::from_utf8(bytes) {
+                    Ok(s) => visitor.visit_borrowed_str(s),
+                    Err(_) => visitor.visit_borrowed_bytes(bytes),
+                }
+            }
+            Some(b'l') => self.deserialize_seq(visitor),
+            Some(b'd') => self.deserialize_map(visitor),
+            Some(_) => Err(self.error("invalid bencode value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.parse_int()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(self.error("boolean must be i0e or i1e")),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(self.error("bencode does not support floats"))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(self.error("bencode does not support floats"))
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let bytes = self.parse_bytes()?;
+        match std::str::from_utf8(bytes) {
+            Ok(s) => visitor.visit_borrowed_str(s),
+            Err(_) => visitor.visit_borrowed_bytes(bytes),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(self.parse_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        // Bencode has no null; absent dict entries become `None` through the
+        // derived missing-field handling, so anything present is `Some`.
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.decoder.enter(b'l')?;
+        let value = visitor.visit_seq(Access::new(self))?;
+        self.decoder.leave()?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.decoder.enter(b'd')?;
+        let value = visitor.visit_map(Access::new(self))?;
+        self.decoder.leave()?;
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.peek() {
+            Some(b'0'..=b'9') => {
+                let bytes = self.parse_bytes()?;
+                let variant =
+                    std::str::from_utf8(bytes).map_err(|_| self.error("invalid enum variant"))?;
+                visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(variant))
+            }
+            Some(b'd') => {
+                self.decoder.enter(b'd')?;
+                let value = visitor.visit_enum(Access::new(&mut *self))?;
+                self.decoder.leave()?;
+                Ok(value)
+            }
+            _ => Err(self.error("expected enum as string or dict")),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 char ignored_any
+    }
+}
+
+/// Element, entry and variant access over a list or dictionary body.
+struct Access<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    prev_key: Option<&'de [u8]>,
+}
+
+impl<'a, 'de> Access<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>) -> Self {
+        Self { de, prev_key: None }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if !self.de.decoder.next_item("unterminated list")? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if !self.de.decoder.next_item("unterminated dict")? {
+            return Ok(None);
+        }
+        // Check the raw key on a lookahead copy; the seed then parses it
+        // again into whatever type it wants.
+        let key = self.de.decoder.clone().parse_key(self.prev_key)?;
+        self.prev_key = Some(key);
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Err(self.de.error("unit variant must be encoded as a string"))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(&mut *self.de, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(&mut *self.de, visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: de::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a bencode value")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> std::result::Result<Value, E> {
+        Ok(Value::Integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<Value, E> {
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map_err(|_| E::custom("integer out of range"))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Value, E> {
+        Ok(Value::String(v.into()))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> std::result::Result<Value, E> {
+        String::from_utf8(v.to_vec())
+            .map(Value::String)
+            .map_err(|_| E::custom("string is not valid utf-8"))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Value, A::Error> {
+        let mut list = Vec::new();
+        while let Some(v) = seq.next_element()? {
+            list.push(v);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> std::result::Result<Value, A::Error> {
+        let mut dict = BTreeMap::new();
+        while let Some((k, v)) = map.next_entry()? {
+            dict.insert(k, v);
+        }
+        Ok(Value::Dict(dict))
+    }
+}
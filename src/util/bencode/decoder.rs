@@ -0,0 +1,308 @@
+use std::io::Write;
+use std::str;
+
+use super::limits::Budget;
+use super::{Encoder, Limits, Value};
+use crate::errors::{Error, Result};
+
+/// A bencode value borrowing its strings from the decoded buffer.
+///
+/// Dictionaries keep their entries in input order as a flat list, which
+/// avoids a tree allocation per dict on the hot KRPC path.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ValueRef<'a> {
+    Bytes(&'a [u8]),
+    Integer(i64),
+    List(Vec<ValueRef<'a>>),
+    Dict(Vec<(&'a [u8], ValueRef<'a>)>),
+}
+
+impl<'a> ValueRef<'a> {
+    /// Look up `key` in a dictionary. Of duplicate keys, which only
+    /// lenient decoding lets through, the last wins, as in the owned
+    /// [`decode`](super::decode).
+    pub fn get(&self, key: &[u8]) -> Option<&ValueRef<'a>> {
+        match self {
+            ValueRef::Dict(d) => d.iter().rev().find(|(k, _)| *k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            ValueRef::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|b| str::from_utf8(b).ok())
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            ValueRef::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[ValueRef<'a>]> {
+        match self {
+            ValueRef::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    /// Encode into `w`, reproducing the decoded bytes.
+    pub fn encode_to<W: Write>(&self, w: W) -> Result<()> {
+        Encoder::new(w).write_value_ref(self)
+    }
+
+    /// Copy into an owned [`Value`], failing on strings that are not utf-8.
+    pub fn to_value(&self) -> Result<Value> {
+        Ok(match self {
+            ValueRef::Bytes(_) => Value::String(self.as_str().ok_or(Error::InvalidValue)?.into()),
+            ValueRef::Integer(i) => Value::Integer(*i),
+            ValueRef::List(l) => {
+                Value::List(l.iter().map(|v| v.to_value()).collect::<Result<_>>()?)
+            }
+            ValueRef::Dict(d) => Value::Dict(
+                d.iter()
+                    .map(|(k, v)| {
+                        let k = str::from_utf8(k).map_err(|_| Error::InvalidValue)?;
+                        Ok((k.to_string(), v.to_value()?))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+}
+
+/// Decode a complete buffer into a [`ValueRef`] without copying strings.
+pub fn decode_ref(input: &[u8]) -> Result<ValueRef<'_>> {
+    let mut decoder = Decoder::new(input);
+    let value = decoder.decode()?;
+    decoder.end()?;
+    Ok(value)
+}
+
+/// Like [`decode_ref`], but only accepts canonical bencode.
+///
+/// See [`Decoder::strict`] for what is rejected.
+pub fn decode_strict(input: &[u8]) -> Result<ValueRef<'_>> {
+    let mut decoder = Decoder::new(input).strict();
+    let value = decoder.decode()?;
+    decoder.end()?;
+    Ok(value)
+}
+
+/// Byte-level bencode reader shared by the borrowed and serde decoders.
+#[derive(Clone)]
+pub struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    strict: bool,
+    budget: Budget,
+}
+
+impl<'a> Decoder<'a> {
+    /// A decoder with the default [`Limits`].
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            pos: 0,
+            strict: false,
+            budget: Budget::new(Limits::default()),
+        }
+    }
+
+    /// A decoder enforcing `limits`, failing straight away if `input`
+    /// itself is over the size limit.
+    pub fn with_limits(input: &'a [u8], limits: Limits) -> Result<Self> {
+        let budget = Budget::new(limits);
+        budget.input(input.len())?;
+        Ok(Self {
+            budget,
+            ..Self::new(input)
+        })
+    }
+
+    /// Reject non-canonical encodings: integers and string lengths with
+    /// leading zeros, `i-0e`, and dict keys that are not strictly ascending.
+    /// Signed data such as BEP 44 items must be re-encoded bit for bit, so
+    /// anything with more than one encoding is refused.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Offset of the next unread byte.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Check that the whole input has been consumed.
+    pub fn end(&self) -> Result<()> {
+        if self.pos != self.input.len() {
+            return Err(self.error("trailing data"));
+        }
+        Ok(())
+    }
+
+    /// Decode the next value.
+    pub fn decode(&mut self) -> Result<ValueRef<'a>> {
+        match self.peek() {
+            Some(b'i') => {
+                let start = self.pos;
+                let v = self.parse_int()?;
+                let v =
+                    i64::try_from(v).map_err(|_| self.error_at(start, "integer out of range"))?;
+                Ok(ValueRef::Integer(v))
+            }
+            Some(b'0'..=b'9') => Ok(ValueRef::Bytes(self.parse_bytes()?)),
+            Some(b'l') => {
+                self.enter(b'l')?;
+                let mut list = Vec::new();
+                while self.next_item("unterminated list")? {
+                    list.push(self.decode()?);
+                }
+                self.leave()?;
+                Ok(ValueRef::List(list))
+            }
+            Some(b'd') => {
+                self.enter(b'd')?;
+                let mut dict: Vec<(&'a [u8], ValueRef<'a>)> = Vec::new();
+                while self.next_item("unterminated dict")? {
+                    let key = self.parse_key(dict.last().map(|(k, _)| *k))?;
+                    dict.push((key, self.decode()?));
+                }
+                self.leave()?;
+                Ok(ValueRef::Dict(dict))
+            }
+            Some(_) => Err(self.error("invalid bencode value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// Consume the opening byte of a list or dict and go one level deeper.
+    pub(crate) fn enter(&mut self, open: u8) -> Result<()> {
+        self.expect(open)?;
+        self.budget.enter()
+    }
+
+    /// Consume the closing `e` of a list or dict.
+    pub(crate) fn leave(&mut self) -> Result<()> {
+        self.expect(b'e')?;
+        self.budget.leave();
+        Ok(())
+    }
+
+    /// Whether another list item or dict entry follows, without consuming
+    /// the closing `e`. Each item counts against the element limit.
+    pub(crate) fn next_item(&mut self, unterminated: &str) -> Result<bool> {
+        match self.peek() {
+            Some(b'e') => Ok(false),
+            Some(_) => self.budget.element().map(|_| true),
+            None => Err(self.error(unterminated)),
+        }
+    }
+
+    /// Parse a dict key; in strict mode it must sort after `prev`.
+    pub(crate) fn parse_key(&mut self, prev: Option<&[u8]>) -> Result<&'a [u8]> {
+        let start = self.pos;
+        if !self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            return Err(self.error("dict key must be a string"));
+        }
+        let key = self.parse_bytes()?;
+        if let (true, Some(prev)) = (self.strict, prev) {
+            if key == prev {
+                return Err(self.error_at(start, "duplicate dict key"));
+            }
+            if key < prev {
+                return Err(self.error_at(start, "dict keys out of order"));
+            }
+        }
+        Ok(key)
+    }
+
+    pub(crate) fn error(&self, msg: &str) -> Error {
+        self.error_at(self.pos, msg)
+    }
+
+    fn error_at(&self, pos: usize, msg: &str) -> Error {
+        Error::BencodeParseError(format!("{} at byte {}", msg, pos))
+    }
+
+    pub(crate) fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    pub(crate) fn expect(&mut self, byte: u8) -> Result<()> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Consume a run of digits, rejecting leading zeros in strict mode.
+    fn digits(&mut self) -> Result<&'a [u8]> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits = &self.input[start..self.pos];
+        if self.strict && digits.len() > 1 && digits[0] == b'0' {
+            return Err(self.error_at(start, "leading zero"));
+        }
+        Ok(digits)
+    }
+
+    /// Parse `i<n>e`. The result is wide enough for the full `i64` and `u64`
+    /// ranges so callers pick their own bounds.
+    pub(crate) fn parse_int(&mut self) -> Result<i128> {
+        self.expect(b'i')?;
+        let start = self.pos;
+        let negative = self.peek() == Some(b'-');
+        if negative {
+            self.pos += 1;
+        }
+        let digits = self.digits()?;
+        if digits.is_empty() {
+            return Err(self.error("expected digits"));
+        }
+        if self.strict && negative && digits == b"0" {
+            return Err(self.error_at(start, "negative zero"));
+        }
+        // Only ascii digits were consumed, so this cannot fail.
+        let value = str::from_utf8(digits)
+            .unwrap_or_default()
+            .parse::<u64>()
+            .map_err(|e| self.error_at(start, &e.to_string()))?;
+        self.expect(b'e')?;
+        Ok(if negative {
+            -i128::from(value)
+        } else {
+            i128::from(value)
+        })
+    }
+
+    pub(crate) fn parse_bytes(&mut self) -> Result<&'a [u8]> {
+        let digits = self.digits()?;
+        if digits.is_empty() {
+            return Err(self.error("expected string length"));
+        }
+        let len = str::from_utf8(digits)
+            .unwrap_or_default()
+            .parse::<usize>()
+            .map_err(|e| self.error(&e.to_string()))?;
+        self.expect(b':')?;
+        self.budget.string(len)?;
+        if self.input.len() - self.pos < len {
+            return Err(self.error(&format!("string of length {} overruns input", len)));
+        }
+        let bytes = &self.input[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::Write;
+
+use super::{Value, ValueRef};
+use crate::errors::Result;
+
+/// Writes bencode tokens straight into an [`io::Write`](std::io::Write).
+///
+/// The encoder does not track structure: callers open lists and dicts with
+/// [`begin_list`](Encoder::begin_list) / [`begin_dict`](Encoder::begin_dict),
+/// close them with [`end`](Encoder::end) and are responsible for writing
+/// dict keys in sorted order.
+pub struct Encoder<W> {
+    writer: W,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub fn write_int(&mut self, v: impl Into<i128>) -> Result<()> {
+        let v = v.into();
+        write!(self.writer, "i{}e", v)?;
+        Ok(())
+    }
+
+    pub fn write_bytes(&mut self, v: &[u8]) -> Result<()> {
+        write!(self.writer, "{}:", v.len())?;
+        self.writer.write_all(v)?;
+        Ok(())
+    }
+
+    pub fn write_str(&mut self, v: &str) -> Result<()> {
+        self.write_bytes(v.as_bytes())
+    }
+
+    pub fn begin_list(&mut self) -> Result<()> {
+        self.writer.write_all(b"l")?;
+        Ok(())
+    }
+
+    pub fn begin_dict(&mut self) -> Result<()> {
+        self.writer.write_all(b"d")?;
+        Ok(())
+    }
+
+    pub fn end(&mut self) -> Result<()> {
+        self.writer.write_all(b"e")?;
+        Ok(())
+    }
+
+    pub fn write_value(&mut self, v: &Value) -> Result<()> {
+        match v {
+            Value::String(s) => self.write_str(s),
+            Value::Integer(i) => self.write_int(*i),
+            Value::List(l) => {
+                self.begin_list()?;
+                for v in l {
+                    self.write_value(v)?;
+                }
+                self.end()
+            }
+            Value::Dict(d) => {
+                self.begin_dict()?;
+                for (k, v) in d {
+                    self.write_str(k)?;
+                    self.write_value(v)?;
+                }
+                self.end()
+            }
+        }
+    }
+
+    /// Write a borrowed value, keeping dict entries in their stored order so
+    /// decoded input is reproduced byte for byte.
+    pub fn write_value_ref(&mut self, v: &ValueRef) -> Result<()> {
+        match v {
+            ValueRef::Bytes(b) => self.write_bytes(b),
+            ValueRef::Integer(i) => self.write_int(*i),
+            ValueRef::List(l) => {
+                self.begin_list()?;
+                for v in l {
+                    self.write_value_ref(v)?;
+                }
+                self.end()
+            }
+            ValueRef::Dict(d) => {
+                self.begin_dict()?;
+                for (k, v) in d {
+                    self.write_bytes(k)?;
+                    self.write_value_ref(v)?;
+                }
+                self.end()
+            }
+        }
+    }
+}
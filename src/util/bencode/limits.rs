@@ -0,0 +1,94 @@
+use crate::errors::{Error, Result};
+
+/// Bounds on the work a decoder will do for a single input.
+///
+/// Elements count every list item and dict entry, so together with the
+/// depth bound they cap both the recursion and the allocations an attacker
+/// can trigger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub max_depth: usize,
+    pub max_str_len: usize,
+    pub max_elements: usize,
+    pub max_input: usize,
+}
+
+impl Default for Limits {
+    /// Only nesting is bounded, which is what trusted local input such as a
+    /// `.torrent` file needs.
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_str_len: usize::MAX,
+            max_elements: usize::MAX,
+            max_input: usize::MAX,
+        }
+    }
+}
+
+impl Limits {
+    /// Bounds for one untrusted UDP datagram, such as a KRPC message.
+    pub fn datagram() -> Self {
+        Self {
+            max_depth: 16,
+            max_str_len: 65_535,
+            max_elements: 1024,
+            max_input: 65_535,
+        }
+    }
+}
+
+/// Running counters checked against [`Limits`] while decoding.
+#[derive(Debug, Clone)]
+pub(crate) struct Budget {
+    limits: Limits,
+    depth: usize,
+    elements: usize,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            depth: 0,
+            elements: 0,
+        }
+    }
+
+    pub(crate) fn input(&self, len: usize) -> Result<()> {
+        if len > self.limits.max_input {
+            return Err(Error::BencodeInputTooLarge(self.limits.max_input));
+        }
+        Ok(())
+    }
+
+    /// Enter a list or dict.
+    pub(crate) fn enter(&mut self) -> Result<()> {
+        if self.depth >= self.limits.max_depth {
+            return Err(Error::BencodeTooDeep(self.limits.max_depth));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Account for one more list item or dict entry.
+    pub(crate) fn element(&mut self) -> Result<()> {
+        if self.elements >= self.limits.max_elements {
+            return Err(Error::BencodeTooManyElements(self.limits.max_elements));
+        }
+        self.elements += 1;
+        Ok(())
+    }
+
+    /// Check a string's claimed length before reading it.
+    pub(crate) fn string(&self, len: usize) -> Result<()> {
+        if len > self.limits.max_str_len {
+            return Err(Error::BencodeStringTooLong(self.limits.max_str_len));
+        }
+        Ok(())
+    }
+}
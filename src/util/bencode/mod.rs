@@ -0,0 +1,263 @@
+use std::{collections::BTreeMap, io::Write, iter::Peekable};
+
+use crate::errors::{Error, Result};
+
+#[cfg(feature = "serde")]
+mod de;
+mod decoder;
+mod encoder;
+mod limits;
+#[cfg(feature = "serde")]
+mod ser;
+
+#[cfg(feature = "serde")]
+pub use self::de::{from_bytes, Deserializer};
+pub use self::decoder::{decode_ref, decode_strict, Decoder, ValueRef};
+pub use self::encoder::Encoder;
+use self::limits::Budget;
+pub use self::limits::Limits;
+#[cfg(feature = "serde")]
+pub use self::ser::{to_bytes, Serializer};
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    List(Vec<Value>),
+    Dict(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn encode(self) -> Result<String> {
+        let mut buf = Vec::new();
+        self.encode_to(&mut buf)?;
+        // Every string in a `Value` is utf-8, so the encoding is too.
+        String::from_utf8(buf).map_err(|_| Error::InvalidValue)
+    }
+
+    /// Encode into `w` without building intermediate strings.
+    pub fn encode_to<W: Write>(&self, w: W) -> Result<()> {
+        Encoder::new(w).write_value(self)
+    }
+}
+
+impl TryFrom<&str> for Value {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        Ok(Value::String(value.into()))
+    }
+}
+
+impl TryInto<String> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<String> {
+        if let Value::String(v) = self {
+            return Ok(v);
+        }
+        Err(Error::InvalidValue)
+    }
+}
+
+impl TryInto<Vec<u8>> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<Vec<u8>> {
+        if let Value::String(v) = self {
+            return Ok(v.into_bytes());
+        }
+        Err(Error::InvalidValue)
+    }
+}
+
+impl TryInto<u8> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<u8> {
+        if let Value::Integer(v) = self {
+            return u8::try_from(v).map_err(|_| Error::InvalidValue);
+        }
+        Err(Error::InvalidValue)
+    }
+}
+
+impl TryInto<u64> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<u64> {
+        if let Value::Integer(v) = self {
+            return u64::try_from(v).map_err(|_| Error::InvalidValue);
+        }
+        Err(Error::InvalidValue)
+    }
+}
+
+impl TryInto<Vec<String>> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<Vec<String>> {
+        if let Value::List(v) = self {
+            return Ok(v.into_iter().filter_map(|x| x.try_into().ok()).collect());
+        }
+        Err(Error::InvalidValue)
+    }
+}
+
+impl TryInto<Vec<Vec<u8>>> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<Vec<Vec<u8>>> {
+        if let Value::List(v) = self {
+            return Ok(v.into_iter().filter_map(|x| x.try_into().ok()).collect());
+        }
+        Err(Error::InvalidValue)
+    }
+}
+
+pub fn decode<I: Iterator<Item = char>>(chars: &mut Peekable<I>) -> Result<Value> {
+    decode_with_limits(chars, Limits::default())
+}
+
+/// Decode one value while enforcing `limits`.
+///
+/// The input size limit cannot be checked up front on an iterator, so
+/// callers holding the whole input should compare its length themselves.
+pub fn decode_with_limits<I: Iterator<Item = char>>(
+    chars: &mut Peekable<I>,
+    limits: Limits,
+) -> Result<Value> {
+    parse_value(chars, &mut Budget::new(limits))
+}
+
+fn parse_value<I: Iterator<Item = char>>(
+    chars: &mut Peekable<I>,
+    budget: &mut Budget,
+) -> Result<Value> {
+    match chars.peek() {
+        Some('i') => parse_int(chars),
+        Some(c) if c.is_ascii_digit() => parse_str(chars, budget),
+        Some('d') => parse_dict(chars, budget),
+        Some('l') => parse_list(chars, budget),
+        _ => Err(Error::BencodeParseError(format!(
+            "invalid bencode content: {}",
+            chars.collect::<String>()
+        ))),
+    }
+}
+
+fn parse_int<I: Iterator<Item = char>>(peek: &mut Peekable<I>) -> Result<Value> {
+    let mut value = String::new();
+    if peek.next_if_eq(&'i').is_none() {
+        return Err(Error::BencodeParseError("invalid bencode format".into()));
+    }
+    if let Some(c) = peek.next_if_eq(&'-') {
+        value.push(c);
+    }
+    while let Some(c) = peek.next_if(|c| c.is_ascii_digit()) {
+        value.push(c);
+    }
+    if peek.next_if_eq(&'e').is_some() {
+        return Ok(Value::Integer(value.parse::<i64>()?));
+    }
+    Err(Error::BencodeParseError("invalid bencode format".into()))
+}
+
+fn parse_str<I: Iterator<Item = char>>(
+    peek: &mut Peekable<I>,
+    budget: &mut Budget,
+) -> Result<Value> {
+    let mut value = String::new();
+    while let Some(c) = peek.next_if(|c| c.is_ascii_digit()) {
+        value.push(c);
+    }
+    let size = value.parse::<usize>()?;
+    if peek.next_if_eq(&':').is_none() {
+        return Err(Error::BencodeParseError("invalid bencode format".into()));
+    }
+    budget.string(size)?;
+    // The length counts utf-8 bytes, not chars.
+    let mut value = String::new();
+    while value.len() < size {
+        match peek.next() {
+            Some(c) => value.push(c),
+            None => break,
+        }
+    }
+    if value.len() != size {
+        return Err(Error::BencodeParseError(format!(
+            "invalid string len want {} got {}",
+            size,
+            value.len()
+        )));
+    }
+    Ok(Value::String(value))
+}
+
+fn parse_list<I: Iterator<Item = char>>(
+    peek: &mut Peekable<I>,
+    budget: &mut Budget,
+) -> Result<Value> {
+    if peek.next_if_eq(&'l').is_none() {
+        return Err(Error::BencodeParseError(
+            "invalid list format of start".into(),
+        ));
+    }
+    budget.enter()?;
+    let mut list = Vec::new();
+    loop {
+        match peek.peek() {
+            Some(c) if *c == 'e' => {
+                peek.next();
+                break;
+            }
+            Some(_) => {
+                budget.element()?;
+                list.push(parse_value(peek, budget)?)
+            }
+            None => {
+                return Err(Error::BencodeParseError(
+                    "invalid list format of end".into(),
+                ))
+            }
+        }
+    }
+    budget.leave();
+    Ok(Value::List(list))
+}
+
+fn parse_dict<I: Iterator<Item = char>>(
+    peek: &mut Peekable<I>,
+    budget: &mut Budget,
+) -> Result<Value> {
+    if peek.next_if_eq(&'d').is_none() {
+        return Err(Error::BencodeParseError(
+            "invalid dict format of start".into(),
+        ));
+    }
+    budget.enter()?;
+    let mut map = BTreeMap::new();
+
+    loop {
+        match peek.peek() {
+            Some(c) if *c == 'e' => {
+                peek.next();
+                break;
+            }
+            Some(c) if c.is_ascii_digit() => {
+                budget.element()?;
+                if let Value::String(key) = parse_str(peek, budget)? {
+                    map.insert(key, parse_value(peek, budget)?);
+                }
+            }
+            Some(_) => return Err(Error::BencodeParseError("dict key must be a string".into())),
+            None => {
+                return Err(Error::BencodeParseError(
+                    "invalid dict format of end".into(),
+                ))
+            }
+        }
+    }
+    budget.leave();
+    Ok(Value::Dict(map))
+}
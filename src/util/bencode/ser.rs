@@ -0,0 +1,434 @@
+use std::io::Write;
+use std::ops::Range;
+
+use serde::ser::{self, Serialize};
+
+use super::Value;
+use crate::errors::{Error, Result};
+
+/// Serialize `value` into a bencoded byte buffer.
+///
+/// Dictionary keys are written in sorted order and `None` values are
+/// omitted, so derived structs produce canonical bencode. Bencode has no
+/// null, so `None` anywhere else, and `()`, are errors.
+pub fn to_bytes<T: ?Sized + Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut ser = Serializer::new();
+    value.serialize(&mut ser)?;
+    Ok(ser.into_inner())
+}
+
+#[derive(Default)]
+pub struct Serializer {
+    buf: Vec<u8>,
+    /// Whether the value being serialized is a dict value, which a `None`
+    /// leaves out.
+    in_dict: bool,
+}
+
+impl Serializer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn write_int<T: std::fmt::Display>(&mut self, v: T) -> Result<()> {
+        write!(self.buf, "i{}e", v).map_err(|e| Error::Serde(e.to_string()))
+    }
+
+    fn write_bytes(&mut self, v: &[u8]) -> Result<()> {
+        write!(self.buf, "{}:", v.len()).map_err(|e| Error::Serde(e.to_string()))?;
+        self.buf.extend_from_slice(v);
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = DictSerializer<'a>;
+    type SerializeStruct = DictSerializer<'a>;
+    type SerializeStructVariant = DictSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_int(v as u8)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Err(Error::Serde("bencode does not support floats".into()))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Err(Error::Serde("bencode does not support floats".into()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.write_bytes(v.encode_utf8(&mut [0; 4]).as_bytes())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.write_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        if std::mem::take(&mut self.in_dict) {
+            return Ok(());
+        }
+        Err(Error::Serde("bencode has no null".into()))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Err(Error::Serde("bencode does not support ()".into()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Err(Error::Serde("bencode does not support unit structs".into()))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.write_bytes(variant.as_bytes())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.in_dict = false;
+        self.buf.push(b'd');
+        self.write_bytes(variant.as_bytes())?;
+        value.serialize(&mut *self)?;
+        self.buf.push(b'e');
+        Ok(())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>> {
+        self.buf.push(b'l');
+        Ok(Compound {
+            ser: self,
+            variant: false,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>> {
+        self.buf.push(b'd');
+        self.write_bytes(variant.as_bytes())?;
+        self.buf.push(b'l');
+        Ok(Compound {
+            ser: self,
+            variant: true,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<DictSerializer<'a>> {
+        DictSerializer::new(self, None)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<DictSerializer<'a>> {
+        DictSerializer::new(self, None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<DictSerializer<'a>> {
+        DictSerializer::new(self, Some(variant))
+    }
+}
+
+/// Serializes lists, tuples and tuple variants straight into the buffer.
+pub struct Compound<'a> {
+    ser: &'a mut Serializer,
+    variant: bool,
+}
+
+impl Compound<'_> {
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.ser.in_dict = false;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn finish(self) -> Result<()> {
+        self.ser.buf.push(b'e');
+        if self.variant {
+            self.ser.buf.push(b'e');
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+/// Writes dictionary entries into the buffer as they come, then puts
+/// them in order of raw key bytes, as bencode requires.
+pub struct DictSerializer<'a> {
+    ser: &'a mut Serializer,
+    variant: bool,
+    /// Where the first entry starts.
+    start: usize,
+    /// Each key with where its entry is in the buffer.
+    entries: Vec<(Vec<u8>, Range<usize>)>,
+    key: Option<Vec<u8>>,
+}
+
+impl<'a> DictSerializer<'a> {
+    fn new(ser: &'a mut Serializer, variant: Option<&'static str>) -> Result<Self> {
+        if let Some(variant) = variant {
+            ser.buf.push(b'd');
+            ser.write_bytes(variant.as_bytes())?;
+        }
+        ser.buf.push(b'd');
+        Ok(Self {
+            start: ser.buf.len(),
+            ser,
+            variant: variant.is_some(),
+            entries: vec![],
+            key: None,
+        })
+    }
+
+    fn entry<T: ?Sized + Serialize>(&mut self, key: Vec<u8>, value: &T) -> Result<()> {
+        let start = self.ser.buf.len();
+        self.ser.write_bytes(&key)?;
+        let value_start = self.ser.buf.len();
+        self.ser.in_dict = true;
+        let written = value.serialize(&mut *self.ser);
+        self.ser.in_dict = false;
+        written?;
+        // Every value but `None` writes something; `None` means the entry
+        // is absent.
+        if self.ser.buf.len() == value_start {
+            self.ser.buf.truncate(start);
+            return Ok(());
+        }
+        self.entries.push((key, start..self.ser.buf.len()));
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if !self.entries.windows(2).all(|w| w[0].0 < w[1].0) {
+            self.entries.sort_by(|a, b| a.0.cmp(&b.0));
+            if self.entries.windows(2).any(|w| w[0].0 == w[1].0) {
+                return Err(Error::Serde("duplicate dict key".into()));
+            }
+            let written = self.ser.buf.split_off(self.start);
+            for (_, range) in &self.entries {
+                self.ser
+                    .buf
+                    .extend_from_slice(&written[range.start - self.start..range.end - self.start]);
+            }
+        }
+        self.ser.buf.push(b'e');
+        if self.variant {
+            self.ser.buf.push(b'e');
+        }
+        Ok(())
+    }
+}
+
+/// Serialize a dictionary key, which must come out as a bencode string.
+fn key_bytes<T: ?Sized + Serialize>(key: &T) -> Result<Vec<u8>> {
+    let encoded = to_bytes(key)?;
+    let colon = encoded
+        .iter()
+        .position(|b| *b == b':')
+        .filter(|_| encoded.first().is_some_and(u8::is_ascii_digit))
+        .ok_or_else(|| Error::Serde("dict keys must be strings".into()))?;
+    Ok(encoded[colon + 1..].to_vec())
+}
+
+impl ser::SerializeMap for DictSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(key_bytes(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::Serde("dict value without a key".into()))?;
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for DictSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.entry(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for DictSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.entry(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Integer(i) => serializer.serialize_i64(*i),
+            Value::List(l) => l.serialize(serializer),
+            Value::Dict(d) => d.serialize(serializer),
+        }
+    }
+}
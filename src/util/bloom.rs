@@ -0,0 +1,92 @@
+//! Bloom filters of IP addresses, used by DHT scrapes (BEP 33) to
+//! estimate swarm sizes without listing peers.
+
+use std::net::IpAddr;
+
+use sha1::{Digest, Sha1};
+
+use crate::errors::{Error, Result};
+
+pub const BLOOM_LENGTH: usize = 256;
+const BITS: usize = BLOOM_LENGTH * 8;
+
+/// A 2048-bit filter with two hash functions, both taken from the SHA-1
+/// of the address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: [u8; BLOOM_LENGTH],
+}
+
+impl BloomFilter {
+    pub fn new() -> Self {
+        Self {
+            bits: [0; BLOOM_LENGTH],
+        }
+    }
+
+    pub fn insert(&mut self, ip: IpAddr) {
+        for i in indices(ip) {
+            self.bits[i / 8] |= 1 << (i % 8);
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        indices(ip)
+            .into_iter()
+            .all(|i| self.bits[i / 8] & (1 << (i % 8)) != 0)
+    }
+
+    /// Add every address in `other`, as when merging filters from
+    /// several nodes.
+    pub fn union(&mut self, other: &BloomFilter) {
+        for (b, o) in self.bits.iter_mut().zip(other.bits) {
+            *b |= o;
+        }
+    }
+
+    /// Estimated number of distinct addresses inserted.
+    pub fn estimate(&self) -> f64 {
+        let zeros = self.bits.iter().map(|b| b.count_zeros()).sum::<u32>();
+        // A full filter says little beyond "very many".
+        let zeros = f64::from(zeros.max(1));
+        let m = BITS as f64;
+        (zeros / m).ln() / (2.0 * (1.0 - 1.0 / m).ln())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|b| *b == 0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; BLOOM_LENGTH] {
+        &self.bits
+    }
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TryFrom<&[u8]> for BloomFilter {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        Ok(Self {
+            bits: value.try_into().map_err(|_| {
+                Error::InvalidEncoding(format!("bloom filter of {} bytes", value.len()))
+            })?,
+        })
+    }
+}
+
+fn indices(ip: IpAddr) -> [usize; 2] {
+    let hash = match ip {
+        IpAddr::V4(ip) => Sha1::digest(ip.octets()),
+        IpAddr::V6(ip) => Sha1::digest(ip.octets()),
+    };
+    [
+        usize::from(u16::from_le_bytes([hash[0], hash[1]])) % BITS,
+        usize::from(u16::from_le_bytes([hash[2], hash[3]])) % BITS,
+    ]
+}
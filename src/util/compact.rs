@@ -0,0 +1,52 @@
+//! Compact peer addresses: 4 or 16 address bytes followed by a big-endian
+//! port, as used by trackers (BEP 23, BEP 7) and the DHT.
+
+use std::net::{IpAddr, SocketAddr};
+
+use crate::errors::{Error, Result};
+
+pub const PEER_V4_LENGTH: usize = 6;
+pub const PEER_V6_LENGTH: usize = 18;
+
+pub fn decode_peers_v4(buf: &[u8]) -> Result<Vec<SocketAddr>> {
+    decode(buf, PEER_V4_LENGTH)
+}
+
+pub fn decode_peers_v6(buf: &[u8]) -> Result<Vec<SocketAddr>> {
+    decode(buf, PEER_V6_LENGTH)
+}
+
+/// Decode one peer, telling the address family from the length.
+pub fn decode_peer(buf: &[u8]) -> Result<SocketAddr> {
+    let (ip, port) = buf.split_at(buf.len().saturating_sub(2));
+    let ip = match ip.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(ip)?),
+        16 => IpAddr::from(<[u8; 16]>::try_from(ip)?),
+        _ => {
+            return Err(Error::InvalidNetAddr(format!(
+                "compact peer of {} bytes",
+                buf.len()
+            )))
+        }
+    };
+    Ok(SocketAddr::new(ip, u16::from_be_bytes([port[0], port[1]])))
+}
+
+pub fn encode_peer(addr: &SocketAddr) -> Vec<u8> {
+    let mut buf = match addr.ip() {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    buf.extend_from_slice(&addr.port().to_be_bytes());
+    buf
+}
+
+fn decode(buf: &[u8], len: usize) -> Result<Vec<SocketAddr>> {
+    if !buf.len().is_multiple_of(len) {
+        return Err(Error::InvalidNetAddr(format!(
+            "compact peer list of {} bytes",
+            buf.len()
+        )));
+    }
+    buf.chunks_exact(len).map(decode_peer).collect()
+}
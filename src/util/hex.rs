@@ -1,2 +1,86 @@
+//! Text encodings for binary identifiers such as info hashes.
 
+use crate::errors::{Error, Result};
 
+const HEX: &[u8; 16] = b"0123456789abcdef";
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Lowercase hex.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push(HEX[(b >> 4) as usize] as char);
+        s.push(HEX[(b & 0xf) as usize] as char);
+    }
+    s
+}
+
+/// Decode hex in either case.
+pub fn decode(s: &str) -> Result<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return Err(invalid("hex", s));
+    }
+    s.as_bytes()
+        .chunks_exact(2)
+        .map(|pair| {
+            let hi = hex_digit(pair[0]).ok_or_else(|| invalid("hex", s))?;
+            let lo = hex_digit(pair[1]).ok_or_else(|| invalid("hex", s))?;
+            Ok(hi << 4 | lo)
+        })
+        .collect()
+}
+
+/// RFC 4648 base32 without padding, as used by magnet links.
+pub fn encode_base32(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let (mut buf, mut bits) = (0u32, 0);
+    for b in bytes {
+        buf = buf << 8 | u32::from(*b);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            s.push(BASE32[(buf >> bits & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        s.push(BASE32[(buf << (5 - bits) & 0x1f) as usize] as char);
+    }
+    s
+}
+
+/// Decode base32 in either case, ignoring trailing `=` padding.
+pub fn decode_base32(s: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buf, mut bits) = (0u32, 0);
+    for c in s.trim_end_matches('=').bytes() {
+        let v = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return Err(invalid("base32", s)),
+        };
+        buf = buf << 5 | u32::from(v);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buf >> bits) as u8);
+        }
+    }
+    // Leftover bits must be zero padding of the last byte.
+    if bits >= 5 || buf & ((1 << bits) - 1) != 0 {
+        return Err(invalid("base32", s));
+    }
+    Ok(out)
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn invalid(encoding: &str, s: &str) -> Error {
+    Error::InvalidEncoding(format!("{} '{}'", encoding, s))
+}
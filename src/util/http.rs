@@ -0,0 +1,98 @@
+//! Just enough of an HTTP/1.1 server to answer GET requests, for the
+//! tracker and the admin endpoint.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::errors::Result;
+
+const MAX_REQUEST_LINE: u64 = 8 * 1024;
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to back off after a failed accept, which is most often the
+/// process running out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The status, content type and body of a response.
+pub type Response = (&'static str, &'static str, Vec<u8>);
+
+/// Accept connections on `listener`, answering each GET with `handler`,
+/// which gets the request target and the client's address. Failed accepts
+/// are logged and retried after a short pause.
+/// Every connection is served on its own thread, up to `max_connections`
+/// at once; more are closed unanswered.
+pub fn serve<F>(listener: TcpListener, max_connections: usize, handler: F) -> Result<()>
+where
+    F: Fn(&str, SocketAddr) -> Response + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!(error = %e, "accept failed");
+                thread::sleep(ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+        if connections.fetch_add(1, Ordering::SeqCst) >= max_connections {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            continue;
+        }
+        let handler = Arc::clone(&handler);
+        let connections = Arc::clone(&connections);
+        thread::spawn(move || {
+            // A client hanging up early is not our problem.
+            let _ = handle(stream, &*handler);
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, handler: &impl Fn(&str, SocketAddr) -> Response) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let from = stream.peer_addr()?;
+    let (status, content_type, body) = match read_request(&stream)? {
+        Some(target) => handler(&target, from),
+        None => ("400 Bad Request", "text/plain", vec![]),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+/// The target of a GET request, or `None` for any other request.
+fn read_request(stream: &TcpStream) -> Result<Option<String>> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    (&mut reader)
+        .take(MAX_REQUEST_LINE)
+        .read_line(&mut request_line)?;
+    // Drain the headers so closing the socket does not reset it.
+    let mut header = String::new();
+    while (&mut reader)
+        .take(MAX_REQUEST_LINE)
+        .read_line(&mut header)?
+        > 2
+    {
+        header.clear();
+    }
+    Ok(match request_line.split(' ').collect::<Vec<_>>()[..] {
+        ["GET", target, _] => Some(target.to_string()),
+        _ => None,
+    })
+}
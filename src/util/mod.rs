@@ -1,5 +1,11 @@
 pub mod bencode;
+pub mod bloom;
+pub mod compact;
 pub mod hex;
+pub mod http;
+pub mod json;
+pub mod random;
+pub mod url;
 
 #[macro_export]
 macro_rules! hashmap {
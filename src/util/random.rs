@@ -0,0 +1,19 @@
+//! Unpredictable values for node ids, tokens and transaction ids.
+//!
+//! std seeds every `RandomState` from the OS, which is enough to keep other
+//! hosts from guessing them without pulling in an RNG crate.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+pub fn u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+pub fn bytes<const N: usize>() -> [u8; N] {
+    let mut out = [0; N];
+    for chunk in out.chunks_mut(8) {
+        chunk.copy_from_slice(&u64().to_le_bytes()[..chunk.len()]);
+    }
+    out
+}
@@ -0,0 +1,46 @@
+//! Percent-encoding for URI query components.
+
+use super::hex;
+use crate::errors::{Error, Result};
+
+/// Percent-encode everything but RFC 3986 unreserved characters, so raw
+/// binary such as an info hash survives in a query string.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            s.push(b as char);
+        } else {
+            s.push_str(&format!("%{:02X}", b));
+        }
+    }
+    s
+}
+
+/// Decode `%XX` escapes and `+` as a space.
+pub fn decode(s: &str) -> Result<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let escape = s
+                    .get(i + 1..i + 3)
+                    .and_then(|h| hex::decode(h).ok())
+                    .ok_or_else(|| Error::InvalidEncoding(format!("url '{}'", s)))?;
+                out.extend(escape);
+                i += 3;
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    Ok(out)
+}
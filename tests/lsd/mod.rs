@@ -0,0 +1,112 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rdht::lsd::Lsd;
+use rdht::server::route_table::Key;
+use rdht::server::Server;
+use rdht::util::hex;
+
+/// Two members of a test group on the loopback interface, listening on
+/// their own threads.
+fn pair(port: u16) -> (Arc<Lsd>, Arc<Lsd>) {
+    let group = SocketAddr::from((Ipv4Addr::new(239, 192, 152, 143), port));
+    let member = || {
+        let lsd = Arc::new(Lsd::on_interface(group, Ipv4Addr::LOCALHOST).unwrap());
+        let runner = Arc::clone(&lsd);
+        thread::spawn(move || runner.run());
+        lsd
+    };
+    (member(), member())
+}
+
+/// Poll `f` until it returns something non-empty.
+fn eventually<T>(f: impl Fn() -> Vec<T>) -> Vec<T> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let found = f();
+        if !found.is_empty() || Instant::now() > deadline {
+            return found;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_multicast_announce() {
+    let (a, b) = pair(26771);
+    let info_hashes: Vec<_> = (0..30).map(|i| Key::from([i; 20])).collect();
+    a.announce(&info_hashes, 6881).unwrap();
+
+    let peer: SocketAddr = "127.0.0.1:6881".parse().unwrap();
+    assert_eq!(eventually(|| b.peers(&Key::from([29; 20]))), vec![peer]);
+    assert_eq!(b.peers(&Key::from([0; 20])), vec![peer]);
+    // Our own announcement comes back and is ignored.
+    assert_eq!(a.peers(&Key::from([0; 20])), vec![]);
+
+    let server = Server::new("127.0.0.1:0", vec![])
+        .unwrap()
+        .with_lsd(Arc::clone(&b));
+    assert_eq!(server.get_peers(&Key::from([0; 20])), Ok(vec![peer]));
+    assert!(server.get_peers(&Key::from([99; 20])).is_err());
+}
+
+#[test]
+fn test_run_announces() {
+    let (a, b) = pair(26773);
+    a.add_torrent(Key::from([1; 20]), 6881);
+    a.add_torrent(Key::from([2; 20]), 6882);
+    let announcer = Arc::clone(&a);
+    thread::spawn(move || announcer.run_announces());
+    let peers = eventually(|| b.peers(&Key::from([2; 20])));
+    assert_eq!(peers, vec!["127.0.0.1:6882".parse().unwrap()]);
+    assert_eq!(
+        eventually(|| b.peers(&Key::from([1; 20]))),
+        vec!["127.0.0.1:6881".parse().unwrap()]
+    );
+    assert!(a.remove_torrent(&Key::from([1; 20])));
+    assert!(!a.remove_torrent(&Key::from([1; 20])));
+}
+
+#[test]
+fn test_limits() {
+    let group = SocketAddr::from((Ipv4Addr::new(239, 192, 152, 143), 26774));
+    let lsd = Lsd::on_interface(group, Ipv4Addr::LOCALHOST)
+        .unwrap()
+        .with_limits(1, 1);
+    for (i, from) in ["192.168.1.5:6771", "192.168.1.6:6771"].iter().enumerate() {
+        let packet = format!(
+            "BT-SEARCH * HTTP/1.1\r\nPort: 1\r\nInfohash: {}\r\n\r\n\r\n",
+            hex::encode(&[i as u8; 20])
+        );
+        lsd.handle(packet.as_bytes(), from.parse().unwrap())
+            .unwrap();
+    }
+    assert_eq!(lsd.peers(&Key::from([0; 20])).len(), 1);
+    assert_eq!(lsd.peers(&Key::from([1; 20])), vec![]);
+}
+
+#[test]
+fn test_handle() {
+    let (lsd, _) = pair(26772);
+    let from: SocketAddr = "192.168.1.5:6771".parse().unwrap();
+    let info_hash = "0101010101010101010101010101010101010101";
+    let packet = format!(
+        "BT-SEARCH * HTTP/1.1\r\nHost: 239.192.152.143:6771\r\nport: 51413\r\nInfohash: {}\r\n\r\n\r\n",
+        info_hash.to_uppercase()
+    );
+    lsd.handle(packet.as_bytes(), from).unwrap();
+    assert_eq!(
+        lsd.peers(&Key::from([1; 20])),
+        vec!["192.168.1.5:51413".parse().unwrap()]
+    );
+
+    for packet in [
+        "NOTIFY * HTTP/1.1\r\nPort: 1\r\n\r\n",
+        "BT-SEARCH * HTTP/1.1\r\nInfohash: 0101010101010101010101010101010101010101\r\n\r\n",
+        "BT-SEARCH * HTTP/1.1\r\nPort: 1\r\nInfohash: 0101\r\n\r\n",
+    ] {
+        assert!(lsd.handle(packet.as_bytes(), from).is_err(), "{}", packet);
+    }
+}
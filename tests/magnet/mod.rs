@@ -0,0 +1,94 @@
+use rdht::errors::Error;
+use rdht::magnet::Magnet;
+use rdht::metainfo::Metainfo;
+use rdht::server::route_table::Key;
+use rdht::util::hex;
+
+const HASH: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+fn key(s: &str) -> Key {
+    let bytes: [u8; 20] = hex::decode(s).unwrap().try_into().unwrap();
+    bytes.into()
+}
+
+#[test]
+fn test_parse() {
+    let m: Magnet = format!(
+        "magnet:?xt=urn:btih:{}&dn=Some+File%20Name&tr=udp%3A%2F%2Ftracker.example%3A1337\
+         &tr.1=http://t2/announce&x.pe=10.0.0.1:6881&x.pe=%5B::1%5D:51413&so=0,2,4-6&foo=bar",
+        HASH
+    )
+    .parse()
+    .unwrap();
+    assert_eq!(m.info_hash, key(HASH));
+    assert_eq!(m.display_name.as_deref(), Some("Some File Name"));
+    assert_eq!(
+        m.trackers,
+        vec!["udp://tracker.example:1337", "http://t2/announce"]
+    );
+    assert_eq!(
+        m.peers,
+        vec![("10.0.0.1".to_string(), 6881), ("::1".to_string(), 51413)]
+    );
+    assert_eq!(m.select_only, vec![0..=0, 2..=2, 4..=6]);
+}
+
+#[test]
+fn test_parse_base32() {
+    let base32 = hex::encode_base32(&hex::decode(HASH).unwrap());
+    assert_eq!(base32.len(), 32);
+    let m: Magnet = format!("magnet:?xt=urn:btih:{}", base32.to_lowercase())
+        .parse()
+        .unwrap();
+    assert_eq!(m, Magnet::new(key(HASH)));
+}
+
+#[test]
+fn test_round_trip() {
+    let m = Magnet {
+        display_name: Some("a b&c".into()),
+        trackers: vec!["udp://t:80/announce".into()],
+        peers: vec![("::1".into(), 1), ("host".into(), 2)],
+        select_only: vec![1..=1, 3..=5],
+        ..Magnet::new(key(HASH))
+    };
+    let link = m.to_string();
+    assert_eq!(
+        link,
+        format!(
+            "magnet:?xt=urn:btih:{}&dn=a%20b%26c&tr=udp%3A%2F%2Ft%3A80%2Fannounce\
+             &x.pe=%5B%3A%3A1%5D%3A1&x.pe=host%3A2&so=1,3-5",
+            HASH
+        )
+    );
+    assert_eq!(link.parse(), Ok(m));
+}
+
+#[test]
+fn test_from_metainfo() {
+    let info = b"d6:lengthi1e4:name4:file12:piece lengthi1e6:pieces0:e";
+    let mut torrent = b"d8:announce4:udp:4:info".to_vec();
+    torrent.extend_from_slice(info);
+    torrent.push(b'e');
+    let meta = Metainfo::from_bytes(&torrent).unwrap();
+
+    let m = Magnet::from(&meta);
+    assert_eq!(m.info_hash, meta.info_hash());
+    assert_eq!(m.display_name.as_deref(), Some("file"));
+    assert_eq!(m.trackers, vec!["udp:"]);
+}
+
+#[test]
+fn test_invalid() {
+    let invalid = |s: &str| matches!(s.parse::<Magnet>(), Err(Error::InvalidMagnet(_)));
+    assert!(invalid("http://example.com"));
+    assert!(invalid("magnet:?dn=x"));
+    assert!(invalid("magnet:?xt=urn:btih:abcd"));
+    assert!(invalid(&format!("magnet:?xt=urn:btih:{}&x.pe=host", HASH)));
+    assert!(invalid(&format!("magnet:?xt=urn:btih:{}&so=5-2", HASH)));
+    assert!(invalid(&format!("magnet:?xt=urn:btih:{}&dn", HASH)));
+    assert!(matches!(
+        format!("magnet:?xt=urn:btih:{}", "g".repeat(40)).parse::<Magnet>(),
+        Err(Error::InvalidEncoding(_))
+    ));
+}
@@ -0,0 +1,152 @@
+use rdht::errors::Error;
+use rdht::metainfo::{File, Metainfo};
+use rdht::server::route_table::Key;
+use sha1::{Digest, Sha1};
+
+fn info_hash(raw: &[u8]) -> Key {
+    let digest: [u8; 20] = Sha1::digest(raw).into();
+    digest.into()
+}
+
+fn torrent(info: &[u8], extra: &[u8]) -> Vec<u8> {
+    [b"d".as_slice(), extra, b"4:info", info, b"e"].concat()
+}
+
+const PIECES: &[u8] = b"aaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbb";
+
+#[test]
+fn test_single_file() {
+    let info = [
+        b"d6:lengthi1048576e4:name8:file.iso12:piece lengthi524288e6:pieces40:".as_slice(),
+        PIECES,
+        b"e",
+    ]
+    .concat();
+    let m = Metainfo::from_bytes(&torrent(
+        &info,
+        b"8:announce31:http://tracker.example/announce7:comment4:test13:creation datei1700000000e",
+    ))
+    .unwrap();
+
+    assert_eq!(m.info_hash(), info_hash(&info));
+    assert_eq!(
+        m.announce.as_deref(),
+        Some("http://tracker.example/announce")
+    );
+    assert_eq!(m.trackers(), vec!["http://tracker.example/announce"]);
+    assert_eq!(m.comment.as_deref(), Some("test"));
+    assert_eq!(m.creation_date, Some(1700000000));
+    assert_eq!(m.info.name, "file.iso");
+    assert_eq!(m.info.piece_length, 524288);
+    assert_eq!(m.info.pieces, vec![[b'a'; 20], [b'b'; 20]]);
+    assert_eq!(m.info.length, Some(1048576));
+    assert!(!m.info.is_multi_file());
+    assert_eq!(m.info.total_length(), 1048576);
+    assert!(!m.info.private);
+}
+
+#[test]
+fn test_multi_file() {
+    let info = [
+        b"d5:filesld6:lengthi10e4:pathl1:a5:b.txteed6:lengthi20e4:pathl5:c.txteee4:name3:dir12:piece lengthi16384e6:pieces20:".as_slice(),
+        &PIECES[..20],
+        b"7:privatei1ee",
+    ]
+    .concat();
+    let extra = b"13:announce-listll8:udp://t18:udp://t2el8:udp://t3ee5:nodesll9:127.0.0.1i6881eel6:routeri6882eee";
+    let m = Metainfo::from_bytes(&torrent(&info, extra)).unwrap();
+
+    assert_eq!(m.info_hash(), info_hash(&info));
+    assert_eq!(
+        m.announce_list,
+        vec![vec!["udp://t1", "udp://t2"], vec!["udp://t3"]]
+    );
+    assert_eq!(m.trackers(), vec!["udp://t1", "udp://t2", "udp://t3"]);
+    assert_eq!(
+        m.nodes,
+        vec![
+            ("127.0.0.1".to_string(), 6881),
+            ("router".to_string(), 6882)
+        ]
+    );
+    assert!(m.info.is_multi_file());
+    assert_eq!(
+        m.info.files,
+        vec![
+            File {
+                path: vec!["a".into(), "b.txt".into()],
+                length: 10
+            },
+            File {
+                path: vec!["c.txt".into()],
+                length: 20
+            },
+        ]
+    );
+    assert_eq!(m.info.total_length(), 30);
+    assert!(m.info.private);
+}
+
+#[test]
+fn test_info_hash_uses_raw_bytes() {
+    // Unsorted keys and a padded integer would both change on re-encoding.
+    let info = [
+        b"d4:name1:x6:lengthi007e12:piece lengthi1e6:pieces20:".as_slice(),
+        &PIECES[..20],
+        b"5:extrad1:bi1e1:ai2eee",
+    ]
+    .concat();
+    let m = Metainfo::from_bytes(&torrent(&info, b"")).unwrap();
+    assert_eq!(m.info_hash(), info_hash(&info));
+    assert_eq!(m.info.length, Some(7));
+
+    let from_info = Metainfo::from_info_bytes(&info).unwrap();
+    assert_eq!(from_info.info_hash(), m.info_hash());
+    assert_eq!(from_info.info, m.info);
+}
+
+#[test]
+fn test_invalid_metainfo() {
+    let invalid = |msg: &str| Err(Error::InvalidMetainfo(msg.into()));
+    assert_eq!(
+        Metainfo::from_bytes(b"d8:announce3:urle"),
+        invalid("missing info dict")
+    );
+    assert_eq!(
+        Metainfo::from_bytes(&torrent(
+            b"d4:name1:x12:piece lengthi1e6:pieces3:abc6:lengthi1ee",
+            b""
+        )),
+        invalid("pieces is not a multiple of 20 bytes")
+    );
+    assert_eq!(
+        Metainfo::from_bytes(&torrent(b"d4:name1:x12:piece lengthi1e6:pieces0:e", b"")),
+        invalid("exactly one of length and files is required")
+    );
+    assert_eq!(
+        Metainfo::from_bytes(&torrent(
+            b"d4:name1:x12:piece lengthi-1e6:pieces0:6:lengthi1ee",
+            b""
+        )),
+        invalid("expected a non-negative integer")
+    );
+    // Two info dicts, so the one hashed and the one parsed could differ.
+    let info = b"d4:name1:x12:piece lengthi1e6:pieces0:6:lengthi1ee";
+    let twice = [
+        b"d4:info".as_slice(),
+        info,
+        b"4:info",
+        b"d4:name1:y12:piece lengthi1e6:pieces0:6:lengthi2ee",
+        b"e",
+    ]
+    .concat();
+    assert_eq!(Metainfo::from_bytes(&twice), invalid("duplicate key"));
+    assert!(matches!(
+        Metainfo::from_bytes(b"d4:infod"),
+        Err(Error::BencodeParseError(_))
+    ));
+    assert!(matches!(
+        Metainfo::from_file("/nonexistent/file.torrent"),
+        Err(Error::Io(_))
+    ));
+}
@@ -0,0 +1,174 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rdht::errors::Error;
+use rdht::pcap::{self, Datagram};
+
+const PAYLOAD: &[u8] = b"d1:ad2:id20:aaaaaaaaaaaaaaaaaaaae1:q4:ping1:t2:aa1:y1:qe";
+
+fn udp(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let mut segment = vec![];
+    segment.extend(src.port().to_be_bytes());
+    segment.extend(dst.port().to_be_bytes());
+    segment.extend((8 + payload.len() as u16).to_be_bytes());
+    segment.extend([0, 0]);
+    segment.extend(payload);
+    segment
+}
+
+/// An Ethernet frame with a VLAN tag, carrying an IPv4 UDP packet.
+fn ethernet_v4(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let (SocketAddr::V4(s), SocketAddr::V4(d)) = (src, dst) else {
+        unreachable!()
+    };
+    let segment = udp(src, dst, payload);
+    let mut frame = vec![0; 12];
+    frame.extend([0x81, 0x00, 0, 1, 0x08, 0x00]);
+    frame.extend([0x45, 0]);
+    frame.extend((20 + segment.len() as u16).to_be_bytes());
+    frame.extend([0, 0, 0x40, 0, 64, 17, 0, 0]);
+    frame.extend(s.ip().octets());
+    frame.extend(d.ip().octets());
+    frame.extend(segment);
+    // Ethernet padding after the IP packet.
+    frame.extend([0; 4]);
+    frame
+}
+
+/// A raw IPv6 UDP packet.
+fn raw_v6(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let (SocketAddr::V6(s), SocketAddr::V6(d)) = (src, dst) else {
+        unreachable!()
+    };
+    let segment = udp(src, dst, payload);
+    let mut packet = vec![0x60, 0, 0, 0];
+    packet.extend((segment.len() as u16).to_be_bytes());
+    packet.extend([17, 64]);
+    packet.extend(s.ip().octets());
+    packet.extend(d.ip().octets());
+    packet.extend(segment);
+    packet
+}
+
+fn pcap(big_endian: bool, nanos: bool, link: u32, frames: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+    let u32 = |n: u32| {
+        if big_endian {
+            n.to_be_bytes()
+        } else {
+            n.to_le_bytes()
+        }
+    };
+    let mut buf = vec![];
+    buf.extend(u32(if nanos { 0xa1b23c4d } else { 0xa1b2c3d4 }));
+    buf.extend(if big_endian {
+        [0, 2, 0, 4]
+    } else {
+        [2, 0, 4, 0]
+    });
+    buf.extend(u32(0));
+    buf.extend(u32(0));
+    buf.extend(u32(65_535));
+    buf.extend(u32(link));
+    for (secs, frac, frame) in frames {
+        buf.extend(u32(*secs));
+        buf.extend(u32(*frac));
+        buf.extend(u32(frame.len() as u32));
+        buf.extend(u32(frame.len() as u32));
+        buf.extend(frame);
+    }
+    buf
+}
+
+fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+    let mut body = body.to_vec();
+    body.resize(body.len().next_multiple_of(4), 0);
+    let len = (body.len() as u32 + 12).to_le_bytes();
+    let mut buf = kind.to_le_bytes().to_vec();
+    buf.extend(len);
+    buf.extend(body);
+    buf.extend(len);
+    buf
+}
+
+#[test]
+fn test_pcap() {
+    let src: SocketAddr = "1.2.3.4:6881".parse().unwrap();
+    let dst: SocketAddr = "5.6.7.8:51413".parse().unwrap();
+    let frame = ethernet_v4(src, dst, PAYLOAD);
+    let expected = |time| Datagram {
+        time,
+        src,
+        dst,
+        payload: PAYLOAD.to_vec(),
+    };
+
+    let buf = pcap(false, false, 1, &[(10, 250_000, frame.clone())]);
+    assert_eq!(
+        pcap::read(&buf),
+        Ok(vec![expected(Duration::from_millis(10_250))])
+    );
+
+    let frames = [(10, 7, frame.clone()), (11, 0, frame)];
+    let mut buf = pcap(true, true, 1, &frames);
+    // Cut off in the middle of the second packet.
+    buf.truncate(buf.len() - 10);
+    assert_eq!(pcap::read(&buf), Ok(vec![expected(Duration::new(10, 7))]));
+}
+
+#[test]
+fn test_pcapng() {
+    let src: SocketAddr = "[2001:db8::1]:6881".parse().unwrap();
+    let dst: SocketAddr = "[2001:db8::2]:6881".parse().unwrap();
+    let packet = raw_v6(src, dst, PAYLOAD);
+
+    let mut shb = 0x1a2b3c4d_u32.to_le_bytes().to_vec();
+    shb.extend([1, 0, 0, 0]);
+    shb.extend(u64::MAX.to_le_bytes());
+    let mut idb = vec![101, 0, 0, 0];
+    idb.extend(65_535_u32.to_le_bytes());
+    // if_tsresol: nanoseconds.
+    idb.extend([9, 0, 1, 0, 9, 0, 0, 0]);
+    idb.extend([0, 0, 0, 0]);
+    let ticks: u64 = 5_000_000_123;
+    let mut epb = 0_u32.to_le_bytes().to_vec();
+    epb.extend(((ticks >> 32) as u32).to_le_bytes());
+    epb.extend((ticks as u32).to_le_bytes());
+    epb.extend((packet.len() as u32).to_le_bytes());
+    epb.extend((packet.len() as u32).to_le_bytes());
+    epb.extend(&packet);
+    let mut spb = (packet.len() as u32).to_le_bytes().to_vec();
+    spb.extend(&packet);
+
+    let mut buf = block(0x0a0d0d0a, &shb);
+    buf.extend(block(1, &idb));
+    buf.extend(block(6, &epb));
+    buf.extend(block(3, &spb));
+    let datagram = |time| Datagram {
+        time,
+        src,
+        dst,
+        payload: PAYLOAD.to_vec(),
+    };
+    assert_eq!(
+        pcap::read(&buf),
+        Ok(vec![
+            datagram(Duration::new(5, 123)),
+            datagram(Duration::ZERO)
+        ])
+    );
+}
+
+#[test]
+fn test_invalid() {
+    assert_eq!(
+        pcap::read(b"GIF89a"),
+        Err(Error::InvalidCapture("not a pcap or pcapng file".into()))
+    );
+    assert!(pcap::read(b"").is_err());
+    // Frames of unknown link types, or without UDP, are skipped.
+    let src: SocketAddr = "1.2.3.4:1".parse().unwrap();
+    let buf = pcap(false, false, 147, &[(0, 0, ethernet_v4(src, src, b""))]);
+    assert_eq!(pcap::read(&buf), Ok(vec![]));
+    let buf = pcap(false, false, 1, &[(0, 0, vec![0; 60])]);
+    assert_eq!(pcap::read(&buf), Ok(vec![]));
+}
@@ -0,0 +1 @@
+mod ut_metadata;
@@ -0,0 +1,207 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rdht::errors::Error;
+use rdht::peer::ut_metadata::{fetch, LOCAL_ID, PIECE_SIZE};
+use rdht::server::route_table::Key;
+use rdht::util::bencode::decode_ref;
+use sha1::{Digest, Sha1};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const REMOTE_ID: u8 = 3;
+
+/// An info dict large enough to span three metadata pieces.
+fn info_dict() -> Vec<u8> {
+    let pieces = vec![b'x'; 20 * 2000];
+    let mut info = b"d6:lengthi1e4:name4:file12:piece lengthi1e6:pieces40000:".to_vec();
+    info.extend_from_slice(&pieces);
+    info.push(b'e');
+    info
+}
+
+fn hash(data: &[u8]) -> Key {
+    let digest: [u8; 20] = Sha1::digest(data).into();
+    digest.into()
+}
+
+fn read_message(s: &mut TcpStream) -> Vec<u8> {
+    let mut len = [0; 4];
+    s.read_exact(&mut len).unwrap();
+    let mut buf = vec![0; u32::from_be_bytes(len) as usize];
+    s.read_exact(&mut buf).unwrap();
+    buf
+}
+
+fn write_extended(s: &mut TcpStream, id: u8, payload: &[u8]) {
+    s.write_all(&(payload.len() as u32 + 2).to_be_bytes())
+        .unwrap();
+    s.write_all(&[20, id]).unwrap();
+    s.write_all(payload).unwrap();
+}
+
+/// Accept one connection and answer the handshake for `info_hash`, then
+/// hand the stream to `script`.
+fn fake_peer<F>(info_hash: Key, extensions: bool, script: F) -> SocketAddr
+where
+    F: FnOnce(TcpStream) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut handshake = [0; 68];
+        s.read_exact(&mut handshake).unwrap();
+        assert_eq!(&handshake[1..20], b"BitTorrent protocol");
+        assert_eq!(handshake[25] & 0x10, 0x10);
+        let mut reply = handshake;
+        reply[20..28].fill(0);
+        if extensions {
+            reply[25] = 0x10;
+        }
+        reply[28..48].copy_from_slice(info_hash.as_bytes());
+        reply[48..].copy_from_slice(b"-FAKE0-0123456789abc");
+        s.write_all(&reply).unwrap();
+        script(s);
+    });
+    addr
+}
+
+/// Serve `metadata` to the client, or refuse every request if `reject`.
+fn serve(mut s: TcpStream, metadata: Vec<u8>, reject: bool) {
+    let msg = read_message(&mut s);
+    assert_eq!(msg[..2], [20, 0]);
+    let handshake = decode_ref(&msg[2..]).unwrap();
+    assert_eq!(
+        handshake
+            .get(b"m")
+            .unwrap()
+            .get(b"ut_metadata")
+            .unwrap()
+            .as_int(),
+        Some(LOCAL_ID as i64)
+    );
+    let reply = format!(
+        "d1:md11:ut_metadatai{}ee13:metadata_sizei{}ee",
+        REMOTE_ID,
+        metadata.len()
+    );
+    // Unrelated traffic the client has to skip.
+    s.write_all(&[0, 0, 0, 0]).unwrap();
+    s.write_all(&[0, 0, 0, 2, 5, 0xff]).unwrap();
+    write_extended(&mut s, 0, reply.as_bytes());
+
+    let pieces = metadata.len().div_ceil(PIECE_SIZE);
+    let mut requests = Vec::new();
+    for _ in 0..pieces {
+        let msg = read_message(&mut s);
+        assert_eq!(msg[..2], [20, REMOTE_ID]);
+        let req = decode_ref(&msg[2..]).unwrap();
+        assert_eq!(req.get(b"msg_type").unwrap().as_int(), Some(0));
+        requests.push(req.get(b"piece").unwrap().as_int().unwrap() as usize);
+    }
+    assert_eq!(requests, (0..pieces).collect::<Vec<_>>());
+    // Answer out of order.
+    for piece in requests.into_iter().rev() {
+        let mut payload = if reject {
+            format!("d8:msg_typei2e5:piecei{}ee", piece).into_bytes()
+        } else {
+            format!(
+                "d8:msg_typei1e5:piecei{}e10:total_sizei{}ee",
+                piece,
+                metadata.len()
+            )
+            .into_bytes()
+        };
+        if !reject {
+            let start = piece * PIECE_SIZE;
+            payload.extend_from_slice(&metadata[start..metadata.len().min(start + PIECE_SIZE)]);
+        }
+        write_extended(&mut s, LOCAL_ID, &payload);
+    }
+}
+
+#[test]
+fn test_fetch() {
+    let info = info_dict();
+    let info_hash = hash(&info);
+    let metadata = info.clone();
+    let addr = fake_peer(info_hash, true, move |s| serve(s, metadata, false));
+
+    let m = fetch(addr, info_hash, [1; 20], TIMEOUT).unwrap();
+    assert_eq!(m.info_hash(), info_hash);
+    assert_eq!(m.info.name, "file");
+    assert_eq!(m.info.pieces.len(), 2000);
+}
+
+#[test]
+fn test_fetch_hash_mismatch() {
+    let mut info = info_dict();
+    let info_hash = hash(&info);
+    *info.last_mut().unwrap() = b'x';
+    let addr = fake_peer(info_hash, true, move |s| serve(s, info, false));
+
+    assert_eq!(
+        fetch(addr, info_hash, [1; 20], TIMEOUT),
+        Err(Error::PeerProtocol(
+            "metadata does not match the info hash".into()
+        ))
+    );
+}
+
+#[test]
+fn test_fetch_rejected() {
+    let info = info_dict();
+    let info_hash = hash(&info);
+    let addr = fake_peer(info_hash, true, move |s| serve(s, info, true));
+
+    assert_eq!(
+        fetch(addr, info_hash, [1; 20], TIMEOUT),
+        Err(Error::PeerProtocol("peer rejected piece 2".into()))
+    );
+}
+
+#[test]
+fn test_fetch_without_extensions() {
+    let info_hash = hash(b"");
+    let addr = fake_peer(info_hash, false, |_| {});
+
+    assert_eq!(
+        fetch(addr, info_hash, [1; 20], TIMEOUT),
+        Err(Error::PeerProtocol(
+            "peer does not support the extension protocol".into()
+        ))
+    );
+}
+
+#[test]
+fn test_fetch_wrong_info_hash() {
+    let addr = fake_peer(hash(b"other"), true, |_| {});
+
+    assert_eq!(
+        fetch(addr, hash(b""), [1; 20], TIMEOUT),
+        Err(Error::PeerProtocol(
+            "peer answered for another info hash".into()
+        ))
+    );
+}
+
+#[test]
+fn test_fetch_deadline() {
+    let info_hash = hash(b"");
+    // A peer that never answers the extension handshake but keeps the
+    // connection busy with keep-alives.
+    let addr = fake_peer(info_hash, true, |mut s| {
+        while s.write_all(&[0, 0, 0, 0]).is_ok() {
+            thread::sleep(Duration::from_millis(20));
+        }
+    });
+
+    let started = Instant::now();
+    assert_eq!(
+        fetch(addr, info_hash, [1; 20], Duration::from_millis(300)),
+        Err(Error::PeerProtocol("timed out".into()))
+    );
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
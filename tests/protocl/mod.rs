@@ -1,5 +1,6 @@
 mod dht;
 
+use rdht::errors::Error;
 use rdht::protocl::KRPC;
 use rdht::protocl::{DHTQuery, DHTResponse};
 
@@ -11,8 +12,9 @@ fn test_ping_decode() {
         Ok(KRPC::Query(
             "aa".into(),
             DHTQuery::Ping {
-                id: "abcdefghij0123456789".to_string()
-            }
+                id: "abcdefghij0123456789".into()
+            },
+            false,
         ))
     );
     let ping = KRPC::decode("d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re");
@@ -21,7 +23,7 @@ fn test_ping_decode() {
         Ok(KRPC::Response(
             "aa".into(),
             DHTResponse::ID {
-                id: "mnopqrstuvwxyz123456".to_string()
+                id: "mnopqrstuvwxyz123456".into()
             }
         ))
     );
@@ -35,9 +37,10 @@ fn test_find_node_decode() {
         Ok(KRPC::Query(
             "aa".into(),
             DHTQuery::FindNode {
-                id: "abcdefghij0123456789".to_string(),
-                target: "mnopqrstuvwxyz123456".to_string(),
-            }
+                id: "abcdefghij0123456789".into(),
+                target: "mnopqrstuvwxyz123456".into(),
+            },
+            false,
         ))
     );
     let find_node =
@@ -47,8 +50,8 @@ fn test_find_node_decode() {
         Ok(KRPC::Response(
             "aa".into(),
             DHTResponse::FindNode {
-                id: "0123456789abcdefghij".to_string(),
-                nodes: "def456...".to_string(),
+                id: "0123456789abcdefghij".into(),
+                nodes: "def456...".into(),
             }
         ))
     );
@@ -62,21 +65,38 @@ fn test_announce_peer_decode() {
         Ok(KRPC::Query(
             "aa".into(),
             DHTQuery::AnnouncePeer {
-                id: "abcdefghij0123456789".to_string(),
+                id: "abcdefghij0123456789".into(),
                 impiled_port: 1,
                 port: 6881,
-                info_hash: "mnopqrstuvwxyz123456".to_string(),
-                token: "aoeusnth".to_string()
-            }
+                info_hash: "mnopqrstuvwxyz123456".into(),
+                token: "aoeusnth".into(),
+                seed: false,
+            },
+            false,
         ))
     );
+    // implied_port is optional.
+    let packet = "d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe";
+    for decoded in [KRPC::decode(packet), KRPC::decode_bytes(packet.as_bytes())] {
+        assert!(matches!(
+            decoded,
+            Ok(KRPC::Query(
+                _,
+                DHTQuery::AnnouncePeer {
+                    impiled_port: 0,
+                    ..
+                },
+                false,
+            ))
+        ));
+    }
     let announce_peer = KRPC::decode("d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re");
     assert_eq!(
         announce_peer,
         Ok(KRPC::Response(
             "aa".into(),
             DHTResponse::ID {
-                id: "mnopqrstuvwxyz123456".to_string()
+                id: "mnopqrstuvwxyz123456".into()
             }
         ))
     );
@@ -87,6 +107,163 @@ fn test_error_decode() {
     let error = KRPC::decode("d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee");
     assert_eq!(
         error,
-        Ok(KRPC::Error(201, "A Generic Error Ocurred".to_string()))
+        Ok(KRPC::Error(
+            b"aa".to_vec(),
+            201,
+            "A Generic Error Ocurred".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_decode_bytes() {
+    for packet in [
+        "d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
+        "d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re",
+        "d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q9:find_node1:t2:aa1:y1:qe",
+        "d1:rd2:id20:0123456789abcdefghij5:nodes9:def456...e1:t2:aa1:y1:re",
+        "d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe",
+        "d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:axje.u6:idhtnmee1:t2:aa1:y1:re",
+        "d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe",
+        "d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee",
+    ] {
+        let decoded = KRPC::decode_bytes(packet.as_bytes());
+        assert!(decoded.is_ok(), "{}: {:?}", packet, decoded);
+        assert_eq!(decoded, KRPC::decode(packet), "{}", packet);
+    }
+
+    for packet in [
+        "d1:ad2:id20:abcdefghij0123456789e1:q4:pong1:t2:aa1:y1:qe",
+        "d1:ad2:id20:abcdefghij0123456789e1:q9:find_node1:t2:aa1:y1:qe",
+        "d1:eli201ee1:t2:aa1:y1:ee",
+        "d1:rde1:t2:aa1:y1:re",
+        "d1:t2:aa1:y1:xe",
+    ] {
+        assert_eq!(
+            KRPC::decode_bytes(packet.as_bytes()),
+            Err(Error::InvalidKRPC),
+            "{}",
+            packet
+        );
+    }
+}
+
+#[test]
+fn test_query_transaction() {
+    for (packet, t) in [
+        (
+            "d1:ad2:id20:abcdefghij0123456789e1:q4:pong1:t2:aa1:y1:qe",
+            Some("aa"),
+        ),
+        ("d1:q4:ping1:t2:bb1:y1:qe", Some("bb")),
+        ("d1:rde1:t2:aa1:y1:re", None),
+        ("d1:q4:ping1:y1:qe", None),
+        ("d1:q4:ping1:t2:aa", None),
+    ] {
+        assert_eq!(
+            KRPC::query_transaction(packet.as_bytes()),
+            t.map(|t| t.as_bytes().to_vec()),
+            "{}",
+            packet
+        );
+    }
+}
+
+#[test]
+fn test_encode_round_trip() {
+    let messages = [
+        "d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
+        "d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re",
+        "d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q9:find_node1:t2:aa1:y1:qe",
+        "d1:rd2:id20:0123456789abcdefghij5:nodes9:def456...e1:t2:aa1:y1:re",
+        "d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe",
+        "d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:axje.u6:idhtnmee1:t2:aa1:y1:re",
+        "d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe",
+        "d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234566:noseedi1e6:scrapei1ee1:q9:get_peers1:t2:aa1:y1:qe",
+        "d1:rd4:BFpe3:abc4:BFsd3:def2:id20:abcdefghij01234567895:token8:aoeusnthe1:t2:aa1:y1:re",
+        "d1:ad2:id20:abcdefghij012345678912:implied_porti0e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e4:seedi1e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe",
+        "d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q3:get1:t2:aa1:y1:qe",
+        "d1:ad2:id20:abcdefghij01234567895:token8:aoeusnth1:vd1:ai1eee1:q3:put1:t2:aa1:y1:qe",
+        "d1:rd2:id20:abcdefghij01234567895:nodes9:def456...5:token8:aoeusnth1:v12:Hello World!e1:t2:aa1:y1:re",
+    ];
+    let mut buf = Vec::new();
+    for packet in messages {
+        let msg = KRPC::decode(packet).unwrap();
+        buf.clear();
+        msg.encode_to(&mut buf).unwrap();
+        assert_eq!(std::str::from_utf8(&buf), Ok(packet));
+        assert_eq!(msg.encode(), Ok(packet.to_string()));
+    }
+
+    assert_eq!(
+        KRPC::Error(b"aa".to_vec(), 201, "A Generic Error Ocurred".into()).encode(),
+        Ok("d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee".to_string())
+    );
+}
+
+#[test]
+fn test_read_only_encode() {
+    let ping = |ro| KRPC::Query(b"aa".to_vec(), DHTQuery::Ping { id: vec![b'a'; 20] }, ro);
+    let mut buf = Vec::new();
+    ping(false).encode_read_only_to(&mut buf).unwrap();
+    let packet = "d1:ad2:id20:aaaaaaaaaaaaaaaaaaaae1:q4:ping2:roi1e1:t2:aa1:y1:qe";
+    assert_eq!(std::str::from_utf8(&buf), Ok(packet));
+    assert_eq!(ping(true).encode(), Ok(packet.to_string()));
+    assert_eq!(KRPC::decode_bytes(&buf), Ok(ping(true)));
+    assert_eq!(KRPC::decode(packet), Ok(ping(true)));
+}
+
+#[test]
+fn test_binary_round_trip() {
+    let msg = KRPC::Response(
+        vec![0, 0xff],
+        DHTResponse::GetPeers {
+            id: vec![0x80; 20],
+            token: vec![0xfe, 0],
+            values: vec![],
+            nodes: vec![0xc0; 26],
+            seeds: vec![0xff; 256],
+            peers: vec![0; 256],
+        },
+    );
+    let mut buf = Vec::new();
+    msg.encode_to(&mut buf).unwrap();
+    // `encode` produces a string, which cannot hold these bytes.
+    assert!(KRPC::decode_bytes(&buf).unwrap().encode().is_err());
+    assert_eq!(KRPC::decode_bytes(&buf), Ok(msg));
+}
+
+#[test]
+fn test_scrape_decode() {
+    let get_peers = KRPC::decode_bytes(b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234566:scrapei1ee1:q9:get_peers1:t2:aa1:y1:qe");
+    assert_eq!(
+        get_peers,
+        Ok(KRPC::Query(
+            "aa".into(),
+            DHTQuery::GetPeers {
+                id: "abcdefghij0123456789".into(),
+                info_hash: "mnopqrstuvwxyz123456".into(),
+                scrape: true,
+                noseed: false,
+            },
+            false,
+        ))
+    );
+    let response = KRPC::decode_bytes(
+        b"d1:rd4:BFsd3:def2:id20:abcdefghij01234567895:token8:aoeusnthe1:t2:aa1:y1:re",
+    );
+    assert_eq!(
+        response,
+        Ok(KRPC::Response(
+            "aa".into(),
+            DHTResponse::GetPeers {
+                id: "abcdefghij0123456789".into(),
+                token: "aoeusnth".into(),
+                values: vec![],
+                nodes: vec![],
+                seeds: "def".into(),
+                peers: vec![],
+            }
+        ))
     );
 }
@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+
+use rdht::errors::Error;
+use rdht::server::announces::{Announces, RETRY_INTERVAL};
+use rdht::server::route_table::Key;
+
+const INTERVAL: Duration = Duration::from_secs(600);
+
+#[test]
+fn test_schedule() {
+    let announces = Announces::new(INTERVAL);
+    let a = Key::from([1; 20]);
+    let b = Key::from([2; 20]);
+    announces.add(a, 6881);
+    announces.add(b, 6882);
+    assert_eq!(announces.len(), 2);
+    let mut due = announces.take_due();
+    due.sort();
+    assert_eq!(due, vec![(a, 6881), (b, 6882)]);
+    // Taken torrents are not due again while being announced.
+    assert_eq!(announces.take_due(), vec![]);
+
+    let before = Instant::now();
+    announces.done(&a, &Ok((8, 3)));
+    let status = announces.status(&a).unwrap();
+    assert_eq!((status.nodes, status.peers, status.error), (8, 3, None));
+    assert!(status.last_announce.unwrap() >= before);
+    assert!(status.next_announce >= before + INTERVAL);
+    assert!(status.next_announce <= Instant::now() + 2 * INTERVAL);
+
+    announces.done(&b, &Err(Error::Dht("no nodes to ask".into())));
+    let status = announces.status(&b).unwrap();
+    assert_eq!(status.error.as_deref(), Some("dht error: no nodes to ask"));
+    assert!(status.next_announce <= Instant::now() + RETRY_INTERVAL);
+    assert_eq!(announces.next_due(), Some(status.next_announce));
+
+    // Adding again makes a torrent due at once, on its new port.
+    announces.add(a, 7000);
+    assert_eq!(announces.take_due(), vec![(a, 7000)]);
+    assert_eq!(announces.status(&a).unwrap().nodes, 8);
+
+    assert!(announces.remove(&a));
+    assert!(!announces.remove(&a));
+    // A torrent removed while being announced stays removed.
+    announces.done(&a, &Ok((1, 1)));
+    assert_eq!(announces.status(&a), None);
+    assert_eq!(announces.all().len(), 1);
+}
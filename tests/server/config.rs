@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use rdht::errors::Error;
+use rdht::server::config::ServerConfig;
+
+#[test]
+fn test_from_toml() {
+    let config = ServerConfig::from_toml(
+        r#"
+        bind = "127.0.0.1:6881"
+        k = 16
+        alpha = 4
+        query_timeout = 0.5
+        peer_ttl = 60
+        read_only = true
+        bootstrap = ["localhost:6881"]
+        state = "dht.state"
+        admin = "127.0.0.1:9881"
+        packet_trace = "packets.jsonl"
+        announce_interval = 1200
+        "#,
+    )
+    .unwrap();
+    let expected = ServerConfig::default()
+        .bind("127.0.0.1:6881")
+        .k(16)
+        .alpha(4)
+        .query_timeout(Duration::from_millis(500))
+        .peer_store(Duration::from_secs(60), 100_000, 2_000)
+        .read_only(true)
+        .bootstrap(vec!["localhost:6881".into()])
+        .state("dht.state")
+        .admin("127.0.0.1:9881")
+        .packet_trace("packets.jsonl")
+        .announce_interval(Duration::from_secs(1200));
+    assert_eq!(config, expected);
+    assert_eq!(ServerConfig::from_toml(""), Ok(ServerConfig::default()));
+}
+
+#[test]
+fn test_invalid() {
+    let invalid = |toml| match ServerConfig::from_toml(toml) {
+        Err(Error::Config(msg)) => msg,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(invalid("port = 1"), "unknown key port");
+    assert_eq!(invalid("k = -1"), "k must be a non-negative integer");
+    assert_eq!(
+        invalid("bootstrap = [1]"),
+        "bootstrap must be a list of strings"
+    );
+    assert_eq!(invalid("alpha = 9"), "alpha must be between 1 and k (8)");
+    assert_eq!(
+        invalid("query_timeout = 0"),
+        "query_timeout must be positive"
+    );
+    assert_eq!(
+        invalid("bind = \"6881\""),
+        "bind address 6881 is not host:port"
+    );
+    assert!(!invalid("k = ").is_empty());
+
+    assert!(ServerConfig::default().k(0).validate().is_err());
+    assert!(ServerConfig::default()
+        .item_store(Duration::from_secs(1), 0)
+        .validate()
+        .is_err());
+}
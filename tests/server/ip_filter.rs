@@ -0,0 +1,96 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use rdht::server::ip_filter::{is_bogon, IpFilter};
+use rdht::server::route_table::{Key, Node, RouteTable};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_parse_formats() {
+    let mut filter = IpFilter::new();
+    let list = "\
+# comment
+001.002.003.000 - 001.002.003.255 , 000 , eMule range
+005.000.000.000 - 005.255.255.255 , 200 , allowed by level
+Some: Organisation:6.0.0.0-6.0.0.9
+7.7.0.0/16
+8.8.8.8
+2001:db8:1::/48
+";
+    assert_eq!(filter.parse(list), Ok(5));
+    for blocked in [
+        "1.2.3.0",
+        "1.2.3.255",
+        "6.0.0.9",
+        "7.7.200.1",
+        "8.8.8.8",
+        "2001:db8:1::1",
+    ] {
+        assert!(filter.is_blocked(ip(blocked)), "{}", blocked);
+    }
+    for allowed in [
+        "1.2.4.0",
+        "5.1.1.1",
+        "6.0.0.10",
+        "7.8.0.0",
+        "8.8.8.9",
+        "2001:db8:2::1",
+    ] {
+        assert!(!filter.is_blocked(ip(allowed)), "{}", allowed);
+    }
+    // IPv4-mapped addresses are checked as IPv4.
+    assert!(filter.is_blocked(ip("::ffff:8.8.8.8")));
+    assert!(filter.parse("not a range").is_err());
+}
+
+#[test]
+fn test_merge() {
+    let mut filter = IpFilter::new();
+    filter.block_cidr("10.0.0.0/9").unwrap();
+    filter.block_cidr("10.128.0.0/9").unwrap();
+    filter.block(ip("10.1.0.0"), ip("10.2.0.0")).unwrap();
+    assert_eq!(filter.len(), 1);
+    assert!(filter.is_blocked(ip("10.255.255.255")));
+    assert!(filter.block(ip("10.0.0.2"), ip("10.0.0.1")).is_err());
+    assert!(filter.block(ip("10.0.0.1"), ip("::1")).is_err());
+    assert!(filter.block_cidr("10.0.0.0/33").is_err());
+}
+
+#[test]
+fn test_bogons() {
+    for bogon in [
+        "10.1.1.1",
+        "127.0.0.1",
+        "192.168.0.1",
+        "100.64.0.1",
+        "0.1.2.3",
+        "::1",
+        "fd00::1",
+        "fe80::1",
+    ] {
+        assert!(is_bogon(ip(bogon)), "{}", bogon);
+    }
+    for public in ["1.1.1.1", "100.128.0.1", "2606:4700::1111"] {
+        assert!(!is_bogon(ip(public)), "{}", public);
+    }
+    let filter = IpFilter::new().block_bogons(true);
+    assert!(filter.is_blocked(ip("192.168.1.1")));
+    assert!(!IpFilter::new().is_blocked(ip("192.168.1.1")));
+}
+
+#[test]
+fn test_route_table() {
+    let mut filter = IpFilter::new();
+    filter.block_cidr("1.2.3.0/24").unwrap();
+    let mut table = RouteTable::new("0.0.0.0:6881")
+        .unwrap()
+        .with_filter(Arc::new(filter));
+    let blocked = Node::from_parts(Key::from([1; 20]), "1.2.3.4:6881".parse().unwrap());
+    let allowed = Node::from_parts(Key::from([2; 20]), "1.2.4.4:6881".parse().unwrap());
+    assert!(table.put(blocked).is_err());
+    assert!(table.put(allowed).is_ok());
+    assert_eq!(table.len(), 1);
+}
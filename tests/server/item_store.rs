@@ -0,0 +1,40 @@
+use std::thread;
+use std::time::Duration;
+
+use rdht::server::item_store::{validate_item, ItemStore};
+
+#[test]
+fn test_put_and_get() {
+    let store = ItemStore::default();
+    let target = store.put(b"12:Hello World!".to_vec()).unwrap();
+    // The example target of BEP 44.
+    assert_eq!(
+        rdht::util::hex::encode(target.as_bytes()),
+        "e5f96f6f38320f0f33959cb4d3d656452117aadb"
+    );
+    assert_eq!(store.get(&target), Some(b"12:Hello World!".to_vec()));
+    assert_eq!(store.len(), 1);
+
+    assert!(store.put(b"3:ab".to_vec()).is_err());
+    let large = format!("1001:{}", "x".repeat(1001));
+    assert!(store.put(large.into_bytes()).is_err());
+}
+
+#[test]
+fn test_validate_item() {
+    let target = validate_item(b"12:Hello World!").unwrap();
+    assert_eq!(target, ItemStore::target(b"12:Hello World!"));
+    // Not canonical bencoding.
+    assert!(validate_item(b"i01e").is_err());
+    assert!(validate_item(b"12:Hello").is_err());
+}
+
+#[test]
+fn test_expire() {
+    let store = ItemStore::new(Duration::from_millis(20));
+    let target = store.put(b"i1e".to_vec()).unwrap();
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(store.get(&target), None);
+    store.expire();
+    assert!(store.is_empty());
+}
@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use rdht::server::metrics::{Histogram, Stats};
+
+#[test]
+fn test_histogram() {
+    let mut histogram = Histogram::default();
+    histogram.observe(Duration::from_millis(50));
+    histogram.observe(Duration::from_millis(100));
+    histogram.observe(Duration::from_millis(300));
+    histogram.observe(Duration::from_secs(60));
+    assert_eq!(histogram.counts, [2, 0, 1, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(histogram.sum, Duration::from_millis(60_450));
+    assert_eq!(histogram.count, 4);
+}
+
+#[test]
+fn test_prometheus() {
+    let mut lookup = Histogram::default();
+    lookup.observe(Duration::from_millis(200));
+    lookup.observe(Duration::from_secs(3));
+    let stats = Stats {
+        queries_in: BTreeMap::from([("ping", 3), ("get_peers", 1)]),
+        errors_in: BTreeMap::from([("203", 2), ("other", 1)]),
+        nodes: BTreeMap::from([(0, 5), (1, 8)]),
+        peers: 7,
+        lookups: BTreeMap::from([("find_node", lookup)]),
+        ..Stats::default()
+    };
+    let text = stats.to_prometheus();
+    for line in [
+        "# TYPE rdht_queries_received_total counter",
+        "rdht_queries_received_total{method=\"get_peers\"} 1",
+        "rdht_queries_received_total{method=\"ping\"} 3",
+        "rdht_responses_sent_total 0",
+        "rdht_errors_received_total{code=\"203\"} 2",
+        "rdht_errors_received_total{code=\"other\"} 1",
+        "rdht_routing_table_nodes{depth=\"1\"} 8",
+        "rdht_peers 7",
+        "rdht_dropped_packets_total{reason=\"malformed\"} 0",
+        "# TYPE rdht_lookup_duration_seconds histogram",
+        "rdht_lookup_duration_seconds_bucket{method=\"find_node\",le=\"0.1\"} 0",
+        "rdht_lookup_duration_seconds_bucket{method=\"find_node\",le=\"0.25\"} 1",
+        "rdht_lookup_duration_seconds_bucket{method=\"find_node\",le=\"5\"} 2",
+        "rdht_lookup_duration_seconds_bucket{method=\"find_node\",le=\"+Inf\"} 2",
+        "rdht_lookup_duration_seconds_sum{method=\"find_node\"} 3.2",
+        "rdht_lookup_duration_seconds_count{method=\"find_node\"} 2",
+    ] {
+        assert!(text.lines().any(|l| l == line), "missing {}", line);
+    }
+}
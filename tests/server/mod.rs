@@ -1 +1,588 @@
+mod announces;
+mod config;
+mod ip_filter;
+mod item_store;
+mod metrics;
+mod packet_trace;
+mod peer_store;
+mod rate_limit;
 mod route_table;
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rdht::protocl::{DHTQuery, DHTResponse, KRPC};
+use rdht::server::config::ServerConfig;
+use rdht::server::events::{Event, CAPACITY};
+use rdht::server::rate_limit::RateLimit;
+use rdht::server::route_table::{Key, Node, NodeLimits};
+use rdht::server::Server;
+use rdht::tracker::ScrapeStats;
+use rdht::util::hex;
+
+const FROM: &str = "127.0.0.1:7000";
+
+/// Send `query` to `server` from [`FROM`] and decode the response.
+fn query(server: &Server, query: DHTQuery) -> Option<DHTResponse> {
+    let mut buf = Vec::new();
+    KRPC::Query(b"aa".to_vec(), query, false)
+        .encode_to(&mut buf)
+        .unwrap();
+    let reply = server.handle(&buf, FROM.parse().unwrap())?;
+    match KRPC::decode_bytes(&reply).unwrap() {
+        KRPC::Response(t, resp) => {
+            assert_eq!(t, b"aa");
+            Some(resp)
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_ping_and_find_node() {
+    let server = local_server();
+    let own = server.id().as_bytes().to_vec();
+    let id = vec![0xff; 20];
+    assert_eq!(
+        query(&server, DHTQuery::Ping { id: id.clone() }),
+        Some(DHTResponse::ID { id: own.clone() })
+    );
+
+    // The pinging node is now in the routing table.
+    let nodes = match query(
+        &server,
+        DHTQuery::FindNode {
+            id: vec![0xee; 20],
+            target: id.clone(),
+        },
+    ) {
+        Some(DHTResponse::FindNode { id, nodes }) => {
+            assert_eq!(id, own);
+            Node::decode_compact(&nodes).unwrap()
+        }
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(
+        nodes[0],
+        Node::from_parts(Key::from([0xff; 20]), FROM.parse().unwrap())
+    );
+
+    assert_eq!(server.handle(b"d1:y1:qe", FROM.parse().unwrap()), None);
+}
+
+#[test]
+fn test_get_peers_and_announce() {
+    let server = local_server();
+    let info_hash = vec![7; 20];
+    let get_peers = || match query(
+        &server,
+        DHTQuery::GetPeers {
+            id: vec![1; 20],
+            info_hash: info_hash.clone(),
+            scrape: false,
+            noseed: false,
+        },
+    ) {
+        Some(DHTResponse::GetPeers { token, values, .. }) => (token, values),
+        other => panic!("unexpected {:?}", other),
+    };
+    let (token, values) = get_peers();
+    assert!(values.is_empty());
+
+    let announce = |token: Vec<u8>, impiled_port| {
+        query(
+            &server,
+            DHTQuery::AnnouncePeer {
+                id: vec![1; 20],
+                impiled_port,
+                port: 6881,
+                info_hash: info_hash.clone(),
+                token,
+                seed: false,
+            },
+        )
+    };
+    assert_eq!(announce(b"forged".to_vec(), 0), None);
+    assert!(announce(token.clone(), 0).is_some());
+    assert!(announce(token, 1).is_some());
+
+    let (_, values) = get_peers();
+    let mut peers: Vec<SocketAddr> = values
+        .iter()
+        .map(|v| rdht::util::compact::decode_peer(v).unwrap())
+        .collect();
+    peers.sort();
+    assert_eq!(
+        peers,
+        vec!["127.0.0.1:6881".parse().unwrap(), FROM.parse().unwrap()]
+    );
+    assert_eq!(server.peer_store().peers(&Key::from([7; 20]), 10).len(), 2);
+}
+
+/// A server on loopback, which the default config would refuse to talk
+/// to, or to keep more than one node of in its table.
+fn local_server() -> Server {
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .node_limits(NodeLimits::UNLIMITED);
+    Server::with_config(config).unwrap()
+}
+
+/// A server on a loopback port, answering on its own thread.
+fn spawn_server() -> Arc<Server> {
+    let server = Arc::new(local_server());
+    let runner = Arc::clone(&server);
+    thread::spawn(move || runner.run());
+    server
+}
+
+#[test]
+fn test_lookup_and_scrape() {
+    let info_hash = Key::from([7; 20]);
+    let client = spawn_server();
+    assert!(client.get_peers(&info_hash).is_err());
+
+    let a = spawn_server();
+    let b = spawn_server();
+    let seed: SocketAddr = "10.0.0.1:1".parse().unwrap();
+    let leecher: SocketAddr = "10.0.0.2:1".parse().unwrap();
+    a.peer_store().announce(info_hash, seed, true);
+    a.peer_store().announce(info_hash, leecher, false);
+    b.peer_store().announce(info_hash, seed, true);
+    b.peer_store()
+        .announce(info_hash, "10.0.0.3:1".parse().unwrap(), true);
+    for server in [&a, &b] {
+        let addr = server.local_addr().unwrap();
+        assert_eq!(client.ping(addr).unwrap(), server.id());
+    }
+
+    assert_eq!(
+        client.get_peers(&info_hash).unwrap(),
+        vec![seed, leecher, "10.0.0.3:1".parse().unwrap()]
+    );
+    assert_eq!(
+        client.scrape(&info_hash).unwrap(),
+        ScrapeStats {
+            seeders: 2,
+            completed: 0,
+            leechers: 1
+        }
+    );
+}
+
+#[test]
+fn test_bootstrap_and_items() {
+    let client = spawn_server();
+    let a = spawn_server();
+    let b = spawn_server();
+    b.ping(a.local_addr().unwrap()).unwrap();
+    assert!(client.bootstrap(&[]).is_err());
+    assert_eq!(client.bootstrap(&[a.local_addr().unwrap()]), Ok(2));
+
+    let found = client.find_node(&b.id()).unwrap();
+    assert_eq!(*found[0].id(), b.id());
+
+    let info_hash = Key::from([9; 20]);
+    assert_eq!(client.announce_peer(&info_hash, 6881), Ok(2));
+    let peer: SocketAddr = "127.0.0.1:6881".parse().unwrap();
+    assert_eq!(a.peer_store().peers(&info_hash, 10), vec![peer]);
+
+    let target = client.put(b"12:Hello World!").unwrap();
+    assert_eq!(b.get(&target), Ok(Some(b"12:Hello World!".to_vec())));
+    assert_eq!(b.get(&Key::from([1; 20])), Ok(None));
+    assert!(client.put(b"not bencode").is_err());
+}
+
+#[test]
+fn test_state() {
+    let path = std::env::temp_dir().join(format!("rdht-state-{}", std::process::id()));
+    let a = spawn_server();
+    let b = spawn_server();
+    a.ping(b.local_addr().unwrap()).unwrap();
+    a.save_state(&path).unwrap();
+
+    let restored = spawn_server();
+    restored.load_state(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(restored.id(), a.id());
+    assert_eq!(restored.nodes(), a.nodes());
+    assert_eq!(restored.bootstrap(&[]), Ok(1));
+}
+
+#[test]
+fn test_read_only() {
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .read_only(true);
+    let server = Server::with_config(config).unwrap();
+    assert_eq!(query(&server, DHTQuery::Ping { id: vec![0xff; 20] }), None);
+    assert!(server.nodes().is_empty());
+}
+
+#[test]
+fn test_read_only_sender() {
+    let server = local_server();
+    let mut buf = Vec::new();
+    KRPC::Query(b"aa".to_vec(), DHTQuery::Ping { id: vec![0xff; 20] }, true)
+        .encode_to(&mut buf)
+        .unwrap();
+    let reply = server.handle(&buf, FROM.parse().unwrap()).unwrap();
+    assert!(matches!(
+        KRPC::decode_bytes(&reply),
+        Ok(KRPC::Response(_, DHTResponse::ID { .. }))
+    ));
+    assert!(server.nodes().is_empty());
+    assert!(query(&server, DHTQuery::Ping { id: vec![0xff; 20] }).is_some());
+    assert_eq!(server.nodes().len(), 1);
+}
+
+#[test]
+fn test_rate_limit() {
+    let limit = RateLimit { rate: 1, burst: 2 };
+    let global = RateLimit {
+        rate: 100,
+        burst: 100,
+    };
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .rate_limits(limit, global)
+        .ban(2, Duration::from_secs(60));
+    let server = Server::with_config(config).unwrap();
+    let ping = || query(&server, DHTQuery::Ping { id: vec![0xff; 20] });
+    assert!(ping().is_some());
+    assert!(ping().is_some());
+    assert_eq!(ping(), None);
+    assert_eq!(server.handle(b"garbage", FROM.parse().unwrap()), None);
+    assert_eq!(server.limiter().banned().len(), 1);
+    assert_eq!(server.limiter().stats().rate_limited, 1);
+    assert_eq!(server.limiter().stats().malformed, 1);
+}
+
+#[test]
+fn test_malformed_query() {
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .ban(2, Duration::from_secs(60));
+    let server = Server::with_config(config).unwrap();
+    let from = FROM.parse().unwrap();
+    // announce_peer without a port
+    let packet = b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234565:token8:aoeusnthe1:q13:announce_peer1:t2:xy1:y1:qe";
+    let reply = server.handle(packet, from).unwrap();
+    assert_eq!(
+        KRPC::decode_bytes(&reply),
+        Ok(KRPC::Error(b"xy".to_vec(), 203, "Protocol Error".into()))
+    );
+    assert_eq!(server.limiter().stats().malformed, 1);
+    assert_eq!(server.handle(packet, from), None);
+    assert_eq!(server.limiter().banned().len(), 1);
+}
+
+#[test]
+fn test_private_addresses() {
+    let server = Server::new("127.0.0.1:0", vec![]).unwrap();
+    assert_eq!(query(&server, DHTQuery::Ping { id: vec![0xff; 20] }), None);
+    assert!(server.ping("127.0.0.1:1".parse().unwrap()).is_err());
+    assert!(server.nodes().is_empty());
+}
+
+#[test]
+fn test_stats() {
+    let client = spawn_server();
+    let a = spawn_server();
+    a.peer_store()
+        .announce(Key::from([7; 20]), "10.0.0.1:1".parse().unwrap(), true);
+    client.ping(a.local_addr().unwrap()).unwrap();
+    client.find_node(&Key::from([1; 20])).unwrap();
+
+    let stats = client.stats();
+    assert_eq!(stats.queries_out.get("ping"), Some(&1));
+    assert_eq!(stats.queries_out.get("find_node"), Some(&1));
+    assert_eq!(stats.responses_in, 2);
+    assert_eq!(stats.nodes.values().sum::<usize>(), 1);
+    assert_eq!(stats.lookups["find_node"].count, 1);
+    assert!(stats.bytes_out > 0 && stats.bytes_in > 0);
+    let stats = a.stats();
+    assert_eq!(stats.queries_in.get("ping"), Some(&1));
+    assert_eq!(stats.responses_out, 2);
+    assert_eq!((stats.torrents, stats.peers), (1, 1));
+
+    assert!(client.ping("127.0.0.1:1".parse().unwrap()).is_err());
+    assert_eq!(client.stats().timeouts, 1);
+}
+
+#[test]
+fn test_errors_received() {
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .query_timeout(Duration::from_millis(500));
+    let client = Arc::new(Server::with_config(config).unwrap());
+    let runner = Arc::clone(&client);
+    thread::spawn(move || runner.run());
+    let node = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = node.local_addr().unwrap();
+    let pinger = Arc::clone(&client);
+    let ping = thread::spawn(move || pinger.ping(addr));
+
+    let mut buf = [0; 1500];
+    let (len, from) = node.recv_from(&mut buf).unwrap();
+    let KRPC::Query(t, ..) = KRPC::decode_bytes(&buf[..len]).unwrap() else {
+        panic!("expected a query");
+    };
+    for (t, code) in [(b"zz".to_vec(), 201), (t, 999)] {
+        let mut reply = Vec::new();
+        KRPC::Error(t, code, "nope".into())
+            .encode_to(&mut reply)
+            .unwrap();
+        node.send_to(&reply, from).unwrap();
+    }
+    assert!(ping.join().unwrap().is_err());
+    assert_eq!(client.stats().errors_in, BTreeMap::from([("other", 1)]));
+}
+
+#[test]
+fn test_admin() {
+    let server = spawn_server();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let admin = Arc::clone(&server);
+    thread::spawn(move || admin.serve_admin(listener));
+
+    let get = |path: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let response = get("/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+    assert!(response.contains("\nrdht_responses_sent_total 0\n"));
+    let response = get("/table");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: application/json\r\n"));
+    let id = hex::encode(server.id().as_bytes());
+    assert!(response.ends_with(&format!("{{\"id\":\"{}\",\"nodes\":0,\"buckets\":[{{\"prefix\":\"\",\"depth\":0,\"last_changed\":0,\"nodes\":[],\"replacements\":[]}}]}}", id)));
+    assert!(get("/nothing").starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[test]
+fn test_packet_trace() {
+    let path = std::env::temp_dir().join(format!("rdht-trace-{}", std::process::id()));
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .packet_trace(&path);
+    let server = Arc::new(Server::with_config(config).unwrap());
+    let runner = Arc::clone(&server);
+    thread::spawn(move || runner.run());
+    let other = spawn_server();
+    server.ping(other.local_addr().unwrap()).unwrap();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("\"dir\":\"out\""));
+    assert!(lines[0].contains("\"q\":\"ping\""));
+    assert!(lines[1].contains("\"dir\":\"in\""));
+    assert!(lines[1].contains("\"y\":\"r\""));
+}
+
+/// Answer the next query `socket` receives as a node with `id`,
+/// reporting `ip` as the querier's address if given.
+fn answer(socket: &UdpSocket, id: [u8; 20], ip: Option<[u8; 6]>) {
+    let mut buf = [0; 1500];
+    let (len, from) = socket.recv_from(&mut buf).unwrap();
+    let KRPC::Query(t, ..) = KRPC::decode_bytes(&buf[..len]).unwrap() else {
+        panic!("expected a query");
+    };
+    let mut reply = b"d".to_vec();
+    if let Some(ip) = ip {
+        reply.extend(b"2:ip6:");
+        reply.extend(ip);
+    }
+    reply.extend(b"1:rd2:id20:");
+    reply.extend(id);
+    reply.extend(format!("e1:t{}:", t.len()).as_bytes());
+    reply.extend(t);
+    reply.extend(b"1:y1:re");
+    socket.send_to(&reply, from).unwrap();
+}
+
+#[test]
+fn test_events() {
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .node_limits(NodeLimits::UNLIMITED)
+        .query_timeout(Duration::from_millis(100));
+    let client = Arc::new(Server::with_config(config).unwrap());
+    let runner = Arc::clone(&client);
+    thread::spawn(move || runner.run());
+    let events = client.subscribe();
+
+    let a = spawn_server();
+    let announced = a.subscribe();
+    let addr = a.local_addr().unwrap();
+    assert_eq!(client.bootstrap(&[addr]), Ok(1));
+    let info_hash = Key::from([9; 20]);
+    assert_eq!(client.announce_peer(&info_hash, 6881), Ok(1));
+    let events: Vec<_> = events.try_iter().collect();
+    assert_eq!(events[0], Event::NodeAdded(Node::from_parts(a.id(), addr)));
+    assert!(matches!(
+        events[1],
+        Event::LookupCompleted {
+            method: "find_node",
+            answered: 1,
+            ..
+        }
+    ));
+    assert_eq!(events[2], Event::BootstrapFinished { nodes: 1 });
+    assert!(matches!(
+        events[3],
+        Event::LookupCompleted {
+            method: "get_peers",
+            target,
+            ..
+        } if target == info_hash
+    ));
+    assert_eq!(events.len(), 4);
+    assert_eq!(
+        announced.try_iter().last(),
+        Some(Event::PeerAnnounced {
+            info_hash,
+            peer: "127.0.0.1:6881".parse().unwrap(),
+            seed: false,
+        })
+    );
+}
+
+#[test]
+fn test_events_bounded() {
+    let limit = RateLimit {
+        rate: 10_000,
+        burst: 10_000,
+    };
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .rate_limits(limit, limit);
+    let server = Server::with_config(config).unwrap();
+    let info_hash = vec![7; 20];
+    let Some(DHTResponse::GetPeers { token, .. }) = query(
+        &server,
+        DHTQuery::GetPeers {
+            id: vec![1; 20],
+            info_hash: info_hash.clone(),
+            scrape: false,
+            noseed: false,
+        },
+    ) else {
+        panic!("no token");
+    };
+    let behind = server.subscribe();
+    drop(server.subscribe());
+    for port in 1..=CAPACITY as u64 + 5 {
+        let announce = DHTQuery::AnnouncePeer {
+            id: vec![1; 20],
+            impiled_port: 0,
+            port,
+            info_hash: info_hash.clone(),
+            token: token.clone(),
+            seed: false,
+        };
+        assert!(query(&server, announce).is_some());
+    }
+    // Only the subscriber that fell behind counts; the one that hung up
+    // is forgotten.
+    assert_eq!(server.stats().events_dropped, 5);
+    assert_eq!(behind.try_iter().count(), CAPACITY);
+}
+
+#[test]
+fn test_node_removed_and_external_ip() {
+    let config = ServerConfig::default()
+        .bind("127.0.0.1:0")
+        .allow_private(true)
+        .node_limits(NodeLimits::UNLIMITED)
+        .query_timeout(Duration::from_millis(100));
+    let client = Arc::new(Server::with_config(config).unwrap());
+    let runner = Arc::clone(&client);
+    thread::spawn(move || runner.run());
+    let subscription = client.subscribe();
+
+    // Three nodes agreeing on our address, on loopback addresses of their
+    // own.
+    let ip = [1, 2, 3, 4, 0, 5];
+    let nodes: Vec<_> = (1..=3)
+        .map(|i| UdpSocket::bind(format!("127.0.0.{}:0", i)).unwrap())
+        .collect();
+    for (i, node) in nodes.iter().enumerate() {
+        thread::scope(|s| {
+            let ping = s.spawn(|| client.ping(node.local_addr().unwrap()));
+            answer(node, [i as u8; 20], Some(ip));
+            ping.join().unwrap().unwrap();
+        });
+        let expected = (i == 2).then(|| "1.2.3.4".parse().unwrap());
+        assert_eq!(client.external_ip(), expected);
+    }
+    let events: Vec<_> = subscription.try_iter().collect();
+    assert_eq!(events.len(), 4);
+    assert_eq!(
+        events[3],
+        Event::ExternalIpChanged("1.2.3.4".parse().unwrap())
+    );
+
+    // The first node stops answering.
+    let gone = nodes[0].local_addr().unwrap();
+    for _ in 0..3 {
+        assert!(client.ping(gone).is_err());
+    }
+    assert_eq!(client.nodes().len(), 2);
+    let events: Vec<_> = subscription.try_iter().collect();
+    let removed = Node::from_parts(Key::from([0; 20]), gone);
+    assert_eq!(events, vec![Event::NodeRemoved(removed)]);
+}
+
+#[test]
+fn test_reannounce() {
+    let client = spawn_server();
+    let a = spawn_server();
+    let b = spawn_server();
+    b.ping(a.local_addr().unwrap()).unwrap();
+    client.bootstrap(&[a.local_addr().unwrap()]).unwrap();
+
+    let info_hash = Key::from([9; 20]);
+    let other = Key::from([8; 20]);
+    a.peer_store()
+        .announce(info_hash, "10.0.0.1:1".parse().unwrap(), true);
+    client.add_torrent(info_hash, 6881);
+    assert_eq!(client.announce_due(), 1);
+    assert_eq!(client.announce_due(), 0);
+    let status = client.torrent_status(&info_hash).unwrap();
+    assert_eq!((status.port, status.nodes, status.peers), (6881, 2, 1));
+    assert_eq!(status.error, None);
+    let peer: SocketAddr = "127.0.0.1:6881".parse().unwrap();
+    assert!(b.peer_store().peers(&info_hash, 10).contains(&peer));
+
+    // Without nodes to ask, the announce fails and is retried.
+    let lonely = spawn_server();
+    lonely.add_torrent(other, 1);
+    assert_eq!(lonely.announce_due(), 1);
+    let status = lonely.torrent_status(&other).unwrap();
+    assert_eq!(status.error.as_deref(), Some("dht error: no nodes to ask"));
+
+    assert_eq!(client.torrents().len(), 1);
+    assert!(client.remove_torrent(&info_hash));
+    assert_eq!(client.torrents(), vec![]);
+}
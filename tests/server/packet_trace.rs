@@ -0,0 +1,42 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use rdht::server::packet_trace::{Direction, PacketTrace};
+
+/// A writer the test can read back after handing it to the trace.
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_record() {
+    let out = Shared::default();
+    let trace = PacketTrace::new(out.clone());
+    let addr = "1.2.3.4:6881".parse().unwrap();
+    trace.record(
+        Direction::In,
+        addr,
+        b"d1:ad2:id2:\x00\xffe1:q4:ping1:t2:aa1:y1:qe",
+    );
+    trace.record(Direction::Out, addr, b"d1:eli201e5:a \"b\"e1:y1:ee");
+    trace.record(Direction::In, addr, b"not bencode");
+
+    let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("{\"time\":"));
+    assert!(lines[0].ends_with(
+        ",\"dir\":\"in\",\"addr\":\"1.2.3.4:6881\",\"message\":\
+         {\"a\":{\"id\":{\"hex\":\"00ff\"}},\"q\":\"ping\",\"t\":\"aa\",\"y\":\"q\"}}"
+    ));
+    assert!(lines[1].ends_with(",\"dir\":\"out\",\"addr\":\"1.2.3.4:6881\",\"message\":{\"e\":[201,\"a \\\"b\\\"\"],\"y\":\"e\"}}"));
+}
@@ -0,0 +1,72 @@
+use std::thread;
+use std::time::Duration;
+
+use rdht::server::peer_store::PeerStore;
+use rdht::server::route_table::Key;
+use rdht::tracker::ScrapeStats;
+
+#[test]
+fn test_announce_and_stats() {
+    let store = PeerStore::default();
+    let info_hash = Key::from([1; 20]);
+    let a = "1.2.3.4:1".parse().unwrap();
+    let b = "1.2.3.4:2".parse().unwrap();
+    store.announce(info_hash, a, false);
+    store.announce(info_hash, b, false);
+    store.complete(info_hash, b);
+
+    let mut peers = store.peers(&info_hash, 10);
+    peers.sort();
+    assert_eq!(peers, vec![a, b]);
+    assert_eq!(store.peers(&info_hash, 1).len(), 1);
+    assert_eq!(
+        store.stats(&info_hash),
+        ScrapeStats {
+            seeders: 1,
+            completed: 1,
+            leechers: 1
+        }
+    );
+    assert!(store.last_announce(&info_hash, &a).is_some());
+    assert_eq!(store.leechers(&info_hash, 10), vec![a]);
+    let (seeds, peers) = store.bloom_filters(&info_hash);
+    assert!(seeds.contains(b.ip()));
+    assert_eq!(peers.estimate().round(), 1.0);
+
+    store.remove(&info_hash, &a);
+    assert_eq!(store.peers(&info_hash, 10), vec![b]);
+    assert_eq!(store.stats(&Key::from([2; 20])), ScrapeStats::default());
+}
+
+#[test]
+fn test_expire() {
+    let store = PeerStore::new(Duration::from_millis(20));
+    let info_hash = Key::from([1; 20]);
+    let addr = "1.2.3.4:1".parse().unwrap();
+    store.announce(info_hash, addr, true);
+    assert_eq!(store.torrents(), 1);
+
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(store.peers(&info_hash, 10), vec![]);
+    assert_eq!(store.last_announce(&info_hash, &addr), None);
+    store.expire();
+    assert_eq!(store.torrents(), 0);
+}
+
+#[test]
+fn test_limits() {
+    let store = PeerStore::default().with_limits(1, 2);
+    let info_hash = Key::from([1; 20]);
+    let (a, b, c) = (
+        "1.2.3.4:1".parse().unwrap(),
+        "1.2.3.4:2".parse().unwrap(),
+        "1.2.3.4:3".parse().unwrap(),
+    );
+    assert!(store.announce(info_hash, a, false));
+    assert!(store.announce(info_hash, b, false));
+    assert!(!store.announce(info_hash, c, false));
+    // Announcing again is not a new peer.
+    assert!(store.complete(info_hash, a));
+    assert!(!store.announce(Key::from([2; 20]), a, false));
+    assert_eq!(store.torrents(), 1);
+}
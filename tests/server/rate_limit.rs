@@ -0,0 +1,95 @@
+use std::net::IpAddr;
+use std::thread;
+use std::time::Duration;
+
+use rdht::server::rate_limit::{DropStats, RateLimit, RateLimiter};
+
+const LIMIT: RateLimit = RateLimit { rate: 10, burst: 2 };
+
+#[test]
+fn test_per_ip_and_global() {
+    let global = RateLimit { rate: 10, burst: 3 };
+    let limiter = RateLimiter::new(LIMIT, global, 100, Duration::from_secs(60));
+    let a: IpAddr = "1.2.3.4".parse().unwrap();
+    let b: IpAddr = "1.2.3.5".parse().unwrap();
+    assert!(limiter.allow(a));
+    assert!(limiter.allow(a));
+    assert!(!limiter.allow(a));
+    assert!(limiter.allow(b));
+    assert!(!limiter.allow(b));
+    assert_eq!(
+        limiter.stats(),
+        DropStats {
+            rate_limited: 1,
+            global_limited: 1,
+            ..DropStats::default()
+        }
+    );
+
+    // Both buckets refill at 10 a second.
+    thread::sleep(Duration::from_millis(150));
+    assert!(limiter.allow(a));
+}
+
+#[test]
+fn test_bans() {
+    let limiter = RateLimiter::new(LIMIT, LIMIT, 3, Duration::from_millis(100));
+    let ip: IpAddr = "1.2.3.4".parse().unwrap();
+    limiter.malformed(ip);
+    limiter.malformed(ip);
+    assert!(!limiter.is_banned(ip));
+    limiter.malformed(ip);
+    assert!(limiter.is_banned(ip));
+    assert_eq!(limiter.banned().len(), 1);
+    assert_eq!(limiter.stats().bans, 1);
+    assert_eq!(limiter.stats().banned, 1);
+
+    thread::sleep(Duration::from_millis(150));
+    assert!(!limiter.is_banned(ip));
+    limiter.expire();
+    assert!(limiter.is_empty());
+
+    limiter.ban(ip, Duration::from_secs(60));
+    assert!(limiter.is_banned(ip));
+    limiter.unban(ip);
+    assert!(!limiter.is_banned(ip));
+}
+
+#[test]
+fn test_sources_bounded() {
+    // Queries over the global limit are dropped before their source is
+    // tracked.
+    let global = RateLimit { rate: 0, burst: 1 };
+    let limiter = RateLimiter::new(LIMIT, global, 100, Duration::from_secs(60));
+    for i in 0..=255 {
+        limiter.allow(IpAddr::from([10, 0, 0, i]));
+    }
+    assert_eq!(limiter.len(), 1);
+    assert_eq!(limiter.stats().global_limited, 255);
+
+    // A flood from many spoofed addresses is capped, keeping the bans.
+    let global = RateLimit {
+        rate: 0,
+        burst: 1_000_000,
+    };
+    let limiter = RateLimiter::new(LIMIT, global, 2, Duration::from_secs(60)).with_max_sources(100);
+    let banned: IpAddr = "1.2.3.4".parse().unwrap();
+    limiter.malformed(banned);
+    limiter.malformed(banned);
+    for i in 0..10_000u32 {
+        let ip = IpAddr::from((0x0a00_0000 + i).to_be_bytes());
+        limiter.allow(ip);
+        limiter.malformed(ip);
+        assert!(limiter.len() <= 100);
+    }
+    assert!(limiter.is_banned(banned));
+}
+
+#[test]
+fn test_unlimited() {
+    let unlimited = RateLimit::UNLIMITED;
+    let limiter = RateLimiter::new(unlimited, unlimited, u32::MAX, Duration::from_secs(1));
+    let ip: IpAddr = "1.2.3.4".parse().unwrap();
+    assert!((0..10_000).all(|_| limiter.allow(ip)));
+    assert_eq!(limiter.stats(), DropStats::default());
+}
@@ -1,6 +1,10 @@
 use rdht::errors::Result;
-use rdht::server::route_table::{Key, Node, RouteTable};
-use sha1::{Digest, Sha1};
+use std::time::Duration;
+
+use rdht::server::route_table::{
+    Key, Node, NodeLimits, NodeStatus, RouteTable, GOOD_FOR, MAX_FAILURES,
+};
+use rdht::util::hex;
 
 #[test]
 fn test_route_table_insert() -> Result<()> {
@@ -13,3 +17,256 @@ fn test_route_table_insert() -> Result<()> {
     println!("{}", node);
     Ok(())
 }
+
+#[test]
+fn test_key_bit_and_distance() {
+    let key = Key::from([0b1010_0000; 20]);
+    assert_eq!(key.bit(0), 1);
+    assert_eq!(key.bit(1), 0);
+    assert_eq!(key.bit(2), 1);
+    assert_eq!(key.bit(8), 1);
+    assert_eq!(key.distance(&key), Key::from([0; 20]));
+    assert!(key.distance(&Key::from([0b1010_0001; 20])) < key.distance(&Key::from([0; 20])));
+}
+
+#[test]
+fn test_route_table_split_and_closest() -> Result<()> {
+    let mut table = RouteTable::new("127.0.0.1:7891")?;
+    let own = *table.id();
+    // More nodes than one bucket holds, all sharing a long prefix with us,
+    // so our bucket keeps splitting.
+    for i in 0..20u8 {
+        let mut id = *own.as_bytes();
+        id[19] ^= i + 1;
+        table.put(Node::from_parts(id.into(), "127.0.0.1:8000".parse()?))?;
+    }
+    table.put(Node::from_parts(own, "127.0.0.1:8000".parse()?))?;
+    assert_eq!(table.len(), 20);
+    // The far half of the id space gets a single bucket.
+    for i in 0..10u8 {
+        let mut id = *own.as_bytes();
+        id[0] ^= 0x80;
+        id[19] = i;
+        table.put(Node::from_parts(id.into(), "127.0.0.1:8000".parse()?))?;
+    }
+    assert_eq!(table.len(), 28);
+
+    let closest = table.closest(&own, 3);
+    let distances: Vec<_> = closest.iter().map(|n| n.id().distance(&own)).collect();
+    let mut one = [0; 20];
+    one[19] = 1;
+    assert_eq!(distances[0], Key::from(one));
+    assert!(distances.windows(2).all(|w| w[0] <= w[1]));
+    Ok(())
+}
+
+#[test]
+fn test_node_limits() -> Result<()> {
+    let mut table = RouteTable::new("0.0.0.0:6881")?.with_limits(NodeLimits {
+        per_ip: 1,
+        per_subnet: 2,
+        per_bucket_subnet: 2,
+        one_id_per_addr: true,
+    });
+    let node = |id: u8, addr: &str| Node::from_parts(Key::from([id; 20]), addr.parse().unwrap());
+    table.put(node(1, "1.2.3.4:1"))?;
+    // The same node again, and after changing port.
+    table.put(node(1, "1.2.3.4:1"))?;
+    table.put(node(1, "1.2.3.4:2"))?;
+    assert!(table.put(node(2, "1.2.3.4:3")).is_err());
+    table.put(node(3, "1.2.3.5:1"))?;
+    assert!(table.put(node(4, "1.2.3.6:1")).is_err());
+    assert_eq!(table.len(), 2);
+
+    let mut table = RouteTable::new("0.0.0.0:6881")?.with_limits(NodeLimits {
+        one_id_per_addr: true,
+        ..NodeLimits::UNLIMITED
+    });
+    table.put(node(1, "1.2.3.4:1"))?;
+    assert!(table.put(node(2, "1.2.3.4:1")).is_err());
+    table.put(node(2, "1.2.3.4:2"))?;
+    Ok(())
+}
+
+#[test]
+fn test_moved_node_limits() -> Result<()> {
+    let limits = NodeLimits {
+        per_ip: 1,
+        per_subnet: 1,
+        per_bucket_subnet: 1,
+        one_id_per_addr: false,
+    };
+    let mut table = RouteTable::new("0.0.0.0:6881")?.with_limits(limits);
+    let node = |id: u8, addr: &str| Node::from_parts(Key::from([id; 20]), addr.parse().unwrap());
+    let addr = |table: &RouteTable, id: u8| table.get(&Key::from([id; 20])).unwrap().addr();
+    table.put(node(1, "1.2.3.4:1"))?;
+    table.put(node(2, "5.6.7.8:1"))?;
+    assert!(table.put(node(2, "1.2.3.4:2")).is_err());
+    assert!(table.put(node(2, "1.2.3.5:1")).is_err());
+    assert_eq!(addr(&table, 2), "5.6.7.8:1".parse().unwrap());
+    // Moving within its own subnet does not count against it.
+    table.put(node(1, "1.2.3.5:1"))?;
+    assert!(table.put(node(2, "1.2.3.4:1")).is_err());
+    table.put(node(2, "9.9.9.9:1"))?;
+    assert_eq!(table.len(), 2);
+
+    // The limit of one bucket applies too.
+    let mut table = RouteTable::new("0.0.0.0:6881")?.with_limits(NodeLimits {
+        per_subnet: 10,
+        ..limits
+    });
+    table.put(node(1, "1.2.3.4:1"))?;
+    table.put(node(2, "5.6.7.8:1"))?;
+    assert!(table.put(node(2, "1.2.3.5:1")).is_err());
+    assert_eq!(addr(&table, 2), "5.6.7.8:1".parse().unwrap());
+    table.put(node(1, "1.2.3.5:1"))?;
+    assert_eq!(addr(&table, 1), "1.2.3.5:1".parse().unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_diverse_closest() -> Result<()> {
+    let mut table = RouteTable::with_id(Key::from([0; 20]), "0.0.0.0:6881")?;
+    for (i, addr) in ["1.0.0.1:1", "1.0.0.2:1", "1.0.0.3:1", "2.0.0.1:1"]
+        .iter()
+        .enumerate()
+    {
+        table.put(Node::from_parts(
+            Key::from([i as u8 + 1; 20]),
+            addr.parse()?,
+        ))?;
+    }
+    let table = table.with_limits(NodeLimits {
+        per_bucket_subnet: 2,
+        ..NodeLimits::UNLIMITED
+    });
+    // The two closest nodes in 1.0.0.0/24, then the one from another
+    // subnet before a closer one from the same.
+    let closest: Vec<_> = table
+        .closest(&Key::from([0; 20]), 3)
+        .iter()
+        .map(|n| n.addr().to_string())
+        .collect();
+    assert_eq!(closest, ["1.0.0.1:1", "1.0.0.2:1", "2.0.0.1:1"]);
+    // Left over places are still filled.
+    assert_eq!(table.closest(&Key::from([0; 20]), 8).len(), 4);
+    Ok(())
+}
+
+#[test]
+fn test_compact_nodes() -> Result<()> {
+    let nodes = vec![
+        Node::from_parts(Key::from([1; 20]), "1.2.3.4:6881".parse()?),
+        Node::from_parts(Key::from([2; 20]), "10.0.0.1:80".parse()?),
+    ];
+    let buf = Node::encode_compact(&nodes);
+    assert_eq!(buf.len(), 52);
+    assert_eq!(Node::decode_compact(&buf)?, nodes);
+    assert!(Node::decode_compact(&buf[1..]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_remove_and_failed() -> Result<()> {
+    let mut table = RouteTable::new("0.0.0.0:6881")?;
+    let node = Node::from_parts([1; 20].into(), "1.2.3.4:1".parse()?);
+    assert_eq!(table.put(node.clone()), Ok(true));
+    assert_eq!(table.put(node.clone()), Ok(false));
+    assert_eq!(table.failed(node.addr()), None);
+    assert_eq!(table.failed(node.addr()), None);
+    // Hearing from the node starts its count over.
+    table.put(node.clone())?;
+    for _ in 1..MAX_FAILURES {
+        assert_eq!(table.failed(node.addr()), None);
+    }
+    assert_eq!(table.failed(node.addr()), Some(node.clone()));
+    assert!(table.is_empty());
+    assert_eq!(table.failed(node.addr()), None);
+
+    table.put(node.clone())?;
+    assert_eq!(table.remove(node.id()), Some(node.clone()));
+    assert_eq!(table.remove(node.id()), None);
+    assert_eq!(table.len(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_iter_and_dump() -> Result<()> {
+    let mut table = RouteTable::with_id(Key::from([0; 20]), "0.0.0.0:6881")?;
+    let node = |first: u8, i: u8| {
+        let mut id = [first; 20];
+        id[19] = i;
+        Node::from_parts(id.into(), format!("1.2.3.{}:1", i).parse().unwrap())
+    };
+    // Ten nodes in the far half, which stops splitting at eight, and two
+    // near ones.
+    for i in 0..10 {
+        table.put(node(0x80, i))?;
+    }
+    table.put(node(0x01, 100))?;
+    table.put(node(0x01, 101))?;
+    assert_eq!(table.len(), 10);
+    assert_eq!(table.iter().count(), 10);
+    assert_eq!((&table).into_iter().count(), 10);
+
+    let dump = table.dump();
+    assert_eq!((dump.id, dump.nodes), (Key::from([0; 20]), 10));
+    let prefixes: Vec<_> = dump.buckets.iter().map(|b| b.prefix.as_str()).collect();
+    assert_eq!(prefixes, ["0", "1"]);
+    let far = &dump.buckets[1];
+    assert_eq!((far.depth, far.nodes.len()), (1, 8));
+    assert!(far.last_changed > 0);
+    assert_eq!(far.replacements, [node(0x80, 9), node(0x80, 8)]);
+    assert!(far
+        .nodes
+        .windows(2)
+        .all(|w| w[0].node.id() < w[1].node.id()));
+    assert!(far
+        .nodes
+        .iter()
+        .all(|n| n.status == NodeStatus::Questionable && n.rtt.is_none()));
+
+    // Answering makes a node good; failing twice in a row makes it bad.
+    let good = node(0x80, 0);
+    table.answered(good.addr(), Duration::from_millis(80));
+    table.answered(good.addr(), Duration::from_millis(160));
+    assert_eq!(table.status(good.id()), Some(NodeStatus::Good));
+    let bad = node(0x80, 1);
+    table.failed(bad.addr());
+    table.failed(bad.addr());
+    assert_eq!(table.status(bad.id()), Some(NodeStatus::Bad));
+    let dump = table.dump();
+    let first = &dump.buckets[1].nodes[0];
+    assert_eq!(first.rtt, Some(Duration::from_millis(90)));
+    assert!(dump.to_json().contains(&format!(
+        "{{\"id\":\"{}\",\"addr\":\"1.2.3.0:1\",\"status\":\"good\",\"rtt_ms\":90.000,\"failures\":0}}",
+        hex::encode(good.id().as_bytes())
+    )));
+    assert!(dump.to_json().contains("\"prefix\":\"1\",\"depth\":1,"));
+
+    // The latest replacement takes a dropped node's place.
+    table.failed(bad.addr());
+    assert_eq!(table.get(node(0x80, 9).id()), Some(&node(0x80, 9)));
+    assert_eq!(table.len(), 10);
+    assert_eq!(table.dump().buckets[1].replacements, [node(0x80, 8)]);
+    Ok(())
+}
+
+#[test]
+fn test_stale_buckets() -> Result<()> {
+    let mut table = RouteTable::with_id(Key::from([0; 20]), "0.0.0.0:6881")?;
+    for i in 0..10 {
+        let mut id = [0x80; 20];
+        id[19] = i;
+        table.put(Node::from_parts(
+            id.into(),
+            format!("1.2.3.{}:1", i).parse().unwrap(),
+        ))?;
+    }
+    assert_eq!(table.stale_buckets(GOOD_FOR), []);
+    // Every bucket is stale at once, with a target in its own half.
+    let targets = table.stale_buckets(Duration::ZERO);
+    assert_eq!(targets.len(), 2);
+    assert_eq!((targets[0].bit(0), targets[1].bit(0)), (0, 1));
+    Ok(())
+}
@@ -1,4 +1,10 @@
 mod util;
 mod protocl;
+mod metainfo;
+mod magnet;
+mod pcap;
+mod lsd;
+mod peer;
+mod tracker;
 
 mod server;
\ No newline at end of file
@@ -0,0 +1,162 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rdht::errors::Error;
+use rdht::server::route_table::Key;
+use rdht::server::Server;
+use rdht::tracker::http::{announce_url, parse_response};
+use rdht::tracker::{self, AnnounceRequest, AnnounceResponse, Event};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn request() -> AnnounceRequest {
+    let mut info_hash = [b'a'; 20];
+    info_hash[0] = 0xff;
+    info_hash[1] = b' ';
+    AnnounceRequest::new(Key::from(info_hash), *b"-RD0001-abcdefghijkl", 6881)
+}
+
+/// Answer a single HTTP request with `status` and `body`, returning the
+/// request line.
+fn fake_tracker(status: &'static str, body: &'static [u8]) -> (SocketAddr, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+        request_line.trim_end().to_string()
+    });
+    (addr, handle)
+}
+
+#[test]
+fn test_announce_url() {
+    let mut req = request();
+    req.event = Some(Event::Started);
+    req.num_want = Some(50);
+    req.left = 10;
+    assert_eq!(
+        announce_url("http://t/announce?key=1", &req),
+        "http://t/announce?key=1&info_hash=%FF%20aaaaaaaaaaaaaaaaaa\
+         &peer_id=-RD0001-abcdefghijkl&port=6881&uploaded=0&downloaded=0&left=10\
+         &compact=1&event=started&numwant=50"
+    );
+}
+
+#[test]
+fn test_parse_response() {
+    let body = b"d8:completei3e10:incompletei1e8:intervali1800e12:min intervali60e\
+        5:peers12:\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x1a\xe2\
+        6:peers618:\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x50e";
+    assert_eq!(
+        parse_response(body),
+        Ok(AnnounceResponse {
+            interval: 1800,
+            min_interval: Some(60),
+            seeders: Some(3),
+            leechers: Some(1),
+            peers: vec![
+                "127.0.0.1:6881".parse().unwrap(),
+                "10.0.0.2:6882".parse().unwrap(),
+                "[::1]:80".parse().unwrap(),
+            ],
+        })
+    );
+
+    let body = b"d8:intervali60e5:peersl\
+        d2:ip8:10.0.0.17:peer id20:aaaaaaaaaaaaaaaaaaaa4:porti1ee\
+        d2:ip11:example.com4:porti2eeee";
+    assert_eq!(
+        parse_response(body).unwrap().peers,
+        vec!["10.0.0.1:1".parse().unwrap()]
+    );
+
+    assert_eq!(
+        parse_response(b"d14:failure reason9:not founde"),
+        Err(Error::Tracker("not found".into()))
+    );
+    assert!(matches!(
+        parse_response(b"d5:peers0:e"),
+        Err(Error::Tracker(_))
+    ));
+    assert!(matches!(
+        parse_response(b"d8:intervali1e5:peers5:abcdee"),
+        Err(Error::InvalidNetAddr(_))
+    ));
+}
+
+#[test]
+fn test_announce() {
+    let (addr, handle) = fake_tracker(
+        "200 OK",
+        b"d8:intervali900e5:peers6:\x7f\x00\x00\x01\x1a\xe1e",
+    );
+    let url = format!("http://{}/announce", addr);
+    let resp = tracker::announce(&url, &request(), TIMEOUT).unwrap();
+    assert_eq!(resp.interval, 900);
+    assert_eq!(resp.peers, vec!["127.0.0.1:6881".parse().unwrap()]);
+    assert_eq!(
+        handle.join().unwrap(),
+        format!("GET {} HTTP/1.1", announce_url("/announce", &request()))
+    );
+}
+
+#[test]
+fn test_announce_http_error() {
+    let (addr, _) = fake_tracker("500 Internal Server Error", b"");
+    let url = format!("http://{}/announce", addr);
+    assert!(matches!(
+        tracker::announce(&url, &request(), TIMEOUT),
+        Err(Error::Tracker(_))
+    ));
+    assert!(matches!(
+        tracker::announce("wss://t/announce", &request(), TIMEOUT),
+        Err(Error::Tracker(_))
+    ));
+}
+
+#[test]
+fn test_server_tracker_peers() {
+    let (a, _) = fake_tracker(
+        "200 OK",
+        b"d8:intervali1e5:peers6:\x7f\x00\x00\x01\x00\x02e",
+    );
+    let (b, _) = fake_tracker(
+        "200 OK",
+        b"d8:intervali1e5:peers12:\x7f\x00\x00\x01\x00\x01\x7f\x00\x00\x01\x00\x02e",
+    );
+    let server = Server::new(
+        "127.0.0.1:0",
+        vec![
+            format!("http://{}/announce", a),
+            format!("http://{}/announce", b),
+            "wss://unsupported/announce".into(),
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        server.tracker_peers(&request(), TIMEOUT),
+        vec![
+            "127.0.0.1:1".parse::<SocketAddr>().unwrap(),
+            "127.0.0.1:2".parse().unwrap(),
+        ]
+    );
+}
@@ -0,0 +1,3 @@
+mod http;
+mod server;
+mod udp;
@@ -0,0 +1,149 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rdht::server::peer_store::PeerStore;
+use rdht::server::route_table::Key;
+use rdht::tracker::server::{TrackerConfig, TrackerServer};
+use rdht::tracker::udp::UdpTracker;
+use rdht::tracker::{http, AnnounceRequest, Event, ScrapeStats};
+use rdht::util::url;
+
+fn tracker() -> TrackerServer {
+    TrackerServer::new(Arc::new(PeerStore::default()), TrackerConfig::default())
+}
+
+fn request(port: u16, event: Option<Event>) -> AnnounceRequest {
+    let mut req = AnnounceRequest::new(Key::from([1; 20]), [2; 20], port);
+    req.event = event;
+    req.left = 100;
+    req
+}
+
+#[test]
+fn test_announce_interval() {
+    let tracker = tracker();
+    let from: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+    let first = tracker
+        .announce(&request(1, Some(Event::Started)), from)
+        .unwrap();
+    assert_eq!(first.peers, vec![]);
+    assert_eq!(first.interval, 1800);
+    assert_eq!(first.min_interval, Some(300));
+
+    // Too soon for a regular announce, but events are always accepted.
+    assert!(tracker.announce(&request(1, None), from).is_err());
+    let other: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+    let resp = tracker.announce(&request(2, None), other).unwrap();
+    assert_eq!(resp.peers, vec!["10.0.0.1:1".parse().unwrap()]);
+    assert_eq!(resp.leechers, Some(2));
+
+    tracker
+        .announce(&request(1, Some(Event::Completed)), from)
+        .unwrap();
+    tracker
+        .announce(&request(2, Some(Event::Stopped)), other)
+        .unwrap();
+    assert_eq!(
+        tracker.scrape(&[Key::from([1; 20])]),
+        vec![ScrapeStats {
+            seeders: 1,
+            completed: 1,
+            leechers: 0
+        }]
+    );
+}
+
+#[test]
+fn test_http() {
+    let tracker = tracker();
+    let from: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+    let req = request(6881, Some(Event::Started));
+    let target = http::announce_url("/announce", &req);
+    let (status, body) = tracker.http_response(&target, from);
+    assert_eq!(status, "200 OK");
+    let resp = http::parse_response(&body).unwrap();
+    assert_eq!(resp.leechers, Some(1));
+    assert_eq!(resp.peers, vec![]);
+
+    let (_, body) = tracker.http_response("/announce?port=1", from);
+    assert!(http::parse_response(&body).is_err());
+    let without_left = target.replace("&left=100", "");
+    let (_, body) = tracker.http_response(&without_left, from);
+    assert_eq!(
+        String::from_utf8_lossy(&body),
+        "d14:failure reason12:missing lefte"
+    );
+
+    let target = format!("/scrape?info_hash={}", url::encode(&[1; 20]));
+    let (status, body) = tracker.http_response(&target, from);
+    assert_eq!(status, "200 OK");
+    let body = String::from_utf8_lossy(&body);
+    assert!(
+        body.contains("completei0e10:downloadedi0e10:incompletei1e"),
+        "{}",
+        body
+    );
+
+    assert_eq!(tracker.http_response("/", from).0, "404 Not Found");
+}
+
+#[test]
+fn test_udp() {
+    let store = Arc::new(PeerStore::default());
+    store.announce(Key::from([1; 20]), "1.2.3.4:5".parse().unwrap(), true);
+    let tracker = TrackerServer::new(store, TrackerConfig::default());
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    thread::spawn(move || tracker.serve_udp(socket));
+
+    let mut client = UdpTracker::with_addr(addr)
+        .unwrap()
+        .with_backoff(Duration::from_millis(200), 3);
+    let resp = client
+        .announce(&request(6881, Some(Event::Started)))
+        .unwrap();
+    assert_eq!(resp.peers, vec!["1.2.3.4:5".parse().unwrap()]);
+    assert_eq!(resp.seeders, Some(1));
+    assert_eq!(resp.leechers, Some(1));
+    assert_eq!(
+        client
+            .scrape(&[Key::from([1; 20]), Key::from([2; 20])])
+            .unwrap(),
+        vec![
+            ScrapeStats {
+                seeders: 1,
+                completed: 0,
+                leechers: 1
+            },
+            ScrapeStats::default(),
+        ]
+    );
+}
+
+#[test]
+fn test_http_connection_limit() {
+    let config = TrackerConfig {
+        max_connections: 1,
+        ..TrackerConfig::default()
+    };
+    let tracker = Arc::new(TrackerServer::new(Arc::new(PeerStore::default()), config));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || tracker.serve_http(listener));
+
+    let get = |stream: &mut TcpStream| {
+        // The refused connection may already be closed.
+        let _ = write!(stream, "GET / HTTP/1.1\r\n\r\n");
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response
+    };
+    // The first connection takes the only place while it sends nothing.
+    let mut first = TcpStream::connect(addr).unwrap();
+    let mut second = TcpStream::connect(addr).unwrap();
+    assert_eq!(get(&mut second), "");
+    assert!(get(&mut first).starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
@@ -0,0 +1,181 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rdht::errors::Error;
+use rdht::server::route_table::Key;
+use rdht::tracker::udp::UdpTracker;
+use rdht::tracker::{self, AnnounceRequest, Event, ScrapeStats};
+use rdht::util::compact;
+
+const CONNECTION_ID: u64 = 0x1122_3344_5566_7788;
+
+/// A BEP 15 tracker answering on `bind` that ignores the first `drop`
+/// packets and reports the peers in `peers`. Returns its address and a
+/// counter of connect requests.
+fn fake_tracker(bind: &str, drop: usize, peers: Vec<SocketAddr>) -> (SocketAddr, Arc<AtomicUsize>) {
+    let socket = UdpSocket::bind(bind).unwrap();
+    let addr = socket.local_addr().unwrap();
+    let connects = Arc::new(AtomicUsize::new(0));
+    let counter = connects.clone();
+    thread::spawn(move || {
+        let mut buf = [0; 1500];
+        let mut dropped = 0;
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).unwrap();
+            if dropped < drop {
+                dropped += 1;
+                continue;
+            }
+            let packet = &buf[..len];
+            let connection_id = u64::from_be_bytes(packet[..8].try_into().unwrap());
+            let action = u32::from_be_bytes(packet[8..12].try_into().unwrap());
+            let mut resp = packet[8..16].to_vec();
+            match action {
+                0 => {
+                    assert_eq!(connection_id, 0x41727101980);
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    resp.extend_from_slice(&CONNECTION_ID.to_be_bytes());
+                }
+                _ if connection_id != CONNECTION_ID => {
+                    resp[..4].copy_from_slice(&3u32.to_be_bytes());
+                    resp.extend_from_slice(b"bad connection id");
+                }
+                1 => {
+                    assert_eq!(len, 98);
+                    // The event and port of the request.
+                    assert_eq!(packet[80..84], 2u32.to_be_bytes());
+                    assert_eq!(packet[96..98], 6881u16.to_be_bytes());
+                    if packet[16] == 0xee {
+                        resp[..4].copy_from_slice(&3u32.to_be_bytes());
+                        resp.extend_from_slice(b"torrent not registered");
+                    } else {
+                        for v in [1800u32, 2, 5] {
+                            resp.extend_from_slice(&v.to_be_bytes());
+                        }
+                        for peer in &peers {
+                            resp.extend(compact::encode_peer(peer));
+                        }
+                    }
+                }
+                2 => {
+                    for (i, _) in packet[16..].chunks(20).enumerate() {
+                        for v in [i as u32, 10, 20] {
+                            resp.extend_from_slice(&v.to_be_bytes());
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+            socket.send_to(&resp, from).unwrap();
+        }
+    });
+    (addr, connects)
+}
+
+fn request(first: u8) -> AnnounceRequest {
+    let mut req = AnnounceRequest::new(Key::from([first; 20]), [2; 20], 6881);
+    req.event = Some(Event::Started);
+    req
+}
+
+fn client(addr: SocketAddr) -> UdpTracker {
+    UdpTracker::with_addr(addr)
+        .unwrap()
+        .with_backoff(Duration::from_millis(100), 3)
+}
+
+#[test]
+fn test_announce_caches_connection_id() {
+    let peers = vec![
+        "1.2.3.4:5".parse().unwrap(),
+        "10.0.0.1:6881".parse().unwrap(),
+    ];
+    let (addr, connects) = fake_tracker("127.0.0.1:0", 0, peers.clone());
+    let mut tracker = client(addr);
+
+    let resp = tracker.announce(&request(1)).unwrap();
+    assert_eq!(resp.interval, 1800);
+    assert_eq!(resp.leechers, Some(2));
+    assert_eq!(resp.seeders, Some(5));
+    assert_eq!(resp.peers, peers);
+    tracker.announce(&request(1)).unwrap();
+    assert_eq!(connects.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_announce_ipv6() {
+    let peers = vec!["[2001:db8::1]:6881".parse().unwrap()];
+    let (addr, _) = fake_tracker("[::1]:0", 0, peers.clone());
+    let url = format!("udp://{}/announce", addr);
+    let resp = tracker::announce(&url, &request(1), Duration::from_secs(5)).unwrap();
+    assert_eq!(resp.peers, peers);
+}
+
+#[test]
+fn test_retransmission() {
+    // Lose the first connect request.
+    let (addr, connects) = fake_tracker("127.0.0.1:0", 1, vec![]);
+    let mut tracker = client(addr);
+    assert_eq!(tracker.announce(&request(1)).unwrap().peers, vec![]);
+    assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut tracker = UdpTracker::with_addr(silent.local_addr().unwrap())
+        .unwrap()
+        .with_backoff(Duration::from_millis(10), 2);
+    assert!(matches!(
+        tracker.announce(&request(1)),
+        Err(Error::Tracker(msg)) if msg.ends_with("did not respond")
+    ));
+
+    // Far more retries than BEP 15 allows are capped rather than
+    // overflowing the backoff.
+    let mut tracker = UdpTracker::with_addr(silent.local_addr().unwrap())
+        .unwrap()
+        .with_backoff(Duration::from_millis(1), u32::MAX);
+    assert!(tracker.announce(&request(1)).is_err());
+}
+
+#[test]
+fn test_error_response() {
+    let (addr, connects) = fake_tracker("127.0.0.1:0", 0, vec![]);
+    let mut tracker = client(addr);
+    assert_eq!(
+        tracker.announce(&request(0xee)),
+        Err(Error::Tracker("torrent not registered".into()))
+    );
+    // The error drops the cached connection id.
+    tracker.announce(&request(1)).unwrap();
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_scrape() {
+    let (addr, _) = fake_tracker("127.0.0.1:0", 0, vec![]);
+    let mut tracker = client(addr);
+    let stats = tracker
+        .scrape(&[Key::from([1; 20]), Key::from([2; 20])])
+        .unwrap();
+    assert_eq!(
+        stats,
+        vec![
+            ScrapeStats {
+                seeders: 0,
+                completed: 10,
+                leechers: 20
+            },
+            ScrapeStats {
+                seeders: 1,
+                completed: 10,
+                leechers: 20
+            },
+        ]
+    );
+    assert!(matches!(
+        tracker.scrape(&[Key::from([0; 20]); 75]),
+        Err(Error::Tracker(_))
+    ));
+}
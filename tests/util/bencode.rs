@@ -1,3 +1,12 @@
+#[cfg(feature = "serde")]
+mod de;
+mod decoder;
+mod encoder;
+mod limits;
+mod prop;
+#[cfg(feature = "serde")]
+mod ser;
+
 use rdht::errors::Error;
 use rdht::hashmap;
 use rdht::util::bencode;
@@ -97,17 +106,13 @@ fn test_decode_list() {
     let r = bencode::decode("l5:hello5:worldi1234e".chars().peekable().borrow_mut());
     assert_eq!(
         r,
-        Err(Error::BencodeParseError(format!(
-            "invalid list format of end"
-        )))
+        Err(Error::BencodeParseError("invalid list format of end".to_string()))
     );
 
     let r = bencode::decode("l5:hell5:worldi1234e".chars().peekable().borrow_mut());
     assert_eq!(
         r,
-        Err(Error::BencodeParseError(format!(
-            "invalid bencode content: :worldi1234e"
-        )))
+        Err(Error::BencodeParseError("invalid bencode content: :worldi1234e".to_string()))
     );
 }
 
@@ -134,9 +139,7 @@ fn test_decode_dict() {
     );
     assert_eq!(
         r,
-        Err(Error::BencodeParseError(format!(
-            "invalid dict format of end"
-        )))
+        Err(Error::BencodeParseError("invalid dict format of end".to_string()))
     );
 
     let r = bencode::decode(
@@ -175,3 +178,22 @@ fn test_decode_dict() {
     w.insert("t".into(), Value::String("aa".into()));
     assert_eq!(r, Ok(Value::Dict(w)));
 }
+
+#[test]
+fn test_decode_negative_int() {
+    let r = bencode::decode("i-42e".chars().peekable().borrow_mut());
+    assert_eq!(r, Ok(Value::Integer(-42)));
+
+    assert_eq!(Value::Integer(-42).encode(), Ok("i-42e".into()));
+}
+
+#[test]
+fn test_decode_dict_non_string_key() {
+    let r = bencode::decode("di1e3:onee".chars().peekable().borrow_mut());
+    assert_eq!(
+        r,
+        Err(Error::BencodeParseError(
+            "dict key must be a string".to_string()
+        ))
+    );
+}
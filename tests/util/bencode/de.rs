@@ -0,0 +1,159 @@
+use rdht::errors::Error;
+use rdht::hashmap;
+use rdht::util::bencode::{from_bytes, to_bytes, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Ping<'a> {
+    t: &'a str,
+    y: String,
+    q: String,
+    a: Args<'a>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Args<'a> {
+    #[serde(with = "serde_bytes")]
+    id: &'a [u8],
+    port: Option<u16>,
+}
+
+#[test]
+fn test_deserialize_struct() {
+    let input = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe";
+    assert_eq!(
+        from_bytes(input),
+        Ok(Ping {
+            t: "aa",
+            y: "q".into(),
+            q: "ping".into(),
+            a: Args {
+                id: b"abcdefghij0123456789",
+                port: None,
+            },
+        })
+    );
+}
+
+#[test]
+fn test_deserialize_unknown_fields() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Port {
+        port: u16,
+    }
+    assert_eq!(
+        from_bytes(b"d5:extrald1:xi1eee4:porti6881ee"),
+        Ok(Port { port: 6881 })
+    );
+}
+
+#[test]
+fn test_deserialize_primitives() {
+    assert_eq!(from_bytes::<i64>(b"i-42e"), Ok(-42));
+    assert_eq!(from_bytes::<u64>(b"i42e"), Ok(42));
+    assert_eq!(from_bytes::<bool>(b"i1e"), Ok(true));
+    assert_eq!(from_bytes::<String>(b"4:spam"), Ok("spam".into()));
+    assert_eq!(
+        from_bytes::<serde_bytes::ByteBuf>(&[b'2', b':', 0xff, 0x00]),
+        Ok(serde_bytes::ByteBuf::from(vec![0xff, 0x00]))
+    );
+    assert_eq!(from_bytes::<Vec<u8>>(b"li1ei2ee"), Ok(vec![1, 2]));
+    assert_eq!(from_bytes::<(String, u8)>(b"l1:ai1ee"), Ok(("a".into(), 1)));
+}
+
+#[test]
+fn test_deserialize_errors() {
+    assert!(matches!(from_bytes::<u8>(b"i256e"), Err(Error::Serde(_))));
+    assert_eq!(
+        from_bytes::<String>(b"4:spamx"),
+        Err(Error::BencodeParseError("trailing data at byte 6".into()))
+    );
+    assert_eq!(
+        from_bytes::<String>(b"5:spam"),
+        Err(Error::BencodeParseError(
+            "string of length 5 overruns input at byte 2".into()
+        ))
+    );
+    assert_eq!(
+        from_bytes::<Vec<u8>>(b"li1e"),
+        Err(Error::BencodeParseError(
+            "unterminated list at byte 4".into()
+        ))
+    );
+    assert_eq!(
+        from_bytes::<BTreeMap<String, u8>>(b"di1ei1ee"),
+        Err(Error::BencodeParseError(
+            "dict key must be a string at byte 1".into()
+        ))
+    );
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Message {
+    Ping,
+    Error(u64, String),
+    Nodes(String),
+    Peer { port: u16, ip: String },
+}
+
+#[test]
+fn test_enum_round_trip() {
+    for m in [
+        Message::Ping,
+        Message::Error(201, "Generic".into()),
+        Message::Nodes("abc".into()),
+        Message::Peer {
+            port: 6881,
+            ip: "1.2.3.4".into(),
+        },
+    ] {
+        let bytes = to_bytes(&m).unwrap();
+        assert_eq!(from_bytes::<Message>(&bytes), Ok(m));
+    }
+}
+
+#[test]
+fn test_deserialize_value() {
+    assert_eq!(
+        from_bytes::<Value>(b"d3:keyl5:valuei1234eee"),
+        Ok(Value::Dict(hashmap![
+            "key".to_string() => Value::List(vec![Value::String("value".into()), Value::Integer(1234)])
+        ]))
+    );
+}
+
+#[test]
+fn test_deserialize_strict() {
+    use rdht::util::bencode::Deserializer;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Item {
+        seq: i64,
+        v: String,
+    }
+
+    let strict = |input: &[u8]| {
+        let mut de = Deserializer::new(input).strict();
+        let item = Item::deserialize(&mut de)?;
+        de.end().map(|_| item)
+    };
+    assert_eq!(
+        strict(b"d3:seqi-1e1:v2:hie"),
+        Ok(Item {
+            seq: -1,
+            v: "hi".into()
+        })
+    );
+    assert_eq!(
+        strict(b"d1:v2:hi3:seqi1ee"),
+        Err(Error::BencodeParseError(
+            "dict keys out of order at byte 8".into()
+        ))
+    );
+    assert_eq!(
+        strict(b"d3:seqi01e1:v2:hie"),
+        Err(Error::BencodeParseError("leading zero at byte 7".into()))
+    );
+    assert!(from_bytes::<Item>(b"d1:v2:hi3:seqi01ee").is_ok());
+}
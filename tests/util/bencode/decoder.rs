@@ -0,0 +1,159 @@
+use rdht::errors::Error;
+use rdht::hashmap;
+use rdht::util::bencode::{decode, decode_ref, decode_strict, Value, ValueRef};
+
+#[test]
+fn test_decode_ref() {
+    assert_eq!(decode_ref(b"i54e"), Ok(ValueRef::Integer(54)));
+    assert_eq!(decode_ref(b"5:hello"), Ok(ValueRef::Bytes(b"hello")));
+    assert_eq!(
+        decode_ref(&[b'2', b':', 0xff, 0x00]),
+        Ok(ValueRef::Bytes(&[0xff, 0x00]))
+    );
+    assert_eq!(
+        decode_ref(b"l5:helloi1234ee"),
+        Ok(ValueRef::List(vec![
+            ValueRef::Bytes(b"hello"),
+            ValueRef::Integer(1234)
+        ]))
+    );
+    assert_eq!(
+        decode_ref(b"d4:coin3:btc7:balancei1000ee"),
+        Ok(ValueRef::Dict(vec![
+            (&b"coin"[..], ValueRef::Bytes(b"btc")),
+            (&b"balance"[..], ValueRef::Integer(1000)),
+        ]))
+    );
+}
+
+#[test]
+fn test_decode_ref_borrows_input() {
+    let input = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe".to_vec();
+    let v = decode_ref(&input).unwrap();
+    let id = v.get(b"a").and_then(|a| a.get(b"id")).unwrap();
+    assert_eq!(
+        id.as_bytes().map(<[u8]>::as_ptr),
+        Some(input[12..].as_ptr())
+    );
+    assert_eq!(v.get(b"q").and_then(ValueRef::as_str), Some("ping"));
+    assert_eq!(v.get(b"missing"), None);
+}
+
+#[test]
+fn test_decode_ref_errors() {
+    assert_eq!(
+        decode_ref(b"5:hell"),
+        Err(Error::BencodeParseError(
+            "string of length 5 overruns input at byte 2".into()
+        ))
+    );
+    assert_eq!(
+        decode_ref(b"l5:hello"),
+        Err(Error::BencodeParseError(
+            "unterminated list at byte 8".into()
+        ))
+    );
+    assert_eq!(
+        decode_ref(b"di1ei2ee"),
+        Err(Error::BencodeParseError(
+            "dict key must be a string at byte 1".into()
+        ))
+    );
+    assert_eq!(
+        decode_ref(b"i1ei2e"),
+        Err(Error::BencodeParseError("trailing data at byte 3".into()))
+    );
+    assert_eq!(
+        decode_ref(b"x"),
+        Err(Error::BencodeParseError(
+            "invalid bencode value at byte 0".into()
+        ))
+    );
+}
+
+#[test]
+fn test_value_ref_to_value() {
+    let v = decode_ref(b"d3:keyl5:valuei1234eee").unwrap();
+    assert_eq!(
+        v.to_value(),
+        Ok(Value::Dict(hashmap![
+            "key".to_string() => Value::List(vec![Value::String("value".into()), Value::Integer(1234)])
+        ]))
+    );
+    assert_eq!(
+        decode_ref(&[b'1', b':', 0xff]).unwrap().to_value(),
+        Err(Error::InvalidValue)
+    );
+}
+
+#[test]
+fn test_duplicate_keys() {
+    let input = "d1:ai1e1:bi0e1:ai2ee";
+    let borrowed = decode_ref(input.as_bytes()).unwrap();
+    assert_eq!(borrowed.get(b"a"), Some(&ValueRef::Integer(2)));
+    let owned = decode(&mut input.chars().peekable()).unwrap();
+    assert_eq!(owned, borrowed.to_value().unwrap());
+    let Value::Dict(dict) = owned else {
+        panic!("expected a dict");
+    };
+    assert_eq!(dict.get("a"), Some(&Value::Integer(2)));
+}
+
+#[test]
+fn test_decode_ref_integers() {
+    assert_eq!(decode_ref(b"i-42e"), Ok(ValueRef::Integer(-42)));
+    assert_eq!(
+        decode_ref(b"i-9223372036854775808e"),
+        Ok(ValueRef::Integer(i64::MIN))
+    );
+    assert_eq!(
+        decode_ref(b"i9223372036854775808e"),
+        Err(Error::BencodeParseError(
+            "integer out of range at byte 0".into()
+        ))
+    );
+    assert_eq!(
+        decode_ref(b"i-e"),
+        Err(Error::BencodeParseError("expected digits at byte 2".into()))
+    );
+}
+
+#[test]
+fn test_decode_strict() {
+    // Lenient decoding accepts every one of these.
+    for input in [
+        &b"i03e"[..],
+        b"i-0e",
+        b"03:abc",
+        b"d1:bi1e1:ai2ee",
+        b"d1:ai1e1:ai2ee",
+    ] {
+        assert!(decode_ref(input).is_ok());
+    }
+
+    let cases: [(&[u8], &str); 7] = [
+        (b"i03e", "leading zero at byte 1"),
+        (b"i-03e", "leading zero at byte 2"),
+        (b"i-0e", "negative zero at byte 1"),
+        (b"l03:abce", "leading zero at byte 1"),
+        (b"d1:bi1e1:ai2ee", "dict keys out of order at byte 7"),
+        (b"d1:ai1e1:ai2ee", "duplicate dict key at byte 7"),
+        (
+            b"d1:ad2:idi1e1:xi1e1:bi1eee",
+            "dict keys out of order at byte 18",
+        ),
+    ];
+    for (input, msg) in cases {
+        assert_eq!(
+            decode_strict(input),
+            Err(Error::BencodeParseError(msg.into())),
+            "{:?}",
+            std::str::from_utf8(input)
+        );
+    }
+
+    assert_eq!(decode_strict(b"i0e"), Ok(ValueRef::Integer(0)));
+    assert_eq!(decode_strict(b"i-10e"), Ok(ValueRef::Integer(-10)));
+    assert_eq!(decode_strict(b"0:"), Ok(ValueRef::Bytes(b"")));
+    assert!(decode_strict(b"d1:ai1e2:aai2e1:bi3ee").is_ok());
+}
@@ -0,0 +1,71 @@
+use rdht::errors::Error;
+use rdht::hashmap;
+use rdht::util::bencode::{decode_ref, Encoder, Value};
+use std::io::{self, Write};
+
+/// A writer that accepts `limit` bytes and then fails.
+struct Limited {
+    buf: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for Limited {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.buf.len() + data.len() > self.limit {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "buffer full"));
+        }
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_encoder_tokens() {
+    let mut e = Encoder::new(Vec::new());
+    e.begin_dict().unwrap();
+    e.write_str("a").unwrap();
+    e.begin_list().unwrap();
+    e.write_int(42).unwrap();
+    e.write_bytes(&[0xff, 0x00]).unwrap();
+    e.write_str("").unwrap();
+    e.end().unwrap();
+    e.end().unwrap();
+    assert_eq!(e.into_inner(), b"d1:ali42e2:\xff\x000:ee".to_vec());
+}
+
+#[test]
+fn test_encode_to_reused_buffer() {
+    let v = Value::Dict(hashmap![
+        "key1".to_string() => Value::String("value".into()),
+        "key2".to_string() => Value::List(vec![Value::String("value".into()), Value::Integer(1234)])
+    ]);
+    let mut buf = Vec::with_capacity(64);
+    for _ in 0..2 {
+        buf.clear();
+        v.encode_to(&mut buf).unwrap();
+        assert_eq!(buf, b"d4:key15:value4:key2l5:valuei1234eee".to_vec());
+    }
+}
+
+#[test]
+fn test_encode_to_propagates_errors() {
+    let v = Value::List(vec![Value::String("value".into()), Value::Integer(1234)]);
+    let mut w = Limited {
+        buf: Vec::new(),
+        limit: 8,
+    };
+    assert_eq!(v.encode_to(&mut w), Err(Error::Io("buffer full".into())));
+}
+
+#[test]
+fn test_value_ref_encode_round_trip() {
+    // Keys out of order are kept as decoded so the bytes round-trip.
+    let input = b"d4:coin3:btc7:balancei1000e5:bytes2:\xff\x00e";
+    let mut buf = Vec::new();
+    decode_ref(input).unwrap().encode_to(&mut buf).unwrap();
+    assert_eq!(buf, input.to_vec());
+}
@@ -0,0 +1,121 @@
+use rdht::errors::Error;
+use rdht::protocl::KRPC;
+use rdht::util::bencode::{self, decode_ref, Decoder, Limits, Value};
+use std::borrow::BorrowMut;
+
+fn nested(depth: usize) -> String {
+    "l".repeat(depth) + &"e".repeat(depth)
+}
+
+fn limits() -> Limits {
+    Limits {
+        max_depth: 2,
+        max_str_len: 4,
+        max_elements: 3,
+        max_input: 16,
+    }
+}
+
+fn decode(input: &[u8]) -> Result<(), Error> {
+    let mut decoder = Decoder::with_limits(input, limits())?;
+    decoder.decode()?;
+    decoder.end()
+}
+
+#[test]
+fn test_decoder_limits() {
+    assert_eq!(decode(b"ll4:spamee"), Ok(()));
+    assert_eq!(decode(b"lllee"), Err(Error::BencodeTooDeep(2)));
+    assert_eq!(decode(b"5:spams"), Err(Error::BencodeStringTooLong(4)));
+    assert_eq!(decode(b"li1ei2ei3ee"), Ok(()));
+    assert_eq!(
+        decode(b"li1ei2ei3ei4ee"),
+        Err(Error::BencodeTooManyElements(3))
+    );
+    assert_eq!(decode(b"d1:ai1e1:bi2ee"), Ok(()));
+    assert_eq!(
+        decode(b"d1:ai1e1:bi2e1:ci3e1:di4ee"),
+        Err(Error::BencodeInputTooLarge(16))
+    );
+    assert_eq!(
+        decode(b"d1:ai1e1:bi2e1:ci3ee"),
+        Err(Error::BencodeInputTooLarge(16))
+    );
+}
+
+#[test]
+fn test_default_limits_bound_depth() {
+    // Deep enough to overflow the stack without a depth limit.
+    let input = nested(1_000_000);
+    assert_eq!(
+        decode_ref(input.as_bytes()),
+        Err(Error::BencodeTooDeep(Limits::default().max_depth))
+    );
+    assert_eq!(
+        bencode::decode(input.chars().peekable().borrow_mut()),
+        Err(Error::BencodeTooDeep(Limits::default().max_depth))
+    );
+    assert!(decode_ref(nested(64).as_bytes()).is_ok());
+}
+
+#[test]
+fn test_legacy_decode_limits() {
+    let decode =
+        |input: &str| bencode::decode_with_limits(input.chars().peekable().borrow_mut(), limits());
+    assert_eq!(
+        decode("l4:spame"),
+        Ok(Value::List(vec![Value::String("spam".into())]))
+    );
+    assert_eq!(decode("lllee"), Err(Error::BencodeTooDeep(2)));
+    // The claimed length is rejected before any of it is read.
+    assert_eq!(
+        decode("99999999999:spam"),
+        Err(Error::BencodeStringTooLong(4))
+    );
+    assert_eq!(
+        decode("d1:ai1e1:bi2e1:ci3e1:di4ee"),
+        Err(Error::BencodeTooManyElements(3))
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_deserializer_limits() {
+    use rdht::util::bencode::Deserializer;
+    use serde::Deserialize;
+
+    let decode = |input: &[u8]| -> Result<Vec<Vec<u8>>, Error> {
+        let mut de = Deserializer::with_limits(input, limits())?;
+        Vec::deserialize(&mut de)
+    };
+    assert_eq!(decode(b"lli1eee"), Ok(vec![vec![1]]));
+    assert_eq!(decode(b"llleee"), Err(Error::BencodeTooDeep(2)));
+    assert_eq!(decode(b"llelelee"), Ok(vec![vec![], vec![], vec![]]));
+    assert_eq!(
+        decode(b"lleleleleee"),
+        Err(Error::BencodeTooManyElements(3))
+    );
+}
+
+#[test]
+fn test_krpc_datagram_limits() {
+    let packet = format!("d1:a{}e", nested(100));
+    assert_eq!(
+        KRPC::decode_bytes(packet.as_bytes()),
+        Err(Error::BencodeTooDeep(Limits::datagram().max_depth))
+    );
+    assert_eq!(
+        KRPC::decode(&packet),
+        Err(Error::BencodeTooDeep(Limits::datagram().max_depth))
+    );
+
+    let packet = "x".repeat(70_000);
+    assert_eq!(
+        KRPC::decode_bytes(packet.as_bytes()),
+        Err(Error::BencodeInputTooLarge(Limits::datagram().max_input))
+    );
+    assert_eq!(
+        KRPC::decode(&packet),
+        Err(Error::BencodeInputTooLarge(Limits::datagram().max_input))
+    );
+}
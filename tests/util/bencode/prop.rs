@@ -0,0 +1,68 @@
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+use rdht::protocl::KRPC;
+use rdht::util::bencode::{self, decode_ref, decode_strict, Value};
+use std::borrow::BorrowMut;
+
+fn value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        any::<String>().prop_map(Value::String),
+        any::<i64>().prop_map(Value::Integer),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..8).prop_map(Value::List),
+            btree_map(any::<String>(), inner, 0..8).prop_map(Value::Dict),
+        ]
+    })
+}
+
+fn encode(v: &Value) -> Vec<u8> {
+    let mut buf = Vec::new();
+    v.encode_to(&mut buf).unwrap();
+    buf
+}
+
+proptest! {
+    #[test]
+    fn prop_decode_encode(v in value()) {
+        let buf = encode(&v);
+        let s = String::from_utf8(buf.clone()).unwrap();
+        prop_assert_eq!(bencode::decode(s.chars().peekable().borrow_mut()), Ok(v));
+    }
+
+    #[test]
+    fn prop_decode_ref_encode(v in value()) {
+        let buf = encode(&v);
+        prop_assert_eq!(decode_ref(&buf).and_then(|r| r.to_value()), Ok(v));
+    }
+
+    #[test]
+    fn prop_encoding_is_canonical(v in value()) {
+        let buf = encode(&v);
+        let strict = decode_strict(&buf);
+        prop_assert!(strict.is_ok(), "{:?}", strict);
+        let mut again = Vec::new();
+        strict.unwrap().encode_to(&mut again).unwrap();
+        prop_assert_eq!(again, buf);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn prop_serde_round_trip(v in value()) {
+        let buf = bencode::to_bytes(&v).unwrap();
+        prop_assert_eq!(&buf, &encode(&v));
+        prop_assert_eq!(bencode::from_bytes::<Value>(&buf), Ok(v));
+    }
+
+    #[test]
+    fn prop_arbitrary_input_does_not_panic(data in vec(any::<u8>(), 0..256)) {
+        let _ = decode_ref(&data);
+        let _ = decode_strict(&data);
+        let _ = KRPC::decode_bytes(&data);
+        if let Ok(s) = std::str::from_utf8(&data) {
+            let _ = bencode::decode(s.chars().peekable().borrow_mut());
+            let _ = KRPC::decode(s);
+        }
+    }
+}
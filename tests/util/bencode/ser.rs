@@ -0,0 +1,159 @@
+use rdht::errors::Error;
+use rdht::hashmap;
+use rdht::util::bencode::{to_bytes, Value};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+struct Ping<'a> {
+    t: &'a str,
+    y: &'a str,
+    q: &'a str,
+    a: Args<'a>,
+}
+
+#[derive(Serialize)]
+struct Args<'a> {
+    #[serde(with = "serde_bytes")]
+    id: &'a [u8],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+}
+
+#[test]
+fn test_serialize_struct() {
+    let ping = Ping {
+        t: "aa",
+        y: "q",
+        q: "ping",
+        a: Args {
+            id: b"abcdefghij0123456789",
+            port: None,
+        },
+    };
+    assert_eq!(
+        to_bytes(&ping),
+        Ok(b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe".to_vec())
+    );
+}
+
+#[test]
+fn test_serialize_option() {
+    #[derive(Serialize)]
+    struct Opt {
+        b: Option<u64>,
+        a: Option<u64>,
+    }
+    assert_eq!(
+        to_bytes(&Opt {
+            b: Some(1),
+            a: None
+        }),
+        Ok(b"d1:bi1ee".to_vec())
+    );
+}
+
+#[test]
+fn test_serialize_primitives() {
+    assert_eq!(to_bytes(&-42i64), Ok(b"i-42e".to_vec()));
+    assert_eq!(to_bytes(&true), Ok(b"i1e".to_vec()));
+    assert_eq!(to_bytes("spam"), Ok(b"4:spam".to_vec()));
+    assert_eq!(
+        to_bytes(&serde_bytes::Bytes::new(&[0xff, 0x00])),
+        Ok(vec![b'2', b':', 0xff, 0x00])
+    );
+    assert_eq!(to_bytes(&vec![1u8, 2]), Ok(b"li1ei2ee".to_vec()));
+    assert_eq!(to_bytes(&("a", 1u8)), Ok(b"l1:ai1ee".to_vec()));
+    assert!(matches!(to_bytes(&1.5f64), Err(Error::Serde(_))));
+}
+
+#[test]
+fn test_serialize_map_sorted() {
+    let mut map = BTreeMap::new();
+    map.insert("zz", 1u8);
+    map.insert("a", 2u8);
+    assert_eq!(to_bytes(&map), Ok(b"d1:ai2e2:zzi1ee".to_vec()));
+
+    let mut map = BTreeMap::new();
+    map.insert(1u8, 1u8);
+    assert!(matches!(to_bytes(&map), Err(Error::Serde(_))));
+}
+
+#[test]
+fn test_serialize_enum() {
+    #[derive(Serialize)]
+    enum Message {
+        Ping,
+        Error(u64, String),
+        Nodes(String),
+        Peer { port: u16, ip: String },
+    }
+    assert_eq!(to_bytes(&Message::Ping), Ok(b"4:Ping".to_vec()));
+    assert_eq!(
+        to_bytes(&Message::Error(201, "Generic".into())),
+        Ok(b"d5:Errorli201e7:Genericee".to_vec())
+    );
+    assert_eq!(
+        to_bytes(&Message::Nodes("abc".into())),
+        Ok(b"d5:Nodes3:abce".to_vec())
+    );
+    assert_eq!(
+        to_bytes(&Message::Peer {
+            port: 6881,
+            ip: "1.2.3.4".into()
+        }),
+        Ok(b"d4:Peerd2:ip7:1.2.3.44:porti6881eee".to_vec())
+    );
+}
+
+#[test]
+fn test_serialize_value() {
+    let v = Value::Dict(hashmap![
+        "key".to_string() => Value::List(vec![Value::String("value".into()), Value::Integer(1234)])
+    ]);
+    assert_eq!(to_bytes(&v), Ok(b"d3:keyl5:valuei1234eee".to_vec()));
+}
+
+#[test]
+fn test_serialize_none_outside_dict() {
+    let serde_error = |r| matches!(r, Err(Error::Serde(_)));
+    assert!(serde_error(to_bytes(&vec![Some(1u8), None])));
+    assert!(serde_error(to_bytes(&None::<u8>)));
+    assert!(serde_error(to_bytes(&())));
+
+    #[derive(Serialize)]
+    struct Nested {
+        list: Option<Vec<Option<u8>>>,
+    }
+    assert!(serde_error(to_bytes(&Nested {
+        list: Some(vec![None])
+    })));
+    assert_eq!(to_bytes(&Nested { list: None }), Ok(b"de".to_vec()));
+}
+
+#[test]
+fn test_serialize_nested_unsorted() {
+    #[derive(Serialize)]
+    struct Inner {
+        z: u8,
+        skipped: Option<u8>,
+        b: &'static str,
+    }
+    #[derive(Serialize)]
+    struct Outer {
+        y: Inner,
+        x: Vec<Inner>,
+    }
+    let inner = || Inner {
+        z: 1,
+        skipped: None,
+        b: "b",
+    };
+    assert_eq!(
+        to_bytes(&Outer {
+            y: inner(),
+            x: vec![inner()]
+        }),
+        Ok(b"d1:xld1:b1:b1:zi1eee1:yd1:b1:b1:zi1eee".to_vec())
+    );
+}
@@ -0,0 +1,45 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use rdht::util::bloom::BloomFilter;
+use rdht::util::hex;
+
+#[test]
+fn test_bep33_vector() {
+    let mut filter = BloomFilter::new();
+    for i in 0..256 {
+        filter.insert(IpAddr::from(Ipv4Addr::new(192, 0, 2, i as u8)));
+    }
+    for i in 0..1000u128 {
+        let base = u128::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0));
+        filter.insert(IpAddr::from(Ipv6Addr::from(base + i)));
+    }
+    let expected = hex::decode(concat!(
+        "f6c3f5eaa07ffd91bde89f777f26fb2bff37bdb8fb2bbaa2fd3ddde7bacfff75",
+        "ee7ccbaefe5eedb1fbfaff67f6abff5e43ddbca3fd9b9ffdf4ffd3e9dff12d1b",
+        "df59db53dbe9fa5b7ff3b8fdfcde1afb8bedd7be2f3ee71ebbbfe93bcdeefe14",
+        "8246c2bc5dbff7e7efdcf24fd8dc7adffd8fffdfddfff7a4bbeedf5cb95ce81f",
+        "c7fcff1ff4ffffdfe5f7fdcbb7fd79b3fa1fc77bfe07fff905b7b7ffc7fefeff",
+        "e0b8370bb0cd3f5b7f2bd93feb4386cfdd6f7fd5bfaf2e9ebffffeecd67adbf7",
+        "c67f17efd5d75eba6ffeba7fff47a91eb1bfbb53e8abfb5762abe8ff237279bf",
+        "efbfeef5ffc5febfdfe5adffadfee1fb737ffffbfd9f6aeffeee76b6fd8f72ef",
+    ))
+    .unwrap();
+    assert_eq!(filter.as_bytes().to_vec(), expected);
+    assert_eq!(filter.estimate().round(), 1225.0);
+}
+
+#[test]
+fn test_union() {
+    let a: IpAddr = "10.0.0.1".parse().unwrap();
+    let b: IpAddr = "10.0.0.2".parse().unwrap();
+    let mut x = BloomFilter::new();
+    assert!(x.is_empty());
+    assert_eq!(x.estimate(), 0.0);
+    x.insert(a);
+    let mut y = BloomFilter::try_from(&x.as_bytes()[..]).unwrap();
+    y.insert(b);
+    x.union(&y);
+    assert!(x.contains(a) && x.contains(b));
+    assert_eq!(x.estimate().round(), 2.0);
+    assert!(BloomFilter::try_from(&[0; 10][..]).is_err());
+}
@@ -0,0 +1,26 @@
+use rdht::errors::Error;
+use rdht::util::compact;
+use std::net::SocketAddr;
+
+#[test]
+fn test_compact_peers() {
+    let v4: SocketAddr = "1.2.3.4:258".parse().unwrap();
+    let v6: SocketAddr = "[::1]:6881".parse().unwrap();
+    assert_eq!(compact::encode_peer(&v4), [1, 2, 3, 4, 1, 2]);
+    assert_eq!(compact::decode_peer(&compact::encode_peer(&v6)), Ok(v6));
+
+    let list = [compact::encode_peer(&v4), compact::encode_peer(&v4)].concat();
+    assert_eq!(compact::decode_peers_v4(&list), Ok(vec![v4, v4]));
+    assert_eq!(
+        compact::decode_peers_v6(&compact::encode_peer(&v6)),
+        Ok(vec![v6])
+    );
+    assert!(matches!(
+        compact::decode_peers_v4(&list[..7]),
+        Err(Error::InvalidNetAddr(_))
+    ));
+    assert!(matches!(
+        compact::decode_peer(&[1]),
+        Err(Error::InvalidNetAddr(_))
+    ));
+}
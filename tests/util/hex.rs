@@ -0,0 +1,41 @@
+use rdht::errors::Error;
+use rdht::util::hex;
+
+#[test]
+fn test_hex() {
+    assert_eq!(hex::encode(b""), "");
+    assert_eq!(hex::encode(&[0x00, 0x0f, 0xab, 0xff]), "000fabff");
+    assert_eq!(hex::decode("000FabfF"), Ok(vec![0x00, 0x0f, 0xab, 0xff]));
+    assert!(matches!(hex::decode("abc"), Err(Error::InvalidEncoding(_))));
+    assert!(matches!(hex::decode("zz"), Err(Error::InvalidEncoding(_))));
+}
+
+#[test]
+fn test_base32() {
+    // RFC 4648 test vectors.
+    for (plain, encoded) in [
+        ("", ""),
+        ("f", "MY"),
+        ("fo", "MZXQ"),
+        ("foo", "MZXW6"),
+        ("foob", "MZXW6YQ"),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI"),
+    ] {
+        assert_eq!(hex::encode_base32(plain.as_bytes()), encoded);
+        assert_eq!(hex::decode_base32(encoded), Ok(plain.as_bytes().to_vec()));
+    }
+    assert_eq!(
+        hex::decode_base32("mzxw6ytboi======"),
+        Ok(b"foobar".to_vec())
+    );
+    assert!(matches!(
+        hex::decode_base32("MZ1"),
+        Err(Error::InvalidEncoding(_))
+    ));
+    // Non-zero bits after the last whole byte.
+    assert!(matches!(
+        hex::decode_base32("MZ"),
+        Err(Error::InvalidEncoding(_))
+    ));
+}
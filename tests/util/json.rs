@@ -0,0 +1,13 @@
+use rdht::util::json;
+
+#[test]
+fn test_string() {
+    assert_eq!(json::string("plain"), "\"plain\"");
+    assert_eq!(
+        json::string("a \"b\" \\ c\nd\u{1}"),
+        "\"a \\\"b\\\" \\\\ c\\nd\\u0001\""
+    );
+    let mut out = String::from("[");
+    json::write_str(&mut out, "é");
+    assert_eq!(out, "[\"é\"");
+}
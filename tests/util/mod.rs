@@ -1 +1,6 @@
 mod bencode;
+mod bloom;
+mod compact;
+mod hex;
+mod json;
+mod url;
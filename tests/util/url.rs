@@ -0,0 +1,14 @@
+use rdht::errors::Error;
+use rdht::util::url;
+
+#[test]
+fn test_url() {
+    assert_eq!(url::encode(b"a-b_c.d~e"), "a-b_c.d~e");
+    assert_eq!(
+        url::encode(b"udp://t:80/\x00\xff"),
+        "udp%3A%2F%2Ft%3A80%2F%00%FF"
+    );
+    assert_eq!(url::decode("udp%3a%2F%2Ft+x"), Ok(b"udp://t x".to_vec()));
+    assert!(matches!(url::decode("%2"), Err(Error::InvalidEncoding(_))));
+    assert!(matches!(url::decode("%zz"), Err(Error::InvalidEncoding(_))));
+}